@@ -0,0 +1,24 @@
+#!/usr/bin/env bash
+#
+# Builds, lints and runs the feature smoke tests under each meaningful feature combination
+#
+
+here="$(dirname "$0")"
+cd "$here"/..
+
+set -e
+
+combos=(
+  ""
+  "no-entrypoint"
+  "test-utils"
+  "no-entrypoint test-utils"
+  "test-bpf"
+  "no-entrypoint test-bpf"
+)
+
+for features in "${combos[@]}"; do
+  echo "--- features: ${features:-<none>}"
+  cargo clippy --all-targets --features "$features" -- -D warnings
+  cargo test --test features --features "$features"
+done
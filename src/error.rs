@@ -24,7 +24,85 @@ pub enum EscrowError {
     #[error("Account not settled or canceled")]
     AccountNotSettledOrCanceled,
     #[error("Account not initialized")]
-    AccountNotInitialized
+    AccountNotInitialized,
+    #[error("Batch too large")]
+    BatchTooLarge,
+    #[error("Mint mismatch")]
+    MintMismatch,
+    #[error("Vault is not fresh")]
+    VaultNotFresh,
+    #[error("Points not configured")]
+    PointsNotConfigured,
+    #[error("Invalid points account")]
+    InvalidPointsAccount,
+    #[error("Amount exceeds the vault balance")]
+    AmountExceedsVault,
+    #[error("Integrity check failed")]
+    IntegrityCheckFailed,
+    #[error("Payee transfer failed")]
+    PayeeTransferFailed,
+    #[error("Fee transfer failed")]
+    FeeTransferFailed,
+    #[error("Refund failed")]
+    RefundFailed,
+    #[error("Vault close failed")]
+    VaultCloseFailed,
+    #[error("Amount is zero")]
+    ZeroAmount,
+    #[error("Duplicate token account")]
+    DuplicateTokenAccount,
+    #[error("Outside the settlement window")]
+    OutsideSettlementWindow,
+    #[error("Vault mint changed")]
+    VaultMintChanged,
+    #[error("Settlement condition not met")]
+    ConditionNotMet,
+    #[error("Vault still holds funds")]
+    FundsStillLocked,
+    #[error("Escrow account is closed")]
+    EscrowClosed,
+    #[error("Invalid fee token account")]
+    InvalidFeeToken,
+    #[error("Token program mismatch")]
+    TokenProgramMismatch,
+    #[error("Escrow has no counterparty side")]
+    CounterpartyNotConfigured,
+    #[error("Counterparty side not deposited")]
+    CounterpartyNotDeposited,
+    #[error("Counterparty side already deposited")]
+    CounterpartyAlreadyDeposited,
+    #[error("Escrow has no expiry")]
+    NoExpiry,
+    #[error("Escrow has not expired")]
+    NotExpired,
+    #[error("Vault is not owned by the payer")]
+    InvalidVaultOwner,
+    #[error("Wrapped SOL escrows can't be settled partially")]
+    NativePartialSettlement,
+    #[error("Vault balance doesn't match the escrowed amount")]
+    VaultBalanceMismatch,
+    #[error("Escrow is disputed")]
+    EscrowDisputed,
+    #[error("Escrow is not disputed")]
+    NotDisputed,
+    #[error("Dispute was resolved the other way")]
+    DisputeResolvedOtherwise,
+    #[error("Wrapped SOL escrows can't be split")]
+    NativeSplitSettlement,
+    #[error("Signer is not the escrow authority")]
+    InvalidAuthority,
+    #[error("Wrapped SOL escrows can't claim the fee before settlement")]
+    NativeFeeClaim,
+    #[error("Wrapped SOL escrows can't be canceled partially")]
+    NativePartialCancel,
+    #[error("Wrapped SOL recipient is not a system account")]
+    InvalidNativeRecipient,
+    #[error("Instruction data has the wrong length")]
+    InvalidInstructionLength,
+    #[error("Only wrapped SOL vaults can be unwrapped")]
+    UnwrapNonNative,
+    #[error("Token-2022 accounts with extensions are not supported")]
+    TokenExtensionsUnsupported,
 }
 
 impl From<EscrowError> for ProgramError {
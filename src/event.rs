@@ -0,0 +1,72 @@
+//! Machine readable events logged with `sol_log_data` for indexers, next to the `msg!` lines
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{log::sol_log_data, program_error::ProgramError, pubkey::Pubkey};
+
+/// Discriminator of an escrow event, the first byte of its data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Settled = 0,
+    Canceled = 1,
+    PartiallySettled = 2,
+    PartiallyCanceled = 3,
+}
+
+/// Event logged once an escrow is settled, canceled or reclaimed, and on partial releases and
+/// refunds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EscrowEvent {
+    pub kind: EventKind,
+    pub escrow: Pubkey,
+    /// The payee token account on settlement, the payer token account on cancellation
+    pub recipient: Pubkey,
+    /// The amount the recipient received
+    pub amount: u64,
+    /// The settlement fee, or the restocking fee on cancellation
+    pub fee: u64,
+}
+
+impl EscrowEvent {
+    pub const LEN: usize = 81;
+
+    /// Serializes the event as logged
+    pub fn pack(&self) -> [u8; Self::LEN] {
+        let mut data = [0; Self::LEN];
+        let dst = array_mut_ref![data, 0, EscrowEvent::LEN];
+        let (kind_dst, escrow_dst, recipient_dst, amount_dst, fee_dst) =
+            mut_array_refs![dst, 1, 32, 32, 8, 8];
+        kind_dst[0] = self.kind as u8;
+        escrow_dst.copy_from_slice(self.escrow.as_ref());
+        recipient_dst.copy_from_slice(self.recipient.as_ref());
+        *amount_dst = self.amount.to_le_bytes();
+        *fee_dst = self.fee.to_le_bytes();
+        data
+    }
+
+    /// Decodes an event from logged data
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let src = array_ref![data, 0, EscrowEvent::LEN];
+        let (kind, escrow, recipient, amount, fee) = array_refs![src, 1, 32, 32, 8, 8];
+        let kind = match kind {
+            [0] => EventKind::Settled,
+            [1] => EventKind::Canceled,
+            [2] => EventKind::PartiallySettled,
+            [3] => EventKind::PartiallyCanceled,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(EscrowEvent {
+            kind,
+            escrow: Pubkey::new_from_array(*escrow),
+            recipient: Pubkey::new_from_array(*recipient),
+            amount: u64::from_le_bytes(*amount),
+            fee: u64::from_le_bytes(*fee),
+        })
+    }
+
+    /// Logs the event with `sol_log_data`
+    pub fn emit(&self) {
+        sol_log_data(&[&self.pack()]);
+    }
+}
@@ -1,48 +1,610 @@
 // inside instruction.rs
-use solana_program::program_error::ProgramError;
-use std::convert::TryInto;
+use arrayref::{array_ref, array_refs};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, sysvar,
+};
+use spl_token::state::Account as TokenAccount;
+use std::{convert::TryInto, fmt};
 
-use crate::error::EscrowError::InvalidInstruction;
+use crate::{
+    error::EscrowError::{
+        AmountOverflow, BatchTooLarge, InvalidInstruction, InvalidInstructionLength,
+    },
+    find_program_authority,
+    state::Escrow,
+    MAX_SPLIT,
+};
+
+/// Instruction tags, the first byte of every instruction's data
+pub const TAG_INIT: u8 = 0;
+pub const TAG_SETTLE: u8 = 1;
+pub const TAG_CANCEL: u8 = 2;
+pub const TAG_CLOSE: u8 = 3;
+pub const TAG_CLOSE_MANY_TO_TREASURY: u8 = 41;
+pub const TAG_FORCE_RECLAIM: u8 = 42;
+pub const TAG_UPDATE_FEE_TOKEN: u8 = 43;
+pub const TAG_SETTLE_WITH_MINT: u8 = 44;
+pub const TAG_SETTLE_MANY: u8 = 45;
+pub const TAG_CHECK_AUTHORIZATION: u8 = 46;
+pub const TAG_LOG_ESCROW: u8 = 48;
+pub const TAG_DEPOSIT_COUNTERPARTY: u8 = 49;
+pub const TAG_RECLAIM: u8 = 50;
+pub const TAG_SETTLE_PARTIAL: u8 = 51;
+pub const TAG_CHANGE_AUTHORITY: u8 = 52;
+pub const TAG_TOP_UP: u8 = 53;
+pub const TAG_RAISE_DISPUTE: u8 = 54;
+pub const TAG_RESOLVE_DISPUTE: u8 = 55;
+pub const TAG_SETTLE_SPLIT: u8 = 56;
+pub const TAG_CLAIM_FEE: u8 = 57;
+pub const TAG_CANCEL_PARTIAL: u8 = 58;
+pub const TAG_SETTLE_AND_CLOSE: u8 = 59;
+pub const TAG_CANCEL_UNWRAP: u8 = 60;
+
+/// One entry of an instruction's account list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AccountSpec {
+    pub index: usize,
+    pub name: &'static str,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl AccountSpec {
+    pub const fn new(index: usize, name: &'static str, is_signer: bool, is_writable: bool) -> Self {
+        AccountSpec {
+            index,
+            name,
+            is_signer,
+            is_writable,
+        }
+    }
+
+    pub fn meta(&self, pubkey: Pubkey) -> AccountMeta {
+        AccountMeta {
+            pubkey,
+            is_signer: self.is_signer,
+            is_writable: self.is_writable,
+        }
+    }
+}
+
+/// Builds the metas of an instruction's required accounts from their keys, in spec order
+pub fn account_metas(specs: &[AccountSpec], keys: &[Pubkey]) -> Vec<AccountMeta> {
+    assert_eq!(specs.len(), keys.len(), "one key per account spec");
+    specs
+        .iter()
+        .zip(keys)
+        .map(|(spec, key)| spec.meta(*key))
+        .collect()
+}
+
+/// Required accounts of `InitEscrow`
+pub const INIT_ESCROW_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "payer", true, false),
+    AccountSpec::new(1, "vault_token", false, true),
+    AccountSpec::new(2, "authority", true, false),
+    AccountSpec::new(3, "escrow", false, true),
+    AccountSpec::new(4, "payer_token", false, false),
+    AccountSpec::new(5, "payee_token", false, false),
+    AccountSpec::new(6, "fee_token", false, false),
+    AccountSpec::new(7, "rent", false, false),
+    AccountSpec::new(8, "token_program", false, false),
+];
+
+/// Required accounts of `Settle`
+pub const SETTLE_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "payee_token", false, true),
+    AccountSpec::new(2, "fee_token", false, true),
+    AccountSpec::new(3, "vault_token", false, true),
+    AccountSpec::new(4, "escrow", false, true),
+    AccountSpec::new(5, "fee_payer", false, true),
+    AccountSpec::new(6, "token_program", false, false),
+    AccountSpec::new(7, "pda", false, false),
+];
+
+/// Required accounts of `Cancel`
+pub const CANCEL_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+    AccountSpec::new(2, "payer_token", false, true),
+    AccountSpec::new(3, "fee_payer", false, true),
+    AccountSpec::new(4, "vault_token", false, true),
+    AccountSpec::new(5, "token_program", false, false),
+    AccountSpec::new(6, "pda", false, false),
+];
+
+/// Required accounts of `CancelUnwrap`
+pub const CANCEL_UNWRAP_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+    AccountSpec::new(2, "payer", false, true),
+    AccountSpec::new(3, "fee_payer", false, true),
+    AccountSpec::new(4, "vault_token", false, true),
+    AccountSpec::new(5, "token_program", false, false),
+    AccountSpec::new(6, "pda", false, false),
+];
+
+/// Required accounts of `Close`
+pub const CLOSE_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+    AccountSpec::new(2, "fee_payer", false, true),
+];
+
+/// Required accounts of `CloseManyToTreasury`, followed by the escrow accounts
+pub const CLOSE_MANY_TO_TREASURY_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "treasury", false, true),
+];
+
+/// Required accounts of `ForceReclaim`
+pub const FORCE_RECLAIM_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "admin", true, false),
+    AccountSpec::new(1, "account", false, true),
+    AccountSpec::new(2, "destination", false, true),
+];
+
+/// Required accounts of `UpdateFeeToken`
+pub const UPDATE_FEE_TOKEN_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+    AccountSpec::new(2, "fee_token", false, false),
+];
+
+/// Required accounts of `ChangeAuthority`
+pub const CHANGE_AUTHORITY_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+    AccountSpec::new(2, "new_authority", false, false),
+];
+
+/// Required accounts of `TopUp`
+pub const TOP_UP_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "payer", true, false),
+    AccountSpec::new(1, "source_token", false, true),
+    AccountSpec::new(2, "vault_token", false, true),
+    AccountSpec::new(3, "escrow", false, true),
+    AccountSpec::new(4, "token_program", false, false),
+];
+
+/// Required accounts of `RaiseDispute`
+pub const RAISE_DISPUTE_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "party", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+];
+
+/// Required accounts of `ResolveDispute`
+pub const RESOLVE_DISPUTE_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+];
+
+/// Required accounts of `SettleSplit`, followed by the condition account, if the escrow has
+/// one, and a token account per recipient
+pub const SETTLE_SPLIT_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "fee_token", false, true),
+    AccountSpec::new(2, "vault_token", false, true),
+    AccountSpec::new(3, "escrow", false, true),
+    AccountSpec::new(4, "fee_payer", false, true),
+    AccountSpec::new(5, "token_program", false, false),
+    AccountSpec::new(6, "pda", false, false),
+];
+
+/// Required accounts of `ClaimFee`
+pub const CLAIM_FEE_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "fee_token", false, true),
+    AccountSpec::new(2, "vault_token", false, true),
+    AccountSpec::new(3, "escrow", false, true),
+    AccountSpec::new(4, "token_program", false, false),
+    AccountSpec::new(5, "pda", false, false),
+];
+
+/// Required accounts of `SettleMany`, followed by a `SETTLE_MANY_GROUP` per escrow
+pub const SETTLE_MANY_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "token_program", false, false),
+    AccountSpec::new(2, "pda", false, false),
+];
+
+/// Accounts of one escrow settled by `SettleMany`, indexed within the group
+pub const SETTLE_MANY_GROUP: &[AccountSpec] = &[
+    AccountSpec::new(0, "payee_token", false, true),
+    AccountSpec::new(1, "fee_token", false, true),
+    AccountSpec::new(2, "vault_token", false, true),
+    AccountSpec::new(3, "escrow", false, true),
+    AccountSpec::new(4, "fee_payer", false, true),
+];
+
+/// Required accounts of `CheckAuthorization`
+pub const CHECK_AUTHORIZATION_ACCOUNTS: &[AccountSpec] = &[AccountSpec::new(0, "escrow", false, false)];
+
+/// Required accounts of `LogEscrow`
+pub const LOG_ESCROW_ACCOUNTS: &[AccountSpec] = &[AccountSpec::new(0, "escrow", false, false)];
+
+/// Required accounts of `DepositCounterparty`
+pub const DEPOSIT_COUNTERPARTY_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "payee", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+    AccountSpec::new(2, "payee_token", false, false),
+    AccountSpec::new(3, "counterparty_vault", false, true),
+    AccountSpec::new(4, "counterparty_refund", false, false),
+    AccountSpec::new(5, "token_program", false, false),
+];
 
+/// Required accounts of `Reclaim`
+pub const RECLAIM_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "payer", true, false),
+    AccountSpec::new(1, "escrow", false, true),
+    AccountSpec::new(2, "payer_token", false, true),
+    AccountSpec::new(3, "fee_payer", false, true),
+    AccountSpec::new(4, "vault_token", false, true),
+    AccountSpec::new(5, "token_program", false, false),
+    AccountSpec::new(6, "pda", false, false),
+];
+
+/// Required accounts of `SettlePartial`
+pub const SETTLE_PARTIAL_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "payee_token", false, true),
+    AccountSpec::new(2, "fee_token", false, true),
+    AccountSpec::new(3, "vault_token", false, true),
+    AccountSpec::new(4, "escrow", false, true),
+    AccountSpec::new(5, "token_program", false, false),
+    AccountSpec::new(6, "pda", false, false),
+];
+
+/// Required accounts of `CancelPartial`
+pub const CANCEL_PARTIAL_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "payer_token", false, true),
+    AccountSpec::new(2, "vault_token", false, true),
+    AccountSpec::new(3, "escrow", false, true),
+    AccountSpec::new(4, "token_program", false, false),
+    AccountSpec::new(5, "pda", false, false),
+];
+
+/// Required accounts of `SettleAndClose`
+pub const SETTLE_AND_CLOSE_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "payee_token", false, true),
+    AccountSpec::new(2, "fee_token", false, true),
+    AccountSpec::new(3, "vault_token", false, true),
+    AccountSpec::new(4, "escrow", false, true),
+    AccountSpec::new(5, "fee_payer", false, true),
+    AccountSpec::new(6, "token_program", false, false),
+    AccountSpec::new(7, "pda", false, false),
+];
+
+/// Required accounts of `SettleWithMint`
+pub const SETTLE_WITH_MINT_ACCOUNTS: &[AccountSpec] = &[
+    AccountSpec::new(0, "authority", true, false),
+    AccountSpec::new(1, "payee_token", false, true),
+    AccountSpec::new(2, "fee_token", false, true),
+    AccountSpec::new(3, "vault_token", false, true),
+    AccountSpec::new(4, "escrow", false, true),
+    AccountSpec::new(5, "fee_payer", false, true),
+    AccountSpec::new(6, "token_program", false, false),
+    AccountSpec::new(7, "pda", false, false),
+    AccountSpec::new(8, "points_mint", false, true),
+    AccountSpec::new(9, "payee_points_token", false, true),
+];
+
+/// Escrow action whose authorization `CheckAuthorization` reports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscrowAction {
+    Settle,
+    Cancel,
+    Reclaim,
+}
+
+impl EscrowAction {
+    fn unpack(input: &u8) -> Result<Self, ProgramError> {
+        match input {
+            0 => Ok(Self::Settle),
+            1 => Ok(Self::Cancel),
+            2 => Ok(Self::Reclaim),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+}
+
+/// Outcome of a dispute, the only flow `ResolveDispute` leaves open
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisputeResolution {
+    /// Settle to the payee
+    Settle = 1,
+    /// Refund the payer through `Cancel` or `Reclaim`
+    Refund = 2,
+}
+
+impl DisputeResolution {
+    fn unpack(input: &u8) -> Result<Self, ProgramError> {
+        match input {
+            1 => Ok(Self::Settle),
+            2 => Ok(Self::Refund),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+}
+
+/// Optional settings of a new escrow, appended after the fee in the `InitEscrow` data.
+/// Omitting them entirely selects the defaults, which disable every option.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InitOptions {
+    /// Program invoked after a successful settlement, the default pubkey disables the callback.
+    /// It receives the escrow account, writable but owned by this program so the callback can't
+    /// change it, and the escrow key, the amount paid to the payee and the fee as data.
+    pub callback_program: Pubkey,
+    /// Allow settling without supplying the callback program account. This hands the choice to
+    /// the settler, who can skip the callback by leaving the account out, so only set it when
+    /// nothing depends on the callback running. A callback that is supplied and fails always
+    /// aborts the settlement, Solana doesn't let a program recover from a failed CPI.
+    pub callback_optional: bool,
+    /// Require a pristine vault: owned by the payer, holding exactly the escrowed amount,
+    /// with no delegate and no close authority.
+    pub require_fresh_vault: bool,
+    /// Upper bound of the fee charged at settlement, zero for no cap
+    pub max_abs_fee: u64,
+    /// Mint of the points paid to the payee by `SettleWithMint`, its mint authority must be
+    /// the program authority
+    pub points_mint: Pubkey,
+    /// Points minted to the payee by `SettleWithMint`
+    pub points_amount: u64,
+    /// Require the payer to sign cancellations alongside the authority
+    pub require_payer_on_cancel: bool,
+    /// Part of the vault kept on cancellation and sent to `restock_token`, zero refunds everything
+    pub restock_fee: u64,
+    /// Account receiving the restocking fee
+    pub restock_token: Pubkey,
+    /// Top the escrow account up to rent exemption from a funder account
+    pub top_up_rent: bool,
+    /// Create the vault at the address derived from the escrow key instead of taking over a
+    /// vault prepared by the payer. Settle and cancel then re-derive and check it.
+    pub derived_vault: bool,
+    /// Earliest unix timestamp settlement is allowed at, zero for no bound
+    pub settle_not_before: i64,
+    /// Latest unix timestamp settlement is allowed at, zero for no bound
+    pub settle_not_after: i64,
+    /// Account that must exist at settlement, e.g. a delivery confirmation, default for none
+    pub condition_account: Pubkey,
+    /// Program the condition account must be owned by, default for any owner
+    pub condition_owner: Pubkey,
+    /// Percentage of the vault balance charged at settlement on top of the flat fee, in basis
+    /// points, at most 10_000
+    pub fee_bps: u16,
+    /// Mint of the payee's side of a two-sided swap, deposited by `DepositCounterparty`,
+    /// default for a one-sided escrow
+    pub counterparty_mint: Pubkey,
+    /// Amount of `counterparty_mint` the payee deposits
+    pub counterparty_amount: u64,
+    /// Payer's account receiving the payee's side at settlement
+    pub counterparty_token: Pubkey,
+    /// Unix timestamp from which the payer may `Reclaim` the escrow, zero for no expiry
+    pub expires_at: i64,
+    /// Settle whatever the vault holds instead of requiring its balance to match the escrowed
+    /// amount, for vaults topped up after init
+    pub allow_balance_drift: bool,
+    /// Create the escrow account at the address derived from the payer and vault keys instead
+    /// of taking over an account prepared by the client, see `find_escrow_address`. Excludes
+    /// `derived_vault`, whose address is derived from the escrow key.
+    pub derived_escrow: bool,
+    /// Require the payer to sign settlements along with the authority
+    pub require_dual_sig: bool,
+    /// Make the program authority the escrow authority, see `find_program_authority`. The
+    /// authority account passed to `InitEscrow` must then be the program authority, which
+    /// doesn't sign.
+    pub program_controlled: bool,
+    /// Let the authority pick the fee recipient at settlement instead of fixing it here, any
+    /// open token account of the vault mint, or any wallet for wrapped SOL. `SettlePartial`,
+    /// `SettleSplit` and `ClaimFee` still pay the fee token given here. Needs an external
+    /// authority, it excludes `program_controlled`.
+    pub dynamic_fee_recipient: bool,
+}
+
+impl InitOptions {
+    pub const LEN: usize = 292;
+
+    fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != Self::LEN {
+            return Err(InvalidInstruction.into());
+        }
+        let input = array_ref![input, 0, InitOptions::LEN];
+        let (
+            callback_program,
+            callback_optional,
+            require_fresh_vault,
+            max_abs_fee,
+            points_mint,
+            points_amount,
+            require_payer_on_cancel,
+            restock_fee,
+            restock_token,
+            top_up_rent,
+            derived_vault,
+            settle_not_before,
+            settle_not_after,
+            condition_account,
+            condition_owner,
+            fee_bps,
+            counterparty_mint,
+            counterparty_amount,
+            counterparty_token,
+            expires_at,
+            allow_balance_drift,
+            derived_escrow,
+            require_dual_sig,
+            program_controlled,
+            dynamic_fee_recipient,
+        ) = array_refs![
+            input, 32, 1, 1, 8, 32, 8, 1, 8, 32, 1, 1, 8, 8, 32, 32, 2, 32, 8, 32, 8, 1, 1, 1, 1, 1
+        ];
+        Ok(InitOptions {
+            callback_program: Pubkey::new_from_array(*callback_program),
+            callback_optional: Self::unpack_bool(callback_optional)?,
+            require_fresh_vault: Self::unpack_bool(require_fresh_vault)?,
+            max_abs_fee: u64::from_le_bytes(*max_abs_fee),
+            points_mint: Pubkey::new_from_array(*points_mint),
+            points_amount: u64::from_le_bytes(*points_amount),
+            require_payer_on_cancel: Self::unpack_bool(require_payer_on_cancel)?,
+            restock_fee: u64::from_le_bytes(*restock_fee),
+            restock_token: Pubkey::new_from_array(*restock_token),
+            top_up_rent: Self::unpack_bool(top_up_rent)?,
+            derived_vault: Self::unpack_bool(derived_vault)?,
+            settle_not_before: i64::from_le_bytes(*settle_not_before),
+            settle_not_after: i64::from_le_bytes(*settle_not_after),
+            condition_account: Pubkey::new_from_array(*condition_account),
+            condition_owner: Pubkey::new_from_array(*condition_owner),
+            fee_bps: u16::from_le_bytes(*fee_bps),
+            counterparty_mint: Pubkey::new_from_array(*counterparty_mint),
+            counterparty_amount: u64::from_le_bytes(*counterparty_amount),
+            counterparty_token: Pubkey::new_from_array(*counterparty_token),
+            expires_at: i64::from_le_bytes(*expires_at),
+            allow_balance_drift: Self::unpack_bool(allow_balance_drift)?,
+            derived_escrow: Self::unpack_bool(derived_escrow)?,
+            require_dual_sig: Self::unpack_bool(require_dual_sig)?,
+            program_controlled: Self::unpack_bool(program_controlled)?,
+            dynamic_fee_recipient: Self::unpack_bool(dynamic_fee_recipient)?,
+        })
+    }
+
+    /// Serializes the options the way `InitEscrow` expects them after the fee
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::LEN);
+        buf.extend_from_slice(self.callback_program.as_ref());
+        buf.push(self.callback_optional as u8);
+        buf.push(self.require_fresh_vault as u8);
+        buf.extend_from_slice(&self.max_abs_fee.to_le_bytes());
+        buf.extend_from_slice(self.points_mint.as_ref());
+        buf.extend_from_slice(&self.points_amount.to_le_bytes());
+        buf.push(self.require_payer_on_cancel as u8);
+        buf.extend_from_slice(&self.restock_fee.to_le_bytes());
+        buf.extend_from_slice(self.restock_token.as_ref());
+        buf.push(self.top_up_rent as u8);
+        buf.push(self.derived_vault as u8);
+        buf.extend_from_slice(&self.settle_not_before.to_le_bytes());
+        buf.extend_from_slice(&self.settle_not_after.to_le_bytes());
+        buf.extend_from_slice(self.condition_account.as_ref());
+        buf.extend_from_slice(self.condition_owner.as_ref());
+        buf.extend_from_slice(&self.fee_bps.to_le_bytes());
+        buf.extend_from_slice(self.counterparty_mint.as_ref());
+        buf.extend_from_slice(&self.counterparty_amount.to_le_bytes());
+        buf.extend_from_slice(self.counterparty_token.as_ref());
+        buf.extend_from_slice(&self.expires_at.to_le_bytes());
+        buf.push(self.allow_balance_drift as u8);
+        buf.push(self.derived_escrow as u8);
+        buf.push(self.require_dual_sig as u8);
+        buf.push(self.program_controlled as u8);
+        buf.push(self.dynamic_fee_recipient as u8);
+        buf
+    }
+
+    fn unpack_bool(input: &[u8; 1]) -> Result<bool, ProgramError> {
+        match input {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(InvalidInstruction.into()),
+        }
+    }
+}
+
+// Instructions are decoded once per call, the size of `InitEscrow` doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum EscrowInstruction {
     /// Starts the trade by creating and populating an escrow account and transferring ownership of the given temp token account to the PDA
     ///
+    /// The amount must not be zero and the fee must not exceed it. A fee equal to the amount is
+    /// allowed, the fee recipient then receives the whole vault at settlement.
+    ///
     ///
-    /// Accounts expected:
+    /// Accounts expected, the required ones are listed by [`INIT_ESCROW_ACCOUNTS`]:
     ///
     /// 0. `[signer]` The account of the payer initializing the escrow
     /// 1. `[writable]`Temporary token account that should be created prior to this instruction and owned by the payer
-    /// 2. `[signer]` The escrow authority responsible for approving / refunding payments due to some external conditions
+    /// 2. `[signer]` The escrow authority responsible for approving / refunding payments due to some external conditions, the unsigned program authority with `program_controlled`
     /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade.
     /// 4. `[]` The payer token account that will receive the amount if the transaction is canceled
     /// 5. `[]` The payee token account that will receive the amount if the transaction is successful
     /// 6. `[]` The fee token account that will receive the fee if the transaction is successful
     /// 7. `[]` The rent sysvar
-    /// 8. `[]` The token program
+    /// 8. `[]` The token program, classic or Token-2022, every later instruction must pass the same one
+    /// 9. `[signer, writable]` The account funding the escrow's rent, if `top_up_rent` is set
+    /// 10. `[]` The system program, if `top_up_rent` is set
+    /// 11. `[]` The vault mint, if `derived_vault` is set
+    /// 12. `[]` The system program, if `derived_vault` is set
+    /// 13. `[]` The system program, if `derived_escrow` is set
+    ///
+    /// With `derived_vault` the vault (1) is created by this instruction at the address
+    /// derived from the escrow key. The payer must then be writable, it pays the vault rent,
+    /// and for SPL tokens the payer token account (4) must be writable, it funds the vault.
+    /// Wrapped SOL vaults are funded with lamports from the payer.
+    ///
+    /// With `derived_escrow` the escrow (3) is created by this instruction at the address
+    /// derived from the payer and vault keys, the payer must then be writable and pays its rent.
     InitEscrow {
         /// The total amount of token X to be paid by the payer
         amount: u64,
-        /// The fee to collect
+        /// The flat fee to collect, `fee_bps` adds a percentage of the amount
         fee: u64,
+        /// The optional escrow settings
+        options: InitOptions,
     },
     /// Settle the payment
     ///
+    /// For wrapped SOL escrows the payee and fee accounts are plain system wallets credited with
+    /// lamports. A wallet that doesn't exist yet is created by the credit, so it must receive at
+    /// least the rent exempt minimum to outlive rent collection. The vault is closed into the
+    /// escrow account, which passes its rent on to the fee payer and keeps only its own.
     ///
-    /// Accounts expected:
+    ///
+    /// Accounts expected, the required ones are listed by [`SETTLE_ACCOUNTS`]:
     ///
     /// 0. `[signer]` The account of the authority
     /// 1. `[writable]` The taker's token account for the token they will receive should the trade go through
-    /// 2. `[writable]` The fee taker's token account for the token they will receive should the trade go through
+    /// 2. `[writable]` The fee taker's token account for the token they will receive should the trade go through, unchecked when no fee is due, any account of the vault mint with `dynamic_fee_recipient`
     /// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close
-    /// 4. `[writable]` The fee payer's main account to send their rent fees to
-    /// 5. `[writable]` The escrow account holding the escrow info
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[writable]` The fee payer's main account to send their rent fees to
     /// 6. `[]` The token program
     /// 7. `[]` The PDA account
-    Settle,
+    /// 8. `[]` The condition account, if the escrow has one
+    /// 9. `[writable]` The counterparty vault, for a two-sided swap
+    /// 10. `[writable]` The payer's counterparty token account, for a two-sided swap
+    /// 11. `[]` The callback program, if the escrow has one
+    ///
+    /// An escrow with `require_dual_sig` also needs the payer among the accounts as a signer,
+    /// at any position after the required ones. `SettlePartial` takes it the same way.
+    /// A wrapped SOL vault holding lamports sent to it without a sync returns them to the payer,
+    /// as `Cancel` does, the payer then has to be among the accounts the same way, writable.
+    ///
+    /// The authority (0) is either an external key, which must sign, or for a
+    /// `program_controlled` escrow the program authority. The program signs for the latter
+    /// with its seeds, so the account is passed unsigned and anyone may submit the settlement
+    /// once the escrow's own conditions, such as its settlement window, condition account or
+    /// dual signature, are met. The fee payer (5) must then be the payer, who gets back the
+    /// rent the settlement frees. `SettleWithMint` and `SettleAndClose` settle the same way,
+    /// the other authority actions need an external authority.
+    Settle {
+        /// Reference recorded on the escrow, such as an invoice id. It may be omitted from
+        /// the data, which records zeroes.
+        memo: [u8; 32],
+    },
     /// Cancel the escrow
     ///
+    /// The payer is refunded the whole vault less any restocking fee, including tokens or
+    /// lamports sent to the vault after init. The fee payer receives only the vault rent.
+    /// A deposited counterparty side goes back to the payee's refund account.
     ///
-    /// Accounts expected:
+    ///
+    /// Accounts expected, the required ones are listed by [`CANCEL_ACCOUNTS`]:
     ///
     /// 0. `[signer]` The account of the authority
     /// 1. `[writable]` The escrow account holding the escrow info   
@@ -51,35 +613,494 @@ pub enum EscrowInstruction {
     /// 4. `[writable]` The PDA's temp token account to get tokens from and eventually close
     /// 5. `[]` The token program
     /// 6. `[]` The PDA account
+    /// 7. `[signer]` The account of the payer, if the escrow requires it
+    /// 8. `[writable]` The restock fee account, if the escrow charges a restocking fee
+    /// 9. `[writable]` The counterparty vault, if the payee deposited it
+    /// 10. `[writable]` The payee's counterparty refund account, if the payee deposited
     Cancel,
     /// Close the escrow
     ///
+    /// Closing an escrow that is already closed, including earlier in the same transaction,
+    /// fails with `AccountNotInitialized`.
     ///
-    /// Accounts expected:
+    /// Accounts expected, the required ones are listed by [`CLOSE_ACCOUNTS`]:
     ///
     /// 0. `[signer]` The account of the authority
     /// 1. `[writable]` The escrow account holding the escrow info     
     /// 2. `[writable]` The fee payer's main account to send their rent fees to
+    /// 3. `[]` The vault, optional, closing fails while it still holds funds
     Close,
+    /// Close a batch of settled or canceled escrows and sweep their rent to a treasury
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`CLOSE_MANY_TO_TREASURY_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The treasury account receiving the reclaimed rent
+    /// 2. `[writable]` The escrow accounts to close, at most `MAX_BATCH` of them
+    CloseManyToTreasury,
+    /// Drain and wipe a program owned account regardless of its escrow state.
+    /// Maintenance escape hatch for accounts left behind by a faulty close.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`FORCE_RECLAIM_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The admin account
+    /// 1. `[writable]` The program owned account to reclaim
+    /// 2. `[writable]` The account receiving the reclaimed lamports
+    ForceReclaim,
+    /// Change the account receiving the fee of an active escrow
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`UPDATE_FEE_TOKEN_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[]` The new fee token account, of the same mint as the vault
+    UpdateFeeToken,
+    /// Settle the payment and mint the escrow's points to the payee
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`SETTLE_WITH_MINT_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The payee token account
+    /// 2. `[writable]` The fee token account, unchecked when no fee is due
+    /// 3. `[writable]` The vault
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[writable]` The fee payer's main account to send their rent fees to
+    /// 6. `[]` The token program
+    /// 7. `[]` The PDA account
+    /// 8. `[writable]` The points mint
+    /// 9. `[writable]` The payee's points token account
+    /// 10. `[]` The condition account, if the escrow has one
+    /// 11. `[writable]` The counterparty vault, for a two-sided swap
+    /// 12. `[writable]` The payer's counterparty token account, for a two-sided swap
+    /// 13. `[]` The callback program, if the escrow has one
+    SettleWithMint,
+    /// Settle a batch of escrows sharing one authority, at most `MAX_BATCH` of them. Escrows
+    /// already settled or canceled are skipped, any other failure aborts the whole batch.
+    /// Escrows with a callback program can't be settled in a batch unless the callback is
+    /// optional, it is then skipped. Escrows with a condition account or a counterparty side
+    /// can't be settled in a batch.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`SETTLE_MANY_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[]` The token program
+    /// 2. `[]` The PDA account
+    ///
+    /// Followed for each escrow by its writable payee token, fee token, vault, escrow and fee
+    /// payer accounts, as listed by [`SETTLE_MANY_GROUP`].
+    SettleMany,
+    /// Report whether a key may perform an action on the escrow right now, applying the
+    /// checks of the action itself. Writes one byte of return data, 1 if authorized and 0 if
+    /// not. Signatures other than the key's, like the payer's on cancel, aren't considered.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`CHECK_AUTHORIZATION_ACCOUNTS`]:
+    ///
+    /// 0. `[]` The escrow account
+    CheckAuthorization {
+        /// The action to check, 0 for settle, 1 for cancel and 2 for reclaim, see `EscrowAction`
+        action: EscrowAction,
+        /// The key that would sign the action
+        key: Pubkey,
+    },
+    /// Log every field of the escrow as `name=value` pairs, changing nothing
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`LOG_ESCROW_ACCOUNTS`]:
+    ///
+    /// 0. `[]` The escrow account
+    LogEscrow,
+    /// Deposit the payee's side of a two-sided swap. The payee hands over a vault holding
+    /// exactly `counterparty_amount` of `counterparty_mint`, like the payer does at init.
+    /// Settlement then requires the deposit and releases both sides, cancellation refunds both.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`DEPOSIT_COUNTERPARTY_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The payee, owner of the payee token account or the payee wallet for wrapped SOL
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[]` The payee token account of the escrow
+    /// 3. `[writable]` The counterparty vault, a pristine token account owned by the payee
+    /// 4. `[]` The payee's token account refunded on cancellation
+    /// 5. `[]` The token program
+    DepositCounterparty,
+    /// Return an expired escrow to its payer, who signs instead of the authority. Works like
+    /// `Cancel` without the restocking fee: the payer token account gets the whole vault and
+    /// a deposited counterparty side goes back to the payee.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`RECLAIM_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The payer that initialized the escrow
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[writable]` The token account of the payer that initialized the escrow
+    /// 3. `[writable]` The fee payer's main account to send their rent fees to
+    /// 4. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[writable]` The counterparty vault, if the payee deposited it
+    /// 8. `[writable]` The payee's counterparty refund account, if the payee deposited
+    Reclaim,
+    /// Release part of an SPL token escrow to the payee, keeping the escrow open. The fee is
+    /// the `fee_bps` share of the released amount and is taken out of it, the flat fee and
+    /// `max_abs_fee` are left to the final `Settle`, which pays out the rest and closes the
+    /// vault. The escrowed amount shrinks by each release.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`SETTLE_PARTIAL_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The payee token account
+    /// 2. `[writable]` The fee taker's token account, unchecked when no fee is due
+    /// 3. `[writable]` The PDA's temp token account to release tokens from
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[]` The condition account, if the escrow has one
+    SettlePartial {
+        /// The amount taken from the vault, at most its balance
+        amount: u64,
+    },
+    /// Hand an active escrow over to a new authority, counted by its rotation count
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`CHANGE_AUTHORITY_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the current authority
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[]` The new authority
+    ChangeAuthority,
+    /// Add tokens of the escrow's mint to an active escrow, growing the escrowed amount
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`TOP_UP_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the payer who initialized the escrow
+    /// 1. `[writable]` The payer's token account the tokens are taken from
+    /// 2. `[writable]` The PDA's temp token account receiving them
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    TopUp {
+        /// The amount added to the vault
+        amount: u64,
+    },
+    /// Freeze an active escrow pending the resolution of a dispute, settlement and refunds are
+    /// rejected until `ResolveDispute`
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`RAISE_DISPUTE_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The payer or the authority of the escrow
+    /// 1. `[writable]` The escrow account holding the escrow info
+    RaiseDispute,
+    /// Lift a dispute, leaving only the flow of the resolution open
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`RESOLVE_DISPUTE_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The escrow account holding the escrow info
+    ResolveDispute {
+        /// Whether the escrow goes to the payee or back to the payer
+        resolution: DisputeResolution,
+    },
+    /// Settle an SPL token escrow to several recipients instead of the payee. The amounts must
+    /// add up to the vault balance less the settlement fee, the vault is then closed. Escrows
+    /// with a callback or a counterparty side settle through `Settle` only.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`SETTLE_SPLIT_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The fee taker's token account, unchecked when no fee is due
+    /// 2. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[writable]` The fee payer's main account to send their rent fees to
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[]` The condition account, if the escrow has one
+    /// 8. `[writable]` The token account of each recipient, in the order of `recipients`
+    SettleSplit {
+        /// Token account and amount of each recipient, at most `MAX_SPLIT` of them
+        recipients: Vec<(Pubkey, u64)>,
+    },
+    /// Pay the flat fee of an SPL token escrow to the fee recipient ahead of the settlement,
+    /// leaving the vault open. The escrowed amount shrinks by the fee and the fee is zeroed,
+    /// so the final settlement only charges the `fee_bps` share of what is left.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`CLAIM_FEE_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The fee taker's token account
+    /// 2. `[writable]` The PDA's temp token account to get tokens from
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    /// 5. `[]` The PDA account
+    ClaimFee,
+    /// Refund part of an SPL token escrow to the payer, keeping the escrow open. The escrowed
+    /// amount shrinks by each refund, the rest settles or cancels as usual. No restocking fee
+    /// is charged, it is left to the final `Cancel`.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`CANCEL_PARTIAL_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The payer token account
+    /// 2. `[writable]` The PDA's temp token account to refund tokens from
+    /// 3. `[writable]` The escrow account holding the escrow info
+    /// 4. `[]` The token program
+    /// 5. `[]` The PDA account
+    CancelPartial {
+        /// The amount refunded
+        amount: u64,
+    },
+    /// Settle the payment like `Settle` without a memo, then close the escrow account like
+    /// `Close`, its lamports going to the fee payer. The callback, if any, still runs on the
+    /// settled escrow before it is closed.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`SETTLE_AND_CLOSE_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The payee token account
+    /// 2. `[writable]` The fee token account, unchecked when no fee is due
+    /// 3. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 4. `[writable]` The escrow account holding the escrow info
+    /// 5. `[writable]` The fee payer's main account, receiving the vault and escrow rent
+    /// 6. `[]` The token program
+    /// 7. `[]` The PDA account
+    /// 8. `[]` The condition account, if the escrow has one
+    /// 9. `[writable]` The counterparty vault, for a two-sided swap
+    /// 10. `[writable]` The payer's counterparty token account, for a two-sided swap
+    /// 11. `[]` The callback program, if the escrow has one
+    SettleAndClose,
+    /// Cancel a wrapped SOL escrow like `Cancel`, unwrapping the refund into native lamports
+    /// on the payer's system account. `Cancel` refunds wrapped SOL vaults the same way, this
+    /// variant fails with `UnwrapNonNative` for any other mint instead of refunding tokens.
+    ///
+    ///
+    /// Accounts expected, the required ones are listed by [`CANCEL_UNWRAP_ACCOUNTS`]:
+    ///
+    /// 0. `[signer]` The account of the authority
+    /// 1. `[writable]` The escrow account holding the escrow info
+    /// 2. `[writable]` The payer's system account receiving the lamports
+    /// 3. `[writable]` The fee payer's main account, receiving the vault rent
+    /// 4. `[writable]` The wrapped SOL vault, closed by the refund
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[signer]` The account of the payer, if the escrow requires it
+    /// 8. `[writable]` The restock fee wallet, if the escrow charges a restocking fee
+    CancelUnwrap,
 }
 
 impl EscrowInstruction {
+    /// Decodes instruction data of this program, for tools replaying its transactions
+    pub fn try_decode(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::unpack(data)
+    }
+
     /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
+    ///
+    /// The data after the tag must have exactly the length of the variant's payload, short
+    /// and trailing data fail with `InvalidInstructionLength`.
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        // Variants without a payload
+        let bare = |instruction: Self| {
+            if rest.is_empty() {
+                Ok(instruction)
+            } else {
+                Err(ProgramError::from(InvalidInstructionLength))
+            }
+        };
 
-        Ok(match tag {
-            0 => Self::InitEscrow {
-                amount: Self::unpack_amount(rest)?,
-                fee: Self::unpack_fee(rest)?,
+        Ok(match *tag {
+            TAG_INIT => {
+                if rest.len() != 16 && rest.len() != 16 + InitOptions::LEN {
+                    return Err(InvalidInstructionLength.into());
+                }
+                Self::InitEscrow {
+                    amount: Self::unpack_amount(rest)?,
+                    fee: Self::unpack_fee(rest)?,
+                    options: match &rest[16..] {
+                        [] => InitOptions::default(),
+                        options => InitOptions::unpack(options)?,
+                    },
+                }
+            }
+            TAG_SETTLE => Self::Settle {
+                memo: Self::unpack_memo(rest)?,
+            },
+            TAG_CANCEL => bare(Self::Cancel)?,
+            TAG_CLOSE => bare(Self::Close)?,
+            TAG_CLOSE_MANY_TO_TREASURY => bare(Self::CloseManyToTreasury)?,
+            TAG_FORCE_RECLAIM => bare(Self::ForceReclaim)?,
+            TAG_UPDATE_FEE_TOKEN => bare(Self::UpdateFeeToken)?,
+            TAG_SETTLE_WITH_MINT => bare(Self::SettleWithMint)?,
+            TAG_SETTLE_MANY => bare(Self::SettleMany)?,
+            TAG_LOG_ESCROW => bare(Self::LogEscrow)?,
+            TAG_DEPOSIT_COUNTERPARTY => bare(Self::DepositCounterparty)?,
+            TAG_RECLAIM => bare(Self::Reclaim)?,
+            TAG_CHANGE_AUTHORITY => bare(Self::ChangeAuthority)?,
+            TAG_RAISE_DISPUTE => bare(Self::RaiseDispute)?,
+            TAG_CLAIM_FEE => bare(Self::ClaimFee)?,
+            TAG_SETTLE_AND_CLOSE => bare(Self::SettleAndClose)?,
+            TAG_CANCEL_UNWRAP => bare(Self::CancelUnwrap)?,
+            TAG_SETTLE_SPLIT => Self::SettleSplit {
+                recipients: Self::unpack_recipients(rest)?,
+            },
+            TAG_RESOLVE_DISPUTE => match rest {
+                [resolution] => Self::ResolveDispute {
+                    resolution: DisputeResolution::unpack(resolution)?,
+                },
+                _ => return Err(InvalidInstructionLength.into()),
+            },
+            TAG_SETTLE_PARTIAL => Self::SettlePartial {
+                amount: Self::unpack_only_amount(rest)?,
+            },
+            TAG_TOP_UP => Self::TopUp {
+                amount: Self::unpack_only_amount(rest)?,
+            },
+            TAG_CANCEL_PARTIAL => Self::CancelPartial {
+                amount: Self::unpack_only_amount(rest)?,
+            },
+            TAG_CHECK_AUTHORIZATION => match rest {
+                [action, key @ ..] if key.len() == 32 => Self::CheckAuthorization {
+                    action: EscrowAction::unpack(action)?,
+                    key: Pubkey::new(key),
+                },
+                _ => return Err(InvalidInstructionLength.into()),
             },
-            1 => Self::Settle,
-            2 => Self::Cancel,
-            3 => Self::Close,
             _ => return Err(InvalidInstruction.into()),
         })
     }
 
+    /// Packs an [EscrowInstruction](enum.EscrowInstruction.html) into instruction data,
+    /// leaving out default init options and an empty settle memo
+    pub fn pack(&self) -> Vec<u8> {
+        match self {
+            Self::InitEscrow {
+                amount,
+                fee,
+                options,
+            } => {
+                let mut buf = vec![TAG_INIT];
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&fee.to_le_bytes());
+                if *options != InitOptions::default() {
+                    buf.extend_from_slice(&options.pack());
+                }
+                buf
+            }
+            Self::Settle { memo } => {
+                let mut buf = vec![TAG_SETTLE];
+                if *memo != [0; 32] {
+                    buf.extend_from_slice(memo);
+                }
+                buf
+            }
+            Self::Cancel => vec![TAG_CANCEL],
+            Self::Close => vec![TAG_CLOSE],
+            Self::CloseManyToTreasury => vec![TAG_CLOSE_MANY_TO_TREASURY],
+            Self::ForceReclaim => vec![TAG_FORCE_RECLAIM],
+            Self::UpdateFeeToken => vec![TAG_UPDATE_FEE_TOKEN],
+            Self::SettleWithMint => vec![TAG_SETTLE_WITH_MINT],
+            Self::SettleMany => vec![TAG_SETTLE_MANY],
+            Self::LogEscrow => vec![TAG_LOG_ESCROW],
+            Self::DepositCounterparty => vec![TAG_DEPOSIT_COUNTERPARTY],
+            Self::Reclaim => vec![TAG_RECLAIM],
+            Self::ChangeAuthority => vec![TAG_CHANGE_AUTHORITY],
+            Self::RaiseDispute => vec![TAG_RAISE_DISPUTE],
+            Self::ClaimFee => vec![TAG_CLAIM_FEE],
+            Self::SettleAndClose => vec![TAG_SETTLE_AND_CLOSE],
+            Self::CancelUnwrap => vec![TAG_CANCEL_UNWRAP],
+            Self::SettleSplit { recipients } => {
+                let mut buf = vec![TAG_SETTLE_SPLIT, recipients.len() as u8];
+                for (token, amount) in recipients {
+                    buf.extend_from_slice(token.as_ref());
+                    buf.extend_from_slice(&amount.to_le_bytes());
+                }
+                buf
+            }
+            Self::ResolveDispute { resolution } => vec![TAG_RESOLVE_DISPUTE, *resolution as u8],
+            Self::SettlePartial { amount } => {
+                let mut buf = vec![TAG_SETTLE_PARTIAL];
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf
+            }
+            Self::TopUp { amount } => {
+                let mut buf = vec![TAG_TOP_UP];
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf
+            }
+            Self::CancelPartial { amount } => {
+                let mut buf = vec![TAG_CANCEL_PARTIAL];
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf
+            }
+            Self::CheckAuthorization { action, key } => {
+                let mut buf = vec![TAG_CHECK_AUTHORIZATION, *action as u8];
+                buf.extend_from_slice(key.as_ref());
+                buf
+            }
+        }
+    }
+
+    /// Position of the escrow account, none for instructions over several or no escrows
+    pub fn escrow_index(&self) -> Option<usize> {
+        let specs = match self {
+            Self::InitEscrow { .. } => INIT_ESCROW_ACCOUNTS,
+            Self::Settle { .. } => SETTLE_ACCOUNTS,
+            Self::Cancel => CANCEL_ACCOUNTS,
+            Self::Close => CLOSE_ACCOUNTS,
+            Self::UpdateFeeToken => UPDATE_FEE_TOKEN_ACCOUNTS,
+            Self::SettleWithMint => SETTLE_WITH_MINT_ACCOUNTS,
+            Self::CheckAuthorization { .. } => CHECK_AUTHORIZATION_ACCOUNTS,
+            Self::LogEscrow => LOG_ESCROW_ACCOUNTS,
+            Self::DepositCounterparty => DEPOSIT_COUNTERPARTY_ACCOUNTS,
+            Self::Reclaim => RECLAIM_ACCOUNTS,
+            Self::SettlePartial { .. } => SETTLE_PARTIAL_ACCOUNTS,
+            Self::ChangeAuthority => CHANGE_AUTHORITY_ACCOUNTS,
+            Self::TopUp { .. } => TOP_UP_ACCOUNTS,
+            Self::RaiseDispute => RAISE_DISPUTE_ACCOUNTS,
+            Self::ResolveDispute { .. } => RESOLVE_DISPUTE_ACCOUNTS,
+            Self::SettleSplit { .. } => SETTLE_SPLIT_ACCOUNTS,
+            Self::ClaimFee => CLAIM_FEE_ACCOUNTS,
+            Self::SettleAndClose => SETTLE_AND_CLOSE_ACCOUNTS,
+            Self::CancelPartial { .. } => CANCEL_PARTIAL_ACCOUNTS,
+            Self::CancelUnwrap => CANCEL_UNWRAP_ACCOUNTS,
+            Self::CloseManyToTreasury | Self::ForceReclaim | Self::SettleMany => return None,
+        };
+        specs
+            .iter()
+            .find(|spec| spec.name == "escrow")
+            .map(|spec| spec.index)
+    }
+
+    /// A count byte followed by that many token keys and amounts
+    fn unpack_recipients(input: &[u8]) -> Result<Vec<(Pubkey, u64)>, ProgramError> {
+        let (count, entries) = input.split_first().ok_or(InvalidInstructionLength)?;
+        if *count == 0 {
+            return Err(InvalidInstruction.into());
+        }
+        if entries.len() != *count as usize * 40 {
+            return Err(InvalidInstructionLength.into());
+        }
+        entries
+            .chunks(40)
+            .map(|entry| {
+                let (token, amount) = entry.split_at(32);
+                Ok((Pubkey::new(token), Self::unpack_amount(amount)?))
+            })
+            .collect()
+    }
+
     fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
         input
             .get(..8)
@@ -88,6 +1109,14 @@ impl EscrowInstruction {
             .ok_or(InvalidInstruction.into())
     }
 
+    /// The amount of a variant whose whole payload it is
+    fn unpack_only_amount(input: &[u8]) -> Result<u64, ProgramError> {
+        if input.len() != 8 {
+            return Err(InvalidInstructionLength.into());
+        }
+        Self::unpack_amount(input)
+    }
+
     fn unpack_fee(input: &[u8]) -> Result<u64, ProgramError> {
         input
             .get(8..16)
@@ -95,4 +1124,556 @@ impl EscrowInstruction {
             .map(u64::from_le_bytes)
             .ok_or(InvalidInstruction.into())
     }
+
+    fn unpack_memo(input: &[u8]) -> Result<[u8; 32], ProgramError> {
+        match input {
+            [] => Ok([0; 32]),
+            memo => memo.try_into().map_err(|_| InvalidInstructionLength.into()),
+        }
+    }
+}
+
+impl fmt::Display for EscrowInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InitEscrow {
+                amount,
+                fee,
+                options,
+            } => {
+                if *options == InitOptions::default() {
+                    write!(f, "InitEscrow {{ amount: {}, fee: {} }}", amount, fee)
+                } else {
+                    write!(
+                        f,
+                        "InitEscrow {{ amount: {}, fee: {}, options: {:?} }}",
+                        amount, fee, options
+                    )
+                }
+            }
+            Self::Settle { memo } => {
+                if *memo == [0; 32] {
+                    write!(f, "Settle")
+                } else {
+                    write!(f, "Settle {{ memo: ")?;
+                    for byte in memo {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                    write!(f, " }}")
+                }
+            }
+            Self::Cancel => write!(f, "Cancel"),
+            Self::Close => write!(f, "Close"),
+            Self::CloseManyToTreasury => write!(f, "CloseManyToTreasury"),
+            Self::ForceReclaim => write!(f, "ForceReclaim"),
+            Self::UpdateFeeToken => write!(f, "UpdateFeeToken"),
+            Self::SettleWithMint => write!(f, "SettleWithMint"),
+            Self::SettleMany => write!(f, "SettleMany"),
+            Self::LogEscrow => write!(f, "LogEscrow"),
+            Self::DepositCounterparty => write!(f, "DepositCounterparty"),
+            Self::Reclaim => write!(f, "Reclaim"),
+            Self::SettlePartial { amount } => write!(f, "SettlePartial {{ amount: {} }}", amount),
+            Self::ChangeAuthority => write!(f, "ChangeAuthority"),
+            Self::TopUp { amount } => write!(f, "TopUp {{ amount: {} }}", amount),
+            Self::CancelPartial { amount } => write!(f, "CancelPartial {{ amount: {} }}", amount),
+            Self::RaiseDispute => write!(f, "RaiseDispute"),
+            Self::ClaimFee => write!(f, "ClaimFee"),
+            Self::SettleAndClose => write!(f, "SettleAndClose"),
+            Self::CancelUnwrap => write!(f, "CancelUnwrap"),
+            Self::SettleSplit { recipients } => {
+                write!(f, "SettleSplit {{ recipients: {} }}", recipients.len())
+            }
+            Self::ResolveDispute { resolution } => {
+                write!(f, "ResolveDispute {{ resolution: {:?} }}", resolution)
+            }
+            Self::CheckAuthorization { action, key } => write!(
+                f,
+                "CheckAuthorization {{ action: {:?}, key: {} }}",
+                action, key
+            ),
+        }
+    }
+}
+
+/// Builds the instructions opening a wrapped SOL escrow of `amount` lamports: creating the
+/// vault, funded with `vault_rent` plus `amount`, handing it to the payer and initializing the
+/// escrow with the payer wallet as the refund account. `payer`, `vault` and `authority` sign,
+/// `payee` and `fee_taker` are the wallets credited at settlement.
+#[allow(clippy::too_many_arguments)]
+pub fn init_native_escrow(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    vault: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    payee: &Pubkey,
+    fee_taker: &Pubkey,
+    amount: u64,
+    fee: u64,
+    vault_rent: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    let vault_lamports = vault_rent
+        .checked_add(amount)
+        .ok_or(AmountOverflow)?;
+    Ok(vec![
+        system_instruction::create_account(
+            payer,
+            vault,
+            vault_lamports,
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account(
+            &spl_token::id(),
+            vault,
+            &spl_token::native_mint::id(),
+            payer,
+        )?,
+        init_escrow(
+            program_id, payer, vault, authority, escrow, payer, payee, fee_taker, amount, fee,
+        ),
+    ])
+}
+
+/// Builds an `InitEscrow` instruction without optional settings, for an SPL token vault
+/// already holding `amount` and owned by `payer`
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    vault_token: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    payer_token: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+    amount: u64,
+    fee: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            INIT_ESCROW_ACCOUNTS,
+            &[
+                *payer,
+                *vault_token,
+                *authority,
+                *escrow,
+                *payer_token,
+                *payee_token,
+                *fee_token,
+                sysvar::rent::id(),
+                spl_token::id(),
+            ],
+        ),
+        data: EscrowInstruction::InitEscrow {
+            amount,
+            fee,
+            options: InitOptions::default(),
+        }
+        .pack(),
+    }
+}
+
+/// Builds every instruction setting up an escrow of `mint`, in order: the vault and its
+/// funding, the escrow account and `InitEscrow`. The vault and escrow accounts are sized and
+/// funded for rent here, both must sign next to the payer and the authority.
+///
+/// SPL token vaults are funded from `payer_token`. Wrapped SOL vaults are created with the
+/// amount as lamports, as `init_native_escrow` does, and `payer_token` is the payer's wallet
+/// refunded on cancel.
+#[allow(clippy::too_many_arguments)]
+pub fn create_escrow_instructions(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    vault: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    mint: &Pubkey,
+    payer_token: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+    amount: u64,
+    fee: u64,
+    rent: &Rent,
+) -> Result<Vec<Instruction>, ProgramError> {
+    let vault_rent = rent.minimum_balance(TokenAccount::LEN);
+    let is_native = *mint == spl_token::native_mint::id();
+    let vault_lamports = if is_native {
+        vault_rent.checked_add(amount).ok_or(AmountOverflow)?
+    } else {
+        vault_rent
+    };
+    let mut instructions = vec![
+        system_instruction::create_account(
+            payer,
+            vault,
+            vault_lamports,
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account(&spl_token::id(), vault, mint, payer)?,
+    ];
+    if !is_native {
+        instructions.push(spl_token::instruction::transfer(
+            &spl_token::id(),
+            payer_token,
+            vault,
+            payer,
+            &[],
+            amount,
+        )?);
+    }
+    instructions.push(system_instruction::create_account(
+        payer,
+        escrow,
+        rent.minimum_balance(Escrow::LEN),
+        Escrow::LEN as u64,
+        program_id,
+    ));
+    instructions.push(init_escrow(
+        program_id,
+        payer,
+        vault,
+        authority,
+        escrow,
+        payer_token,
+        payee_token,
+        fee_token,
+        amount,
+        fee,
+    ));
+    Ok(instructions)
+}
+
+/// Builds a `Settle` instruction without a memo
+pub fn settle(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+    fee_payer: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            SETTLE_ACCOUNTS,
+            &[
+                *authority,
+                *payee_token,
+                *fee_token,
+                *vault_token,
+                *escrow,
+                *fee_payer,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::Settle { memo: [0; 32] }.pack(),
+    }
+}
+
+/// Builds a `SettleAndClose` instruction
+pub fn settle_and_close(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+    fee_payer: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            SETTLE_AND_CLOSE_ACCOUNTS,
+            &[
+                *authority,
+                *payee_token,
+                *fee_token,
+                *vault_token,
+                *escrow,
+                *fee_payer,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::SettleAndClose.pack(),
+    }
+}
+
+/// Builds a `Cancel` instruction
+pub fn cancel(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    payer_token: &Pubkey,
+    fee_payer: &Pubkey,
+    vault_token: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            CANCEL_ACCOUNTS,
+            &[
+                *authority,
+                *escrow,
+                *payer_token,
+                *fee_payer,
+                *vault_token,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::Cancel.pack(),
+    }
+}
+
+/// Builds a `CancelUnwrap` instruction refunding a wrapped SOL vault to the `payer` wallet
+pub fn cancel_unwrap(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    payer: &Pubkey,
+    fee_payer: &Pubkey,
+    vault_token: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            CANCEL_UNWRAP_ACCOUNTS,
+            &[
+                *authority,
+                *escrow,
+                *payer,
+                *fee_payer,
+                *vault_token,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::CancelUnwrap.pack(),
+    }
+}
+
+/// Builds a `Close` instruction
+pub fn close(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    fee_payer: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(CLOSE_ACCOUNTS, &[*authority, *escrow, *fee_payer]),
+        data: EscrowInstruction::Close.pack(),
+    }
+}
+
+/// Builds a `Reclaim` instruction, signed by the payer
+pub fn reclaim(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    escrow: &Pubkey,
+    payer_token: &Pubkey,
+    fee_payer: &Pubkey,
+    vault_token: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            RECLAIM_ACCOUNTS,
+            &[
+                *payer,
+                *escrow,
+                *payer_token,
+                *fee_payer,
+                *vault_token,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::Reclaim.pack(),
+    }
+}
+
+/// Builds a `SettlePartial` instruction releasing `amount` from the vault
+pub fn settle_partial(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            SETTLE_PARTIAL_ACCOUNTS,
+            &[
+                *authority,
+                *payee_token,
+                *fee_token,
+                *vault_token,
+                *escrow,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::SettlePartial { amount }.pack(),
+    }
+}
+
+/// Builds a `CancelPartial` instruction refunding `amount` from the vault
+pub fn cancel_partial(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            CANCEL_PARTIAL_ACCOUNTS,
+            &[
+                *authority,
+                *payer_token,
+                *vault_token,
+                *escrow,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::CancelPartial { amount }.pack(),
+    }
+}
+
+/// Builds a `ChangeAuthority` instruction, signed by the current authority
+pub fn change_authority(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    new_authority: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            CHANGE_AUTHORITY_ACCOUNTS,
+            &[*authority, *escrow, *new_authority],
+        ),
+        data: EscrowInstruction::ChangeAuthority.pack(),
+    }
+}
+
+/// Builds a `TopUp` instruction, signed by the payer owning `source_token`
+pub fn top_up(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    source_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            TOP_UP_ACCOUNTS,
+            &[*payer, *source_token, *vault_token, *escrow, spl_token::id()],
+        ),
+        data: EscrowInstruction::TopUp { amount }.pack(),
+    }
+}
+
+/// Builds a `RaiseDispute` instruction, signed by the escrow's payer or authority
+pub fn raise_dispute(program_id: &Pubkey, party: &Pubkey, escrow: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(RAISE_DISPUTE_ACCOUNTS, &[*party, *escrow]),
+        data: EscrowInstruction::RaiseDispute.pack(),
+    }
+}
+
+/// Builds a `ResolveDispute` instruction, signed by the authority
+pub fn resolve_dispute(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    resolution: DisputeResolution,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(RESOLVE_DISPUTE_ACCOUNTS, &[*authority, *escrow]),
+        data: EscrowInstruction::ResolveDispute { resolution }.pack(),
+    }
+}
+
+/// Builds a `SettleSplit` instruction paying each `(token account, amount)` of `recipients`,
+/// failing for more than `MAX_SPLIT` of them, which the one byte count can't always carry
+pub fn settle_split(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    fee_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+    fee_payer: &Pubkey,
+    recipients: &[(Pubkey, u64)],
+) -> Result<Instruction, ProgramError> {
+    if recipients.len() > MAX_SPLIT {
+        return Err(BatchTooLarge.into());
+    }
+    let mut accounts = account_metas(
+        SETTLE_SPLIT_ACCOUNTS,
+        &[
+            *authority,
+            *fee_token,
+            *vault_token,
+            *escrow,
+            *fee_payer,
+            spl_token::id(),
+            find_program_authority(program_id).0,
+        ],
+    );
+    accounts.extend(
+        recipients
+            .iter()
+            .map(|(token, _)| AccountMeta::new(*token, false)),
+    );
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::SettleSplit {
+            recipients: recipients.to_vec(),
+        }
+        .pack(),
+    })
+}
+
+/// Builds a `ClaimFee` instruction, signed by the authority
+pub fn claim_fee(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    fee_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: account_metas(
+            CLAIM_FEE_ACCOUNTS,
+            &[
+                *authority,
+                *fee_token,
+                *vault_token,
+                *escrow,
+                spl_token::id(),
+                find_program_authority(program_id).0,
+            ],
+        ),
+        data: EscrowInstruction::ClaimFee.pack(),
+    }
 }
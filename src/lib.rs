@@ -1,4 +1,5 @@
 pub mod error;
+pub mod event;
 pub mod instruction;
 pub mod processor;
 pub mod state;
@@ -7,20 +8,109 @@ pub mod utils;
 
 pub const PREFIX: &str = "escrow";
 
+/// Maximum number of escrows a single batch instruction may process
+pub const MAX_BATCH: usize = 10;
+
+/// Maximum number of recipients a `SettleSplit` pays
+pub const MAX_SPLIT: usize = 8;
+
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 
 use solana_program::{
-    declare_id, entrypoint::ProgramResult, pubkey::Pubkey,
+    declare_id, entrypoint::ProgramResult, program_pack::Pack, pubkey::Pubkey, rent::Rent,
 };
+use spl_token::state::Account as TokenAccount;
 
 declare_id!("escJ4uwy5ndByWNK2UpmHptAYCQahfKLXBbRVBR17fX");
 
+/// The maintenance key allowed to force reclaim program owned accounts
+pub mod admin {
+    #[cfg(not(feature = "test-admin"))]
+    solana_program::declare_id!("7ZBNFkswTVUCgmpdEeREENuw8na5BpkyxpGUxbdAyy5v");
+    // Integration tests sign with a known key, see `admin_keypair` in tests/utils
+    #[cfg(feature = "test-admin")]
+    solana_program::declare_id!("BsfPbKrLoQFCKE7guVg1SNHENxGsDzmyBKpJwAaP6h99");
+}
+
+#[cfg(all(feature = "test-admin", target_arch = "bpf"))]
+compile_error!("`test-admin` swaps in a publicly known admin key, it can't be built for BPF");
+
 /// Generates program authority
+///
+/// The authority is only ever used as the signing owner of escrow vaults in token program CPIs.
+/// Callers validate the passed account by key alone: the runtime grants the signature only for
+/// the key derived from these seeds and the token program only compares that key against the
+/// vault owner, so the owner and data of the account at that address are never read.
 pub fn find_program_authority(program_id: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(&[PREFIX.as_bytes(), program_id.as_ref()], program_id)
 }
 
+/// Generates the address of the vault owned by an escrow
+pub fn find_vault_address(program_id: &Pubkey, escrow: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PREFIX.as_bytes(), escrow.as_ref()], program_id)
+}
+
+/// Generates the address of the escrow a payer opens over a vault, unique per payer and vault
+pub fn find_escrow_address(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    vault_token: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PREFIX.as_bytes(), payer.as_ref(), vault_token.as_ref()],
+        program_id,
+    )
+}
+
+/// Program derived addresses related to an escrow. The program has no receipt, metadata or fee
+/// vault accounts, so the authority and the vault are all there is to derive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EscrowPdas {
+    /// The program authority, see `find_program_authority`
+    pub authority: Pubkey,
+    /// The escrow's vault, see `find_vault_address`
+    pub vault: Pubkey,
+}
+
+/// Derives every program address related to `escrow`
+pub fn derive_all(program_id: &Pubkey, escrow: &Pubkey) -> EscrowPdas {
+    EscrowPdas {
+        authority: find_program_authority(program_id).0,
+        vault: find_vault_address(program_id, escrow).0,
+    }
+}
+
+/// Accounts beyond the escrow and its vault a client funds over an escrow's lifecycle
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LifecycleOpts {
+    /// Wrapped SOL escrow, its payee and fee recipients are plain wallets
+    pub native: bool,
+    /// The payee account doesn't exist yet and must be created before settlement
+    pub create_payee: bool,
+    /// The fee recipient doesn't exist yet and must be created before settlement
+    pub create_fee_recipient: bool,
+    /// The payee's points token account for `SettleWithMint` doesn't exist yet
+    pub create_points_token: bool,
+}
+
+/// Total rent exempt minimum of the accounts an escrow lifecycle requires: the escrow
+/// account, the vault and whichever accounts `opts` marks as created
+pub fn estimate_lifecycle_rent(rent: &Rent, opts: LifecycleOpts) -> u64 {
+    let recipient_len = if opts.native { 0 } else { TokenAccount::LEN };
+    let mut total = rent.minimum_balance(state::Escrow::LEN) + rent.minimum_balance(TokenAccount::LEN);
+    if opts.create_payee {
+        total += rent.minimum_balance(recipient_len);
+    }
+    if opts.create_fee_recipient {
+        total += rent.minimum_balance(recipient_len);
+    }
+    if opts.create_points_token {
+        total += rent.minimum_balance(TokenAccount::LEN);
+    }
+    total
+}
+
 /// Checks that the supplied authority ID is the correct one for SPL-token
 pub fn check_authority_account(escrow_authority_id: &Pubkey) -> ProgramResult {
     if escrow_authority_id != &id() {
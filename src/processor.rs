@@ -1,69 +1,348 @@
 use crate::{
     error::EscrowError::{
-        AccountAlreadyCanceled, AccountAlreadySettled, AccountNotSettledOrCanceled, AmountOverflow,
-        ExpectedAmountMismatch, FeeOverflow,
+        self, AccountAlreadyCanceled, AccountAlreadySettled, AccountNotSettledOrCanceled,
+        AmountExceedsVault, AmountOverflow, BatchTooLarge, ExpectedAmountMismatch, FeeOverflow, InvalidAuthorityId,
+        FeeTransferFailed, InvalidPointsAccount, OutsideSettlementWindow, PayeeTransferFailed, PointsNotConfigured,
+        RefundFailed, VaultCloseFailed, ZeroAmount, CounterpartyNotConfigured, CounterpartyNotDeposited,
+        CounterpartyAlreadyDeposited, NoExpiry, NotExpired, InvalidVaultOwner,
+        NativePartialSettlement, EscrowDisputed, NotDisputed, DisputeResolvedOtherwise,
+        NativeSplitSettlement, InvalidAuthority, NativeFeeClaim,
+        DuplicateTokenAccount, NativePartialCancel, AccountNotInitialized, UnwrapNonNative,
     },
+    admin,
+    event::{EscrowEvent, EventKind},
+    MAX_BATCH,
+    MAX_SPLIT,
     PREFIX,
-    find_program_authority,
-    instruction::EscrowInstruction,
+    find_escrow_address, find_program_authority, find_vault_address,
+    instruction::{
+        DisputeResolution, EscrowAction, EscrowInstruction, InitOptions, SETTLE_MANY_GROUP,
+    },
     state::Escrow,
-    utils::{assert_account_key, assert_owned_by, assert_rent_exempt, assert_signer, assert_initialized},
+    utils::{
+        assert_account_key, assert_authority, assert_condition_met, assert_dual_sig, assert_derived_vault, assert_escrow_open, assert_fee_recipient, assert_fee_token_open, assert_token_program, assert_vault_balance, assert_vault_drained, assert_fresh_vault, assert_initialized, assert_mint_matches,
+        assert_native_recipient, assert_token_account,
+        assert_owned_by, assert_owned_by_token_program, assert_rent_exempt, assert_signer,
+        assert_supported_token_program, assert_valid_authority, assert_vault_mint_unchanged,
+        log_escrow_error, token_instruction, map_cpi_error, safe_total, unpack_token_account,
+        validate_init_params,
+    },
 };
 
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar},
+    system_instruction, system_program,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 use spl_token::state::Account as TokenAccount;
 
 pub struct Processor;
 impl Processor {
+    /// Decodes instruction data the way `process` does, for inspection before dispatch
+    pub fn decode(instruction_data: &[u8]) -> Result<EscrowInstruction, ProgramError> {
+        EscrowInstruction::unpack(instruction_data)
+    }
+
     pub fn process(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
-        let instruction = EscrowInstruction::unpack(instruction_data)?;
+        let instruction = Self::decode(instruction_data)?;
+        let escrow_info = instruction
+            .escrow_index()
+            .and_then(|index| accounts.get(index));
+        let escrow_key = escrow_info.map(|account| account.key);
+        if let Some(escrow_info) = escrow_info {
+            // `Close` reports an escrow that is already closed itself, see `close_escrow_account`
+            if !matches!(
+                instruction,
+                EscrowInstruction::InitEscrow { .. } | EscrowInstruction::Close
+            ) {
+                log_escrow_error(escrow_key, assert_escrow_open(escrow_info))?;
+            }
+        }
 
-        match instruction {
-            EscrowInstruction::InitEscrow { amount, fee } => {
+        let result = match instruction {
+            EscrowInstruction::InitEscrow {
+                amount,
+                fee,
+                options,
+            } => {
                 msg!("Instruction: InitEscrow");
-                Self::process_init_escrow(accounts, amount, fee, program_id)
+                Self::process_init_escrow(accounts, amount, fee, &options, program_id)
             }
-            EscrowInstruction::Settle => {
+            EscrowInstruction::Settle { memo } => {
                 msg!("Instruction: Settle");
-                Self::process_settlement(accounts, program_id)
+                Self::process_settlement(accounts, false, memo, program_id)
             }
             EscrowInstruction::Cancel => {
                 msg!("Instruction: Cancel");
-                Self::process_cancel(accounts, program_id)
+                Self::process_cancel(accounts, false, false, program_id)
             }
             EscrowInstruction::Close => {
                 msg!("Instruction: Close");
                 Self::process_close(accounts, program_id)
             }
-        }
+            EscrowInstruction::CloseManyToTreasury => {
+                msg!("Instruction: CloseManyToTreasury");
+                Self::process_close_many_to_treasury(accounts, program_id)
+            }
+            EscrowInstruction::ForceReclaim => {
+                msg!("Instruction: ForceReclaim");
+                Self::process_force_reclaim(accounts, program_id)
+            }
+            EscrowInstruction::UpdateFeeToken => {
+                msg!("Instruction: UpdateFeeToken");
+                Self::process_update_fee_token(accounts, program_id)
+            }
+            EscrowInstruction::SettleWithMint => {
+                msg!("Instruction: SettleWithMint");
+                Self::process_settlement(accounts, true, [0; 32], program_id)
+            }
+            EscrowInstruction::SettleMany => {
+                msg!("Instruction: SettleMany");
+                Self::process_settle_many(accounts, program_id)
+            }
+            EscrowInstruction::CheckAuthorization { action, key } => {
+                msg!("Instruction: CheckAuthorization");
+                Self::process_check_authorization(accounts, action, &key, program_id)
+            }
+            EscrowInstruction::LogEscrow => {
+                msg!("Instruction: LogEscrow");
+                Self::process_log_escrow(accounts, program_id)
+            }
+            EscrowInstruction::DepositCounterparty => {
+                msg!("Instruction: DepositCounterparty");
+                Self::process_deposit_counterparty(accounts, program_id)
+            }
+            EscrowInstruction::Reclaim => {
+                msg!("Instruction: Reclaim");
+                Self::process_cancel(accounts, true, false, program_id)
+            }
+            EscrowInstruction::ChangeAuthority => {
+                msg!("Instruction: ChangeAuthority");
+                Self::process_change_authority(accounts, program_id)
+            }
+            EscrowInstruction::TopUp { amount } => {
+                msg!("Instruction: TopUp");
+                Self::process_top_up(accounts, amount, program_id)
+            }
+            EscrowInstruction::SettleSplit { recipients } => {
+                msg!("Instruction: SettleSplit");
+                Self::process_settle_split(accounts, &recipients, program_id)
+            }
+            EscrowInstruction::ClaimFee => {
+                msg!("Instruction: ClaimFee");
+                Self::process_claim_fee(accounts, program_id)
+            }
+            EscrowInstruction::RaiseDispute => {
+                msg!("Instruction: RaiseDispute");
+                Self::process_raise_dispute(accounts, program_id)
+            }
+            EscrowInstruction::ResolveDispute { resolution } => {
+                msg!("Instruction: ResolveDispute");
+                Self::process_resolve_dispute(accounts, resolution, program_id)
+            }
+            EscrowInstruction::SettlePartial { amount } => {
+                msg!("Instruction: SettlePartial");
+                Self::process_settle_partial(accounts, amount, program_id)
+            }
+            EscrowInstruction::SettleAndClose => {
+                msg!("Instruction: SettleAndClose");
+                Self::process_settle_and_close(accounts, program_id)
+            }
+            EscrowInstruction::CancelUnwrap => {
+                msg!("Instruction: CancelUnwrap");
+                Self::process_cancel(accounts, false, true, program_id)
+            }
+            EscrowInstruction::CancelPartial { amount } => {
+                msg!("Instruction: CancelPartial");
+                Self::process_cancel_partial(accounts, amount, program_id)
+            }
+        };
+        log_escrow_error(escrow_key, result)
     }
 
     fn process_init_escrow(
         accounts: &[AccountInfo],
         amount: u64,
         fee: u64,
+        options: &InitOptions,
         program_id: &Pubkey,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let payer_info = next_account_info(account_info_iter)?;
         assert_signer(payer_info)?;
         let vault_token_info = next_account_info(account_info_iter)?;
-        assert_owned_by(vault_token_info, &spl_token::id())?;
-        let vault_token =
-            TokenAccount::unpack(&vault_token_info.data.borrow())?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let escrow_info = next_account_info(account_info_iter)?;
+        let payer_token_info = next_account_info(account_info_iter)?;
+        let payee_token_info = next_account_info(account_info_iter)?;
+        let fee_token_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let rent_info = &Rent::from_account_info(rent_sysvar_info)?;
+
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_supported_token_program(token_program_info)?;
+
+        let (pda, _bump_seed) = find_program_authority(program_id);
+
+        // Checked before the vault so a zero amount isn't reported as a balance mismatch
+        validate_init_params(
+            amount,
+            fee,
+            vault_token_info.key,
+            payer_token_info.key,
+            payee_token_info.key,
+            fee_token_info.key,
+        )?;
+        if options.derived_escrow && options.derived_vault {
+            msg!("An escrow derived from its vault can't own a vault derived from the escrow");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if options.dynamic_fee_recipient && options.program_controlled {
+            msg!("A program controlled escrow can't take its fee recipient at settlement");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let top_up_accounts = if options.top_up_rent {
+            let funder_info = next_account_info(account_info_iter)?;
+            assert_signer(funder_info)?;
+            let system_program_info = next_account_info(account_info_iter)?;
+            assert_account_key(system_program_info, &system_program::id())?;
+            Some((funder_info, system_program_info))
+        } else {
+            None
+        };
+
+        if options.derived_vault {
+            let mint_info = next_account_info(account_info_iter)?;
+            let system_program_info = next_account_info(account_info_iter)?;
+            assert_account_key(system_program_info, &system_program::id())?;
+            let (vault_address, vault_bump_seed) =
+                find_vault_address(program_id, escrow_info.key);
+            assert_account_key(vault_token_info, &vault_address)?;
+            // Wrapped SOL vaults are funded by the lamports they are created with
+            let is_native = *mint_info.key == spl_token::native_mint::id();
+            let mut lamports = rent_info.minimum_balance(TokenAccount::LEN);
+            if is_native {
+                lamports = lamports.checked_add(amount).ok_or(AmountOverflow)?;
+            }
+            msg!("Creating the derived vault...");
+            invoke_signed(
+                &system_instruction::create_account(
+                    payer_info.key,
+                    vault_token_info.key,
+                    lamports,
+                    TokenAccount::LEN as u64,
+                    token_program_info.key,
+                ),
+                &[
+                    payer_info.clone(),
+                    vault_token_info.clone(),
+                    system_program_info.clone(),
+                ],
+                &[&[PREFIX.as_bytes(), escrow_info.key.as_ref(), &[vault_bump_seed]]],
+            )?;
+            invoke(
+                &token_instruction(
+                    token_program_info.key,
+                    spl_token::instruction::initialize_account2(
+                        &spl_token::id(),
+                        vault_token_info.key,
+                        mint_info.key,
+                        &pda,
+                    ),
+                )?,
+                &[
+                    vault_token_info.clone(),
+                    mint_info.clone(),
+                    rent_sysvar_info.clone(),
+                    token_program_info.clone(),
+                ],
+            )?;
+            if !is_native {
+                msg!("Calling the token program to fund the derived vault...");
+                invoke(
+                    &token_instruction(
+                        token_program_info.key,
+                        spl_token::instruction::transfer(
+                            &spl_token::id(),
+                            payer_token_info.key,
+                            vault_token_info.key,
+                            payer_info.key,
+                            &[],
+                            amount,
+                        ),
+                    )?,
+                    &[
+                        payer_token_info.clone(),
+                        vault_token_info.clone(),
+                        payer_info.clone(),
+                        token_program_info.clone(),
+                    ],
+                )?;
+            }
+        }
+
+        let escrow_bump = if options.derived_escrow {
+            let system_program_info = next_account_info(account_info_iter)?;
+            assert_account_key(system_program_info, &system_program::id())?;
+            let (escrow_address, escrow_bump) =
+                find_escrow_address(program_id, payer_info.key, vault_token_info.key);
+            assert_account_key(escrow_info, &escrow_address)?;
+            msg!("Creating the derived escrow...");
+            invoke_signed(
+                &system_instruction::create_account(
+                    payer_info.key,
+                    escrow_info.key,
+                    rent_info.minimum_balance(Escrow::LEN),
+                    Escrow::LEN as u64,
+                    program_id,
+                ),
+                &[
+                    payer_info.clone(),
+                    escrow_info.clone(),
+                    system_program_info.clone(),
+                ],
+                &[&[
+                    PREFIX.as_bytes(),
+                    payer_info.key.as_ref(),
+                    vault_token_info.key.as_ref(),
+                    &[escrow_bump],
+                ]],
+            )?;
+            escrow_bump
+        } else {
+            0
+        };
+
+        assert_owned_by(vault_token_info, token_program_info.key)?;
+        let vault_token = unpack_token_account(vault_token_info)?;
+        // A derived vault is owned by the program authority from the start, any other vault is
+        // handed over by the payer below
+        let vault_owner = if options.derived_vault { &pda } else { payer_info.key };
+        if vault_token.owner != *vault_owner {
+            msg!("Vault is owned by {}, expected {}", vault_token.owner, vault_owner);
+            return Err(InvalidVaultOwner.into());
+        }
+        // Settle and cancel must never move more than the vault holds
+        if amount > vault_token.amount {
+            msg!(
+                "Amount exceeds the vault balance..., got: {} , vault holds {}",
+                amount,
+                vault_token.amount
+            );
+            return Err(AmountExceedsVault.into());
+        }
         if vault_token.amount != amount {
             msg!(
                 "Got Mismatched amount..., got: {} , expected {}",
@@ -72,26 +351,46 @@ impl Processor {
             );
             return Err(ExpectedAmountMismatch.into());
         }
+        if options.require_fresh_vault {
+            assert_fresh_vault(&vault_token, vault_owner, amount)?;
+        }
 
-        let authority_info = next_account_info(account_info_iter)?;
-        assert_signer(authority_info)?;
+        if options.program_controlled {
+            assert_account_key(authority_info, &pda)?;
+        } else {
+            assert_valid_authority(authority_info.key, program_id)?;
+            assert_signer(authority_info)?;
+        }
 
-        let escrow_info = next_account_info(account_info_iter)?;
-        let payer_token_info = next_account_info(account_info_iter)?;
-        let payee_token_info = next_account_info(account_info_iter)?;
-        let fee_token_info = next_account_info(account_info_iter)?;
-        if vault_token.is_native() {
+        let fee_recipient = if vault_token.is_native() {
             assert_account_key(payer_token_info, payer_info.key)?;
+            *fee_token_info.key
         } else {
-            assert_owned_by(payer_token_info, &spl_token::id())?;
-            assert_owned_by(payee_token_info, &spl_token::id())?;
-            assert_owned_by(fee_token_info, &spl_token::id())?;
-            let _: TokenAccount = assert_initialized(payer_token_info)?;
-            let _: TokenAccount = assert_initialized(payee_token_info)?;
-            let _: TokenAccount = assert_initialized(fee_token_info)?;
-        }
+            // Settlement and refunds transfer the vault's tokens to these accounts
+            let token_program = token_program_info.key;
+            let mint = Some(&vault_token.mint);
+            assert_token_account(payer_token_info, token_program, mint, None)?;
+            assert_token_account(payee_token_info, token_program, mint, None)?;
+            assert_token_account(fee_token_info, token_program, mint, None)?.owner
+        };
+        assert_fee_recipient(fee_token_info, &fee_recipient, &pda)?;
 
-        let rent_info = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+        if let Some((funder_info, system_program_info)) = top_up_accounts {
+            let shortfall = rent_info
+                .minimum_balance(escrow_info.data_len())
+                .saturating_sub(escrow_info.lamports());
+            if shortfall > 0 {
+                msg!("Topping up the escrow account rent by {} lamports...", shortfall);
+                invoke(
+                    &system_instruction::transfer(funder_info.key, escrow_info.key, shortfall),
+                    &[
+                        funder_info.clone(),
+                        escrow_info.clone(),
+                        system_program_info.clone(),
+                    ],
+                )?;
+            }
+        }
 
         assert_rent_exempt(rent_info, escrow_info)?;
 
@@ -99,9 +398,23 @@ impl Processor {
         if escrow.is_initialized() {
             return Err(ProgramError::AccountAlreadyInitialized);
         }
-        if fee > amount {
+        if options.restock_fee > amount {
             return Err(FeeOverflow.into());
         }
+        if options.counterparty_mint != Pubkey::default() && options.counterparty_amount == 0 {
+            return Err(ZeroAmount.into());
+        }
+        if options.fee_bps > 10_000 {
+            msg!("Fee of {} bps exceeds the amount", options.fee_bps);
+            return Err(FeeOverflow.into());
+        }
+        if options.settle_not_before != 0
+            && options.settle_not_after != 0
+            && options.settle_not_before > options.settle_not_after
+        {
+            msg!("Settlement window ends before it starts");
+            return Err(ProgramError::InvalidArgument);
+        }
         escrow.is_initialized = true;
         escrow.is_settled = false;
         escrow.is_canceled = false;
@@ -113,20 +426,51 @@ impl Processor {
         escrow.fee_token = *fee_token_info.key;
         escrow.authority = *authority_info.key;
         escrow.amount = amount;
+        escrow.callback_program = options.callback_program;
+        escrow.callback_optional = options.callback_optional;
+        escrow.mint = vault_token.mint;
+        escrow.max_abs_fee = options.max_abs_fee;
+        escrow.points_mint = options.points_mint;
+        escrow.points_amount = options.points_amount;
+        escrow.require_payer_on_cancel = options.require_payer_on_cancel;
+        escrow.restock_fee = options.restock_fee;
+        escrow.restock_token = options.restock_token;
+        escrow.derived_vault = options.derived_vault;
+        escrow.settle_not_before = options.settle_not_before;
+        escrow.settle_not_after = options.settle_not_after;
+        escrow.condition_account = options.condition_account;
+        escrow.condition_owner = options.condition_owner;
+        escrow.fee_bps = options.fee_bps;
+        escrow.token_program = *token_program_info.key;
+        escrow.version = Escrow::current_version();
+        escrow.authority_bump = find_program_authority(program_id).1;
+        escrow.expires_at = options.expires_at;
+        escrow.allow_balance_drift = options.allow_balance_drift;
+        escrow.derived_escrow = options.derived_escrow;
+        escrow.require_dual_sig = options.require_dual_sig;
+        escrow.dynamic_fee_recipient = options.dynamic_fee_recipient;
+        escrow.escrow_bump = escrow_bump;
+        escrow.counterparty_mint = options.counterparty_mint;
+        escrow.counterparty_amount = options.counterparty_amount;
+        escrow.counterparty_token = options.counterparty_token;
+        escrow.checksum = escrow.compute_checksum();
 
         Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
 
-        let (pda, _bump_seed) = find_program_authority(program_id);
+        if options.derived_vault {
+            return Ok(());
+        }
 
-        let token_program_info = next_account_info(account_info_iter)?;
-        assert_account_key(token_program_info, &spl_token::id())?;
-        let owner_change_ix = spl_token::instruction::set_authority(
+        let owner_change_ix = token_instruction(
             token_program_info.key,
-            vault_token_info.key,
-            Some(&pda),
-            spl_token::instruction::AuthorityType::AccountOwner,
-            payer_info.key,
-            &[&payer_info.key],
+            spl_token::instruction::set_authority(
+                &spl_token::id(),
+                vault_token_info.key,
+                Some(&pda),
+                spl_token::instruction::AuthorityType::AccountOwner,
+                payer_info.key,
+                &[payer_info.key],
+            ),
         )?;
 
         msg!("Calling the token program to transfer token account ownership...");
@@ -144,92 +488,172 @@ impl Processor {
     //inside: impl Processor {}
     fn process_settlement(
         accounts: &[AccountInfo],
+        with_points: bool,
+        memo: [u8; 32],
         program_id: &Pubkey,
     ) -> ProgramResult {
         msg!("Process settlement with fee");
         let account_info_iter = &mut accounts.iter();
         let authority_info = next_account_info(account_info_iter)?;
 
-        assert_signer(authority_info)?;
-
         let payee_token_info = next_account_info(account_info_iter)?;
         let fee_token_info = next_account_info(account_info_iter)?;
-
         let vault_token_info = next_account_info(account_info_iter)?;
-        assert_owned_by(vault_token_info, &spl_token::id())?;
-
-        let vault_token =
-            TokenAccount::unpack(&vault_token_info.data.borrow())?;
 
         let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
         let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
-
-        if escrow.is_canceled() {
-            return Err(AccountAlreadyCanceled.into());
-        }
-        if escrow.is_settled() {
-            return Err(AccountAlreadySettled.into());
+        escrow.verify_checksum()?;
+        // The program authority signs through its seeds, see `InitOptions::program_controlled`
+        if !escrow.is_program_controlled(program_id) {
+            assert_signer(authority_info)?;
         }
+        Self::authorize(&escrow, EscrowAction::Settle, authority_info.key)?;
+        assert_dual_sig(&escrow, accounts)?;
+
+        // Read after `authorize`, the vault of a resolved escrow is closed
+        assert_owned_by_token_program(vault_token_info)?;
+        let vault_token = unpack_token_account(vault_token_info)?;
+        assert_vault_balance(&escrow, &vault_token)?;
+
+        let (amount, fee) = escrow.settlement_breakdown(vault_token.amount)?;
 
-        assert_account_key(authority_info, &escrow.authority)?;
         assert_account_key(payee_token_info, &escrow.payee_token)?;
-        assert_account_key(fee_token_info, &escrow.fee_token)?;
+        if fee > 0 {
+            // The authority signed for whichever recipient it passes, see
+            // `InitOptions::dynamic_fee_recipient`
+            if !escrow.dynamic_fee_recipient {
+                assert_account_key(fee_token_info, &escrow.fee_token)?;
+            }
+            // Wrapped SOL fees go to a wallet, not a token account
+            if vault_token.is_native() {
+                assert_native_recipient(fee_token_info)?;
+            } else {
+                assert_fee_token_open(fee_token_info, &escrow.token_program)?;
+                if escrow.dynamic_fee_recipient {
+                    let mint = Some(&vault_token.mint);
+                    assert_token_account(fee_token_info, &escrow.token_program, mint, None)?;
+                }
+            }
+        }
+        if vault_token.is_native() {
+            assert_native_recipient(payee_token_info)?;
+        }
         assert_account_key(vault_token_info, &escrow.vault_token)?;
+        if escrow.derived_vault {
+            assert_derived_vault(vault_token_info, escrow_info.key, program_id)?;
+        }
+        assert_vault_mint_unchanged(&vault_token, &escrow.mint)?;
 
         let fee_payer_info = next_account_info(account_info_iter)?;
-        
-        let token_program_info = next_account_info(account_info_iter)?;
-        assert_account_key(token_program_info, &spl_token::id())?;
-
-        let (vault, bump_seed) = find_program_authority(program_id);
+        // Anyone may submit a program controlled settlement, the rent it frees returns to the
+        // payer rather than to the submitter. `SettleAndClose` closes the escrow into the same
+        // account.
+        if escrow.is_program_controlled(program_id) {
+            assert_account_key(fee_payer_info, &escrow.payer)?;
+        }
 
-        let vault_info = next_account_info(account_info_iter)?;
-        assert_account_key(vault_info, &vault)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+        assert_owned_by(vault_token_info, &escrow.token_program)?;
 
+        let bump_seed = escrow.authority_bump;
         let vault_signer_seeds = [
             PREFIX.as_bytes(),
             program_id.as_ref(),
             &[bump_seed],
         ];
+        let vault = Pubkey::create_program_address(&vault_signer_seeds, program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)?;
 
-        let fee = escrow.fee;
+        let vault_info = next_account_info(account_info_iter)?;
+        // Key check only, see `find_program_authority`
+        assert_account_key(vault_info, &vault)?;
 
-        if fee > vault_token.amount {
-            msg!(
-                "Fee too high..., {} should be less than or equal to {}",
-                fee,
-                vault_token.amount
-            );
-            return Err(FeeOverflow.into());
+        let points_infos = if with_points {
+            if escrow.points_mint == Pubkey::default() {
+                return Err(PointsNotConfigured.into());
+            }
+            let points_mint_info = next_account_info(account_info_iter)?;
+            assert_account_key(points_mint_info, &escrow.points_mint)?;
+            let points_token_info = next_account_info(account_info_iter)?;
+            let points_mint = Some(&escrow.points_mint);
+            let points_token =
+                assert_token_account(points_token_info, &escrow.token_program, points_mint, None)?;
+            let payee = if vault_token.is_native() {
+                *payee_token_info.key
+            } else {
+                unpack_token_account(payee_token_info)?.owner
+            };
+            if points_token.owner != payee {
+                return Err(InvalidPointsAccount.into());
+            }
+            Some((points_mint_info, points_token_info))
+        } else {
+            None
+        };
+
+        if escrow.condition_account != Pubkey::default() {
+            let condition_info = next_account_info(account_info_iter)?;
+            assert_condition_met(condition_info, &escrow.condition_account, &escrow.condition_owner)?;
         }
 
-        let amount = vault_token
-            .amount
-            .checked_sub(fee)
-            .ok_or(AmountOverflow)?;
+        let counterparty_infos = if escrow.counterparty_mint != Pubkey::default() {
+            let counterparty_vault_info = next_account_info(account_info_iter)?;
+            assert_account_key(counterparty_vault_info, &escrow.counterparty_vault)?;
+            let counterparty_token_info = next_account_info(account_info_iter)?;
+            assert_account_key(counterparty_token_info, &escrow.counterparty_token)?;
+            Some((counterparty_vault_info, counterparty_token_info))
+        } else {
+            None
+        };
 
         if vault_token.is_native() {
-            let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
+            let vault_lamports = vault_token_info.lamports();
+            // The closed vault's lamports are its rent, the settled amount and fee, and any
+            // surplus sent to it without a sync since. As on cancel, the vault's own rent goes to
+            // the fee payer and the surplus back to the payer.
+            let vault_rent = Rent::get()?.minimum_balance(vault_token_info.data_len());
+            let held = vault_lamports.checked_sub(vault_rent).ok_or(AmountOverflow)?;
+            let surplus = held
+                .checked_sub(safe_total(amount, fee)?)
+                .ok_or(AmountOverflow)?;
+            let payer_info = if surplus > 0 {
+                msg!("Returning a vault surplus of {} lamports to the payer", surplus);
+                let payer_info = accounts.iter().find(|account| *account.key == escrow.payer);
+                Some(payer_info.ok_or(ProgramError::NotEnoughAccountKeys)?)
+            } else {
+                None
+            };
+            let close_pdas_temp_acc_ix = token_instruction(
                 token_program_info.key,
-                vault_token_info.key,
-                escrow_info.key,
-                &vault,
-                &[&vault],
+                spl_token::instruction::close_account(
+                    &spl_token::id(),
+                    vault_token_info.key,
+                    escrow_info.key,
+                    &vault,
+                    &[&vault],
+                ),
             )?;
             msg!("Calling the token program to close pda's temp account...and add the remaining lamports to the escrow account");
-            invoke_signed(
-                &close_pdas_temp_acc_ix,
-                &[
-                    vault_token_info.clone(),
-                    escrow_info.clone(),
-                    vault_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[&vault_signer_seeds],
+            map_cpi_error(
+                invoke_signed(
+                    &close_pdas_temp_acc_ix,
+                    &[
+                        vault_token_info.clone(),
+                        escrow_info.clone(),
+                        vault_info.clone(),
+                        token_program_info.clone(),
+                    ],
+                    &[&vault_signer_seeds],
+                ),
+                VaultCloseFailed,
             )?;
+            // All of the closed vault's lamports move on, leaving the escrow account with only
+            // its own rent
             let source_starting_lamports = escrow_info.lamports();
             **escrow_info.lamports.borrow_mut() = source_starting_lamports
-                .checked_sub(amount)
+                .checked_sub(vault_lamports)
                 .ok_or(AmountOverflow)?;
 
             let dest_starting_lamports = payee_token_info.lamports();
@@ -237,71 +661,147 @@ impl Processor {
                 .checked_add(amount)
                 .ok_or(AmountOverflow)?;
             if fee > 0 {
-                let source_starting_lamports = escrow_info.lamports();
-                **escrow_info.lamports.borrow_mut() = source_starting_lamports
-                    .checked_sub(fee)
-                    .ok_or(AmountOverflow)?;
-
                 let dest_starting_lamports = fee_token_info.lamports();
                 **fee_token_info.lamports.borrow_mut() = dest_starting_lamports
                     .checked_add(fee)
                     .ok_or(AmountOverflow)?;
             }
+            let dest_starting_lamports = fee_payer_info.lamports();
+            **fee_payer_info.lamports.borrow_mut() = dest_starting_lamports
+                .checked_add(vault_rent)
+                .ok_or(AmountOverflow)?;
+            if let Some(payer_info) = payer_info {
+                let dest_starting_lamports = payer_info.lamports();
+                **payer_info.lamports.borrow_mut() = dest_starting_lamports
+                    .checked_add(surplus)
+                    .ok_or(AmountOverflow)?;
+            }
+            // The escrow stays a live program account until `Close`
+            assert_rent_exempt(&Rent::get()?, escrow_info)?;
         } else {
-            let transfer_to_taker_ix = spl_token::instruction::transfer(
-                token_program_info.key,
-                vault_token_info.key,
-                payee_token_info.key,
-                &vault,
-                &[&vault],
-                amount,
-            )?;
-            msg!("Calling the token program to transfer tokens to the taker...");
-            invoke_signed(
-                &transfer_to_taker_ix,
-                &[
-                    vault_token_info.clone(),
-                    payee_token_info.clone(),
-                    vault_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[&vault_signer_seeds],
-            )?;
+            // A fee taking the whole vault leaves nothing for the payee
+            if amount > 0 {
+                let transfer_to_taker_ix = token_instruction(
+                    token_program_info.key,
+                    spl_token::instruction::transfer(
+                        &spl_token::id(),
+                        vault_token_info.key,
+                        payee_token_info.key,
+                        &vault,
+                        &[&vault],
+                        amount,
+                    ),
+                )?;
+                msg!("Calling the token program to transfer tokens to the taker...");
+                map_cpi_error(
+                    invoke_signed(
+                        &transfer_to_taker_ix,
+                        &[
+                            vault_token_info.clone(),
+                            payee_token_info.clone(),
+                            vault_info.clone(),
+                            token_program_info.clone(),
+                        ],
+                        &[&vault_signer_seeds],
+                    ),
+                    PayeeTransferFailed,
+                )?;
+            }
             if fee > 0 {
-                let transfer_to_fee_taker_ix = spl_token::instruction::transfer(
+                let transfer_to_fee_taker_ix = token_instruction(
                     token_program_info.key,
+                    spl_token::instruction::transfer(
+                        &spl_token::id(),
+                        vault_token_info.key,
+                        fee_token_info.key,
+                        &vault,
+                        &[&vault],
+                        fee,
+                    ),
+                )?;
+                msg!("Calling the token program to transfer tokens to the fee taker...");
+                map_cpi_error(
+                    invoke_signed(
+                        &transfer_to_fee_taker_ix,
+                        &[
+                            vault_token_info.clone(),
+                            fee_token_info.clone(),
+                            vault_info.clone(),
+                            token_program_info.clone(),
+                        ],
+                        &[&vault_signer_seeds],
+                    ),
+                    FeeTransferFailed,
+                )?;
+            }
+
+            let close_pdas_temp_acc_ix = token_instruction(
+                token_program_info.key,
+                spl_token::instruction::close_account(
+                    &spl_token::id(),
                     vault_token_info.key,
-                    fee_token_info.key,
+                    fee_payer_info.key,
                     &vault,
                     &[&vault],
-                    fee,
-                )?;
-                msg!("Calling the token program to transfer tokens to the fee taker...");
+                ),
+            )?;
+            msg!("Calling the token program to close pda's temp account...");
+            map_cpi_error(
                 invoke_signed(
-                    &transfer_to_fee_taker_ix,
+                    &close_pdas_temp_acc_ix,
                     &[
                         vault_token_info.clone(),
-                        fee_token_info.clone(),
+                        fee_payer_info.clone(),
                         vault_info.clone(),
                         token_program_info.clone(),
                     ],
                     &[&vault_signer_seeds],
-                )?;
-            }
+                ),
+                VaultCloseFailed,
+            )?;
+        }
+
+        if let Some((counterparty_vault_info, counterparty_token_info)) = counterparty_infos {
+            msg!("Releasing the counterparty side to the payer...");
+            Self::drain_vault(
+                token_program_info,
+                counterparty_vault_info,
+                counterparty_token_info,
+                fee_payer_info,
+                vault_info,
+                &vault_signer_seeds,
+                // The payer is the payee of the counterparty side
+                PayeeTransferFailed,
+            )?;
+        }
+
+        msg!("Mark the escrow account as settled...");
+        escrow.is_settled = true;
+        escrow.set_resolved_at(escrow_info.data_len(), Clock::get()?.unix_timestamp);
+        escrow.settle_memo = memo;
+        let callback_program = escrow.callback_program;
+        let callback_optional = escrow.callback_optional;
+        let points_amount = escrow.points_amount;
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
 
-            let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
+        if let Some((points_mint_info, points_token_info)) = points_infos {
+            let mint_points_ix = token_instruction(
                 token_program_info.key,
-                vault_token_info.key,
-                fee_payer_info.key,
-                &vault,
-                &[&vault],
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    points_mint_info.key,
+                    points_token_info.key,
+                    &vault,
+                    &[&vault],
+                    points_amount,
+                ),
             )?;
-            msg!("Calling the token program to close pda's temp account...");
+            msg!("Calling the token program to mint points to the payee...");
             invoke_signed(
-                &close_pdas_temp_acc_ix,
+                &mint_points_ix,
                 &[
-                    vault_token_info.clone(),
-                    fee_payer_info.clone(),
+                    points_mint_info.clone(),
+                    points_token_info.clone(),
                     vault_info.clone(),
                     token_program_info.clone(),
                 ],
@@ -309,109 +809,457 @@ impl Processor {
             )?;
         }
 
-        msg!("Mark the escrow account as settled...");
-        escrow.is_settled = true;
-        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+        if callback_program != Pubkey::default() {
+            match next_account_info(account_info_iter) {
+                Ok(callback_program_info) => {
+                    assert_account_key(callback_program_info, &callback_program)?;
+                    Self::invoke_settle_callback(
+                        callback_program_info,
+                        escrow_info,
+                        amount,
+                        fee,
+                    )?;
+                }
+                Err(_) if callback_optional => {
+                    msg!("Callback program not supplied, skipping the callback...");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        EscrowEvent {
+            kind: EventKind::Settled,
+            escrow: *escrow_info.key,
+            recipient: *payee_token_info.key,
+            amount,
+            fee,
+        }
+        .emit();
         Ok(())
     }
 
-    //inside: impl Processor {}
-    fn process_cancel(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
-        msg!("Process cancelation");
+    fn process_settle_partial(
+        accounts: &[AccountInfo],
+        amount: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        msg!("Process partial settlement");
         let account_info_iter = &mut accounts.iter();
         let authority_info = next_account_info(account_info_iter)?;
         assert_signer(authority_info)?;
 
-        let escrow_info = next_account_info(account_info_iter)?;
-        let payer_token_info = next_account_info(account_info_iter)?;
-        let fee_payer_info = next_account_info(account_info_iter)?;
+        let payee_token_info = next_account_info(account_info_iter)?;
+        let fee_token_info = next_account_info(account_info_iter)?;
         let vault_token_info = next_account_info(account_info_iter)?;
-        let vault_token =
-            TokenAccount::unpack(&vault_token_info.data.borrow())?;
 
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
         let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+        Self::authorize(&escrow, EscrowAction::Settle, authority_info.key)?;
+        assert_dual_sig(&escrow, accounts)?;
 
-        if escrow.is_canceled() {
-            return Err(AccountAlreadyCanceled.into());
+        // Read after `authorize`, the vault of a resolved escrow is closed
+        assert_owned_by_token_program(vault_token_info)?;
+        let vault_token = unpack_token_account(vault_token_info)?;
+        assert_vault_balance(&escrow, &vault_token)?;
+
+        // Wrapped SOL is paid out by closing the vault, it can't be released in parts
+        if vault_token.is_native() {
+            return Err(NativePartialSettlement.into());
         }
-        if escrow.is_settled() {
-            return Err(AccountAlreadySettled.into());
+        if amount == 0 {
+            return Err(ZeroAmount.into());
+        }
+        if amount > vault_token.amount {
+            msg!(
+                "Amount exceeds the vault balance..., got: {} , vault holds {}",
+                amount,
+                vault_token.amount
+            );
+            return Err(AmountExceedsVault.into());
         }
+        let fee = escrow.partial_fee(amount);
 
-        assert_account_key(payer_token_info, &escrow.payer_token)?;
-        assert_account_key(authority_info, &escrow.authority)?;
+        assert_account_key(payee_token_info, &escrow.payee_token)?;
+        if fee > 0 {
+            assert_account_key(fee_token_info, &escrow.fee_token)?;
+            assert_fee_token_open(fee_token_info, &escrow.token_program)?;
+        }
         assert_account_key(vault_token_info, &escrow.vault_token)?;
+        if escrow.derived_vault {
+            assert_derived_vault(vault_token_info, escrow_info.key, program_id)?;
+        }
+        assert_vault_mint_unchanged(&vault_token, &escrow.mint)?;
 
         let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+        assert_owned_by(vault_token_info, &escrow.token_program)?;
+
+        let bump_seed = escrow.authority_bump;
+        let vault_signer_seeds = [
+            PREFIX.as_bytes(),
+            program_id.as_ref(),
+            &[bump_seed],
+        ];
+        let vault = Pubkey::create_program_address(&vault_signer_seeds, program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+        let vault_info = next_account_info(account_info_iter)?;
+        // Key check only, see `find_program_authority`
+        assert_account_key(vault_info, &vault)?;
+
+        if escrow.condition_account != Pubkey::default() {
+            let condition_info = next_account_info(account_info_iter)?;
+            assert_condition_met(condition_info, &escrow.condition_account, &escrow.condition_owner)?;
+        }
+
+        let payout = amount.checked_sub(fee).ok_or(AmountOverflow)?;
+        msg!("Calling the token program to release tokens to the taker...");
+        Self::transfer_from_vault(
+            token_program_info,
+            vault_token_info,
+            payee_token_info,
+            vault_info,
+            &vault_signer_seeds,
+            payout,
+            PayeeTransferFailed,
+        )?;
+        if fee > 0 {
+            msg!("Calling the token program to transfer tokens to the fee taker...");
+            Self::transfer_from_vault(
+                token_program_info,
+                vault_token_info,
+                fee_token_info,
+                vault_info,
+                &vault_signer_seeds,
+                fee,
+                FeeTransferFailed,
+            )?;
+        }
+
+        escrow.amount = escrow.amount.saturating_sub(amount);
+        escrow.checksum = escrow.compute_checksum();
+        msg!("released={} remaining={}", amount, escrow.amount);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+
+        EscrowEvent {
+            kind: EventKind::PartiallySettled,
+            escrow: *escrow_info.key,
+            recipient: *payee_token_info.key,
+            amount: payout,
+            fee,
+        }
+        .emit();
+        Ok(())
+    }
+
+    fn process_cancel_partial(
+        accounts: &[AccountInfo],
+        amount: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        msg!("Process partial cancellation");
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
 
-        let (vault_key, bump_seed) = find_program_authority(program_id);
+        let payer_token_info = next_account_info(account_info_iter)?;
+        let vault_token_info = next_account_info(account_info_iter)?;
+
+        // The escrow is checked first, the vault of a canceled escrow is already closed
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+        Self::authorize(&escrow, EscrowAction::Cancel, authority_info.key)?;
+
+        assert_owned_by_token_program(vault_token_info)?;
+        let vault_token = unpack_token_account(vault_token_info)?;
+        assert_vault_balance(&escrow, &vault_token)?;
+
+        // Wrapped SOL is refunded by closing the vault, it can't be refunded in parts
+        if vault_token.is_native() {
+            return Err(NativePartialCancel.into());
+        }
+        if amount == 0 {
+            return Err(ZeroAmount.into());
+        }
+        if amount > vault_token.amount {
+            msg!(
+                "Amount exceeds the vault balance..., got: {} , vault holds {}",
+                amount,
+                vault_token.amount
+            );
+            return Err(AmountExceedsVault.into());
+        }
 
+        assert_account_key(payer_token_info, &escrow.payer_token)?;
+        assert_account_key(vault_token_info, &escrow.vault_token)?;
+        if escrow.derived_vault {
+            assert_derived_vault(vault_token_info, escrow_info.key, program_id)?;
+        }
+        assert_vault_mint_unchanged(&vault_token, &escrow.mint)?;
+
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+        assert_owned_by(vault_token_info, &escrow.token_program)?;
+
+        let bump_seed = escrow.authority_bump;
+        let vault_signer_seeds = [
+            PREFIX.as_bytes(),
+            program_id.as_ref(),
+            &[bump_seed],
+        ];
+        let vault = Pubkey::create_program_address(&vault_signer_seeds, program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)?;
         let vault_info = next_account_info(account_info_iter)?;
-        assert_account_key(vault_info, &vault_key)?;
+        // Key check only, see `find_program_authority`
+        assert_account_key(vault_info, &vault)?;
 
-        let amount = vault_token.amount;
+        msg!("Calling the token program to refund tokens to the payer...");
+        Self::transfer_from_vault(
+            token_program_info,
+            vault_token_info,
+            payer_token_info,
+            vault_info,
+            &vault_signer_seeds,
+            amount,
+            RefundFailed,
+        )?;
+
+        escrow.amount = escrow.amount.checked_sub(amount).ok_or(AmountOverflow)?;
+        escrow.checksum = escrow.compute_checksum();
+        msg!("refunded={} remaining={}", amount, escrow.amount);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+
+        EscrowEvent {
+            kind: EventKind::PartiallyCanceled,
+            escrow: *escrow_info.key,
+            recipient: *payer_token_info.key,
+            amount,
+            fee: 0,
+        }
+        .emit();
+        Ok(())
+    }
+
+    fn process_claim_fee(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        msg!("Process fee claim");
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+
+        let fee_token_info = next_account_info(account_info_iter)?;
+        let vault_token_info = next_account_info(account_info_iter)?;
+
+        // The escrow is checked first, the vault of a settled escrow is already closed
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+        Self::authorize(&escrow, EscrowAction::Settle, authority_info.key)?;
+        assert_dual_sig(&escrow, accounts)?;
+
+        assert_owned_by_token_program(vault_token_info)?;
+        let vault_token = unpack_token_account(vault_token_info)?;
+        assert_vault_balance(&escrow, &vault_token)?;
+
+        // Wrapped SOL fees are paid in lamports when the vault is closed
+        if vault_token.is_native() {
+            return Err(NativeFeeClaim.into());
+        }
+        let fee = escrow.fee;
+        if fee == 0 {
+            return Err(ZeroAmount.into());
+        }
+        if fee > vault_token.amount {
+            msg!(
+                "Fee too high..., {} should be less than or equal to {}",
+                fee,
+                vault_token.amount
+            );
+            return Err(FeeOverflow.into());
+        }
+
+        assert_account_key(fee_token_info, &escrow.fee_token)?;
+        assert_fee_token_open(fee_token_info, &escrow.token_program)?;
+        assert_account_key(vault_token_info, &escrow.vault_token)?;
+        if escrow.derived_vault {
+            assert_derived_vault(vault_token_info, escrow_info.key, program_id)?;
+        }
+        assert_vault_mint_unchanged(&vault_token, &escrow.mint)?;
+
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+        assert_owned_by(vault_token_info, &escrow.token_program)?;
+
+        let bump_seed = escrow.authority_bump;
         let vault_signer_seeds = [
             PREFIX.as_bytes(),
             program_id.as_ref(),
             &[bump_seed],
         ];
+        let vault = Pubkey::create_program_address(&vault_signer_seeds, program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+        let vault_info = next_account_info(account_info_iter)?;
+        // Key check only, see `find_program_authority`
+        assert_account_key(vault_info, &vault)?;
+
+        msg!("Calling the token program to transfer tokens to the fee taker...");
+        Self::transfer_from_vault(
+            token_program_info,
+            vault_token_info,
+            fee_token_info,
+            vault_info,
+            &vault_signer_seeds,
+            fee,
+            FeeTransferFailed,
+        )?;
+
+        // The vault keeps matching the escrowed amount and the fee isn't charged again
+        escrow.amount = escrow.amount.saturating_sub(fee);
+        escrow.fee = 0;
+        escrow.checksum = escrow.compute_checksum();
+        msg!("fee_claimed={} remaining={}", fee, escrow.amount);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+
+        EscrowEvent {
+            kind: EventKind::PartiallySettled,
+            escrow: *escrow_info.key,
+            recipient: *fee_token_info.key,
+            amount: 0,
+            fee,
+        }
+        .emit();
+        Ok(())
+    }
+
+    fn process_settle_split(
+        accounts: &[AccountInfo],
+        recipients: &[(Pubkey, u64)],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        msg!("Process split settlement");
+        if recipients.len() > MAX_SPLIT {
+            msg!("Split too large..., got: {} , max {}", recipients.len(), MAX_SPLIT);
+            return Err(BatchTooLarge.into());
+        }
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+
+        let fee_token_info = next_account_info(account_info_iter)?;
+        let vault_token_info = next_account_info(account_info_iter)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+        Self::authorize(&escrow, EscrowAction::Settle, authority_info.key)?;
+        assert_dual_sig(&escrow, accounts)?;
+
+        // Read after `authorize`, the vault of a resolved escrow is closed
+        assert_owned_by_token_program(vault_token_info)?;
+        let vault_token = unpack_token_account(vault_token_info)?;
+        assert_vault_balance(&escrow, &vault_token)?;
+
+        // Wrapped SOL is paid out by closing the vault into a single wallet
         if vault_token.is_native() {
-            let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
-                token_program_info.key,
-                vault_token_info.key,
-                escrow_info.key,
-                &vault_key,
-                &[&vault_key],
-            )?;
-            msg!("Calling the token program to close pda's temp account...and add the remaining lamports to the escrow account");
-            invoke_signed(
-                &close_pdas_temp_acc_ix,
-                &[
-                    vault_token_info.clone(),
-                    escrow_info.clone(),
-                    vault_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[&vault_signer_seeds],
-            )?;
-            let source_starting_lamports = escrow_info.lamports();
-            **escrow_info.lamports.borrow_mut() = source_starting_lamports
-                .checked_sub(amount)
-                .ok_or(AmountOverflow)?;
+            return Err(NativeSplitSettlement.into());
+        }
+        if escrow.callback_program != Pubkey::default()
+            || escrow.counterparty_mint != Pubkey::default()
+        {
+            msg!("Escrows with a callback or a counterparty side settle through Settle");
+            return Err(ProgramError::InvalidArgument);
+        }
 
-            let dest_starting_lamports = payer_token_info.lamports();
-            **payer_token_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(amount)
-                .ok_or(AmountOverflow)?;
-        } else {
-            let transfer_to_payer_ix = spl_token::instruction::transfer(
-                token_program_info.key,
-                vault_token_info.key,
-                payer_token_info.key,
-                &vault_key,
-                &[&vault_key],
-                amount,
+        let (payout, fee) = escrow.settlement_breakdown(vault_token.amount)?;
+        if fee > 0 {
+            assert_account_key(fee_token_info, &escrow.fee_token)?;
+            assert_fee_token_open(fee_token_info, &escrow.token_program)?;
+        }
+        assert_account_key(vault_token_info, &escrow.vault_token)?;
+        if escrow.derived_vault {
+            assert_derived_vault(vault_token_info, escrow_info.key, program_id)?;
+        }
+        assert_vault_mint_unchanged(&vault_token, &escrow.mint)?;
+
+        let fee_payer_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+        assert_owned_by(vault_token_info, &escrow.token_program)?;
+
+        let bump_seed = escrow.authority_bump;
+        let vault_signer_seeds = [
+            PREFIX.as_bytes(),
+            program_id.as_ref(),
+            &[bump_seed],
+        ];
+        let vault = Pubkey::create_program_address(&vault_signer_seeds, program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+        let vault_info = next_account_info(account_info_iter)?;
+        // Key check only, see `find_program_authority`
+        assert_account_key(vault_info, &vault)?;
+
+        if escrow.condition_account != Pubkey::default() {
+            let condition_info = next_account_info(account_info_iter)?;
+            assert_condition_met(condition_info, &escrow.condition_account, &escrow.condition_owner)?;
+        }
+
+        let mut recipient_infos = Vec::with_capacity(recipients.len());
+        let mut total: u64 = 0;
+        for (token, amount) in recipients {
+            let recipient_info = next_account_info(account_info_iter)?;
+            assert_account_key(recipient_info, token)?;
+            assert_owned_by(recipient_info, &escrow.token_program)?;
+            let recipient_token: TokenAccount = assert_initialized(recipient_info)?;
+            assert_mint_matches(&recipient_token, &vault_token.mint)?;
+            total = total.checked_add(*amount).ok_or(AmountOverflow)?;
+            recipient_infos.push(recipient_info);
+        }
+        if total != payout {
+            msg!("Split pays {}, the vault less the fee is {}", total, payout);
+            return Err(ExpectedAmountMismatch.into());
+        }
+
+        for (recipient_info, (_, amount)) in recipient_infos.iter().zip(recipients) {
+            msg!("Calling the token program to transfer {} to a recipient...", amount);
+            Self::transfer_from_vault(
+                token_program_info,
+                vault_token_info,
+                recipient_info,
+                vault_info,
+                &vault_signer_seeds,
+                *amount,
+                PayeeTransferFailed,
             )?;
-            msg!("Calling the token program to transfer tokens to the payer...");
-            invoke_signed(
-                &transfer_to_payer_ix,
-                &[
-                    vault_token_info.clone(),
-                    payer_token_info.clone(),
-                    vault_info.clone(),
-                    token_program_info.clone(),
-                ],
-                &[&vault_signer_seeds],
+        }
+        if fee > 0 {
+            msg!("Calling the token program to transfer tokens to the fee taker...");
+            Self::transfer_from_vault(
+                token_program_info,
+                vault_token_info,
+                fee_token_info,
+                vault_info,
+                &vault_signer_seeds,
+                fee,
+                FeeTransferFailed,
             )?;
+        }
 
-            let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
-                token_program_info.key,
+        let close_pdas_temp_acc_ix = token_instruction(
+            token_program_info.key,
+            spl_token::instruction::close_account(
+                &spl_token::id(),
                 vault_token_info.key,
                 fee_payer_info.key,
-                &vault_key,
-                &[&vault_key],
-            )?;
-            msg!("Calling the token program to close pda's temp account...");
+                &vault,
+                &[&vault],
+            ),
+        )?;
+        msg!("Calling the token program to close pda's temp account...");
+        map_cpi_error(
             invoke_signed(
                 &close_pdas_temp_acc_ix,
                 &[
@@ -421,42 +1269,1019 @@ impl Processor {
                     token_program_info.clone(),
                 ],
                 &[&vault_signer_seeds],
-            )?;
-        }
+            ),
+            VaultCloseFailed,
+        )?;
 
         msg!("Mark the escrow account as settled...");
-        escrow.is_canceled = true;
+        escrow.is_settled = true;
+        escrow.set_resolved_at(escrow_info.data_len(), Clock::get()?.unix_timestamp);
         Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+
+        // The fee is reported once, with the first recipient
+        for (index, (token, amount)) in recipients.iter().enumerate() {
+            EscrowEvent {
+                kind: EventKind::Settled,
+                escrow: *escrow_info.key,
+                recipient: *token,
+                amount: *amount,
+                fee: if index == 0 { fee } else { 0 },
+            }
+            .emit();
+        }
         Ok(())
     }
 
+    /// Transfers `amount` out of a vault owned by the program authority
+    fn transfer_from_vault<'a>(
+        token_program_info: &AccountInfo<'a>,
+        vault_token_info: &AccountInfo<'a>,
+        destination_info: &AccountInfo<'a>,
+        authority_info: &AccountInfo<'a>,
+        authority_signer_seeds: &[&[u8]],
+        amount: u64,
+        error: EscrowError,
+    ) -> ProgramResult {
+        let transfer_ix = token_instruction(
+            token_program_info.key,
+            spl_token::instruction::transfer(
+                &spl_token::id(),
+                vault_token_info.key,
+                destination_info.key,
+                authority_info.key,
+                &[authority_info.key],
+                amount,
+            ),
+        )?;
+        map_cpi_error(
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    vault_token_info.clone(),
+                    destination_info.clone(),
+                    authority_info.clone(),
+                    token_program_info.clone(),
+                ],
+                &[authority_signer_seeds],
+            ),
+            error,
+        )
+    }
+
+    fn invoke_settle_callback<'a>(
+        callback_program_info: &AccountInfo<'a>,
+        escrow_info: &AccountInfo<'a>,
+        amount: u64,
+        fee: u64,
+    ) -> ProgramResult {
+        let mut data = Vec::with_capacity(48);
+        data.extend_from_slice(escrow_info.key.as_ref());
+        data.extend_from_slice(&amount.to_le_bytes());
+        data.extend_from_slice(&fee.to_le_bytes());
+        // Writable because the escrow was just packed, native program-test checks that write
+        // against the privileges of this instruction. The callback doesn't own the escrow, the
+        // runtime rejects any change it makes to it.
+        let callback_ix = Instruction {
+            program_id: *callback_program_info.key,
+            accounts: vec![AccountMeta::new(*escrow_info.key, false)],
+            data,
+        };
+        msg!("Calling the callback program...");
+        invoke(
+            &callback_ix,
+            &[escrow_info.clone(), callback_program_info.clone()],
+        )
+    }
+
     //inside: impl Processor {}
-    fn process_close(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    /// Cancels the escrow under its authority, or under its payer once expired for `reclaim`
+    fn process_cancel(
+        accounts: &[AccountInfo],
+        reclaim: bool,
+        unwrap_to_native: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        msg!("Process cancelation");
         let account_info_iter = &mut accounts.iter();
         let authority_info = next_account_info(account_info_iter)?;
         assert_signer(authority_info)?;
 
         let escrow_info = next_account_info(account_info_iter)?;
+        let payer_token_info = next_account_info(account_info_iter)?;
+        let fee_payer_info = next_account_info(account_info_iter)?;
+        let vault_token_info = next_account_info(account_info_iter)?;
+
         assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+        let action = if reclaim {
+            EscrowAction::Reclaim
+        } else {
+            EscrowAction::Cancel
+        };
+        Self::authorize(&escrow, action, authority_info.key)?;
 
-        let escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        // Read after `authorize`, the vault of a resolved escrow is closed
+        assert_owned_by(vault_token_info, &escrow.token_program)?;
+        let vault_token = unpack_token_account(vault_token_info)?;
 
-        if escrow.authority != *authority_info.key {
-            return Err(ProgramError::InvalidAccountData);
+        assert_account_key(payer_token_info, &escrow.payer_token)?;
+        assert_account_key(vault_token_info, &escrow.vault_token)?;
+        if escrow.derived_vault {
+            assert_derived_vault(vault_token_info, escrow_info.key, program_id)?;
+        }
+        assert_vault_mint_unchanged(&vault_token, &escrow.mint)?;
+        // Wrapped SOL vaults are always refunded in lamports, the unwrap only rules out tokens
+        if unwrap_to_native && vault_token.mint != spl_token::native_mint::id() {
+            msg!("Vault mint {} is not wrapped SOL", vault_token.mint);
+            return Err(UnwrapNonNative.into());
         }
 
-        if !(escrow.is_settled() || escrow.is_canceled()) {
-            return Err(AccountNotSettledOrCanceled.into());
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+
+        let bump_seed = escrow.authority_bump;
+        let vault_signer_seeds = [
+            PREFIX.as_bytes(),
+            program_id.as_ref(),
+            &[bump_seed],
+        ];
+        let vault_key = Pubkey::create_program_address(&vault_signer_seeds, program_id)
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+
+        let vault_info = next_account_info(account_info_iter)?;
+        // Key check only, see `find_program_authority`
+        assert_account_key(vault_info, &vault_key)?;
+
+        if escrow.require_payer_on_cancel && !reclaim {
+            let payer_info = next_account_info(account_info_iter)?;
+            assert_signer(payer_info)?;
+            assert_account_key(payer_info, &escrow.payer)?;
         }
 
-        let fee_payer_info = next_account_info(account_info_iter)?;
-        msg!("Closing the escrow account...");
-        **fee_payer_info.lamports.borrow_mut() = fee_payer_info
-            .lamports()
-            .checked_add(escrow_info.lamports())
-            .ok_or(AmountOverflow)?;
-        **escrow_info.lamports.borrow_mut() = 0;
-        *escrow_info.data.borrow_mut() = &mut [];
+        let restock_fee = if reclaim { 0 } else { escrow.restock_fee };
+        let restock_token_info = if restock_fee > 0 {
+            let restock_token_info = next_account_info(account_info_iter)?;
+            assert_account_key(restock_token_info, &escrow.restock_token)?;
+            Some(restock_token_info)
+        } else {
+            None
+        };
+
+        let counterparty_infos = if escrow.counterparty_vault != Pubkey::default() {
+            let counterparty_vault_info = next_account_info(account_info_iter)?;
+            assert_account_key(counterparty_vault_info, &escrow.counterparty_vault)?;
+            let counterparty_refund_info = next_account_info(account_info_iter)?;
+            assert_account_key(counterparty_refund_info, &escrow.counterparty_refund)?;
+            Some((counterparty_vault_info, counterparty_refund_info))
+        } else {
+            None
+        };
+
+        let amount = vault_token.amount;
+        let mut refund = amount.checked_sub(restock_fee).ok_or(FeeOverflow)?;
+        if vault_token.is_native() {
+            assert_native_recipient(payer_token_info)?;
+            if let Some(restock_token_info) = restock_token_info {
+                assert_native_recipient(restock_token_info)?;
+            }
+            let vault_lamports = vault_token_info.lamports();
+            let close_pdas_temp_acc_ix = token_instruction(
+                token_program_info.key,
+                spl_token::instruction::close_account(
+                    &spl_token::id(),
+                    vault_token_info.key,
+                    escrow_info.key,
+                    &vault_key,
+                    &[&vault_key],
+                ),
+            )?;
+            msg!("Calling the token program to close pda's temp account...and add the remaining lamports to the escrow account");
+            map_cpi_error(
+                invoke_signed(
+                    &close_pdas_temp_acc_ix,
+                    &[
+                        vault_token_info.clone(),
+                        escrow_info.clone(),
+                        vault_info.clone(),
+                        token_program_info.clone(),
+                    ],
+                    &[&vault_signer_seeds],
+                ),
+                VaultCloseFailed,
+            )?;
+            // The closed vault's lamports are its rent, the escrowed amount and any surplus sent
+            // to it since, synced or not. The payer gets the amount less the restocking fee plus
+            // the surplus, the vault's own rent goes to the fee payer as it does when an SPL
+            // vault is closed.
+            let vault_rent = Rent::get()?.minimum_balance(vault_token_info.data_len());
+            let held = vault_lamports.checked_sub(vault_rent).ok_or(AmountOverflow)?;
+            let surplus = held.checked_sub(escrow.amount).ok_or(AmountOverflow)?;
+            if surplus > 0 {
+                msg!("Returning a vault surplus of {} lamports to the payer", surplus);
+            }
+            refund = held.checked_sub(restock_fee).ok_or(FeeOverflow)?;
+
+            let source_starting_lamports = escrow_info.lamports();
+            **escrow_info.lamports.borrow_mut() = source_starting_lamports
+                .checked_sub(vault_lamports)
+                .ok_or(AmountOverflow)?;
+
+            let dest_starting_lamports = payer_token_info.lamports();
+            **payer_token_info.lamports.borrow_mut() = dest_starting_lamports
+                .checked_add(refund)
+                .ok_or(AmountOverflow)?;
+
+            if let Some(restock_token_info) = restock_token_info {
+                let dest_starting_lamports = restock_token_info.lamports();
+                **restock_token_info.lamports.borrow_mut() = dest_starting_lamports
+                    .checked_add(restock_fee)
+                    .ok_or(AmountOverflow)?;
+            }
+
+            let dest_starting_lamports = fee_payer_info.lamports();
+            **fee_payer_info.lamports.borrow_mut() = dest_starting_lamports
+                .checked_add(vault_rent)
+                .ok_or(AmountOverflow)?;
+            assert_rent_exempt(&Rent::get()?, escrow_info)?;
+        } else {
+            let transfer_to_payer_ix = token_instruction(
+                token_program_info.key,
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    vault_token_info.key,
+                    payer_token_info.key,
+                    &vault_key,
+                    &[&vault_key],
+                    refund,
+                ),
+            )?;
+            msg!("Calling the token program to transfer tokens to the payer...");
+            map_cpi_error(
+                invoke_signed(
+                    &transfer_to_payer_ix,
+                    &[
+                        vault_token_info.clone(),
+                        payer_token_info.clone(),
+                        vault_info.clone(),
+                        token_program_info.clone(),
+                    ],
+                    &[&vault_signer_seeds],
+                ),
+                RefundFailed,
+            )?;
+
+            if let Some(restock_token_info) = restock_token_info {
+                let transfer_to_restock_ix = token_instruction(
+                    token_program_info.key,
+                    spl_token::instruction::transfer(
+                        &spl_token::id(),
+                        vault_token_info.key,
+                        restock_token_info.key,
+                        &vault_key,
+                        &[&vault_key],
+                        restock_fee,
+                    ),
+                )?;
+                msg!("Calling the token program to transfer the restocking fee...");
+                map_cpi_error(
+                    invoke_signed(
+                        &transfer_to_restock_ix,
+                        &[
+                            vault_token_info.clone(),
+                            restock_token_info.clone(),
+                            vault_info.clone(),
+                            token_program_info.clone(),
+                        ],
+                        &[&vault_signer_seeds],
+                    ),
+                    FeeTransferFailed,
+                )?;
+            }
+
+            let close_pdas_temp_acc_ix = token_instruction(
+                token_program_info.key,
+                spl_token::instruction::close_account(
+                    &spl_token::id(),
+                    vault_token_info.key,
+                    fee_payer_info.key,
+                    &vault_key,
+                    &[&vault_key],
+                ),
+            )?;
+            msg!("Calling the token program to close pda's temp account...");
+            map_cpi_error(
+                invoke_signed(
+                    &close_pdas_temp_acc_ix,
+                    &[
+                        vault_token_info.clone(),
+                        fee_payer_info.clone(),
+                        vault_info.clone(),
+                        token_program_info.clone(),
+                    ],
+                    &[&vault_signer_seeds],
+                ),
+                VaultCloseFailed,
+            )?;
+        }
+
+        if let Some((counterparty_vault_info, counterparty_refund_info)) = counterparty_infos {
+            msg!("Refunding the counterparty side to the payee...");
+            Self::drain_vault(
+                token_program_info,
+                counterparty_vault_info,
+                counterparty_refund_info,
+                fee_payer_info,
+                vault_info,
+                &vault_signer_seeds,
+                RefundFailed,
+            )?;
+        }
+
+        msg!("Mark the escrow account as settled...");
+        escrow.is_canceled = true;
+        escrow.set_resolved_at(escrow_info.data_len(), Clock::get()?.unix_timestamp);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+
+        EscrowEvent {
+            kind: EventKind::Canceled,
+            escrow: *escrow_info.key,
+            recipient: *payer_token_info.key,
+            amount: refund,
+            fee: restock_fee,
+        }
+        .emit();
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_close(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        let fee_payer_info = next_account_info(account_info_iter)?;
+        if let Ok(vault_token_info) = next_account_info(account_info_iter) {
+            assert_owned_by(escrow_info, program_id)?;
+            let escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+            assert_account_key(vault_token_info, &escrow.vault_token)?;
+            assert_vault_drained(vault_token_info)?;
+        }
+        msg!("Closing the escrow account...");
+        let lamports = Self::close_escrow_account(escrow_info, authority_info, program_id)?;
+        **fee_payer_info.lamports.borrow_mut() = fee_payer_info
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(AmountOverflow)?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_settle_and_close(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        // Settlement runs every check and leaves the escrow settled, as the callback and
+        // `close_escrow_account` read it. Nothing reads the escrow once it is closed.
+        Self::process_settlement(accounts, false, [0; 32], program_id)?;
+
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        let escrow_info = account_info_iter.nth(3).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let fee_payer_info = next_account_info(account_info_iter)?;
+        msg!("Closing the escrow account...");
+        let lamports = Self::close_escrow_account(escrow_info, authority_info, program_id)?;
+        **fee_payer_info.lamports.borrow_mut() = fee_payer_info
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(AmountOverflow)?;
+        Ok(())
+    }
+
+    //inside: impl Processor {}
+    fn process_close_many_to_treasury(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+
+        let treasury_info = next_account_info(account_info_iter)?;
+        let escrow_infos = account_info_iter.as_slice();
+        if escrow_infos.is_empty() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        if escrow_infos.len() > MAX_BATCH {
+            msg!(
+                "Batch too large..., got: {} , max {}",
+                escrow_infos.len(),
+                MAX_BATCH
+            );
+            return Err(BatchTooLarge.into());
+        }
+
+        let mut rent_recovered: u64 = 0;
+        for escrow_info in escrow_infos {
+            let lamports = log_escrow_error(
+                Some(escrow_info.key),
+                Self::close_escrow_account(escrow_info, authority_info, program_id),
+            )?;
+            rent_recovered = rent_recovered
+                .checked_add(lamports)
+                .ok_or(AmountOverflow)?;
+        }
+
+        **treasury_info.lamports.borrow_mut() = treasury_info
+            .lamports()
+            .checked_add(rent_recovered)
+            .ok_or(AmountOverflow)?;
+        msg!(
+            "rent_recovered={} count={}",
+            rent_recovered,
+            escrow_infos.len()
+        );
+        Ok(())
+    }
+
+    fn process_settle_many(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let pda_info = next_account_info(account_info_iter)?;
+
+        let group_infos = account_info_iter.as_slice();
+        let groups = group_infos.chunks_exact(SETTLE_MANY_GROUP.len());
+        if group_infos.is_empty() || !groups.remainder().is_empty() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let count = groups.len();
+        if count > MAX_BATCH {
+            msg!("Batch too large..., got: {} , max {}", count, MAX_BATCH);
+            return Err(BatchTooLarge.into());
+        }
+
+        let mut settled = 0;
+        for group in groups {
+            let escrow_info = &group[3];
+            // Checked before the skip, a foreign account reading as resolved aborts the batch
+            assert_owned_by(escrow_info, program_id)?;
+            let escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+            if escrow.is_settled() || escrow.is_canceled() {
+                msg!("Skipping resolved escrow {}", escrow_info.key);
+                continue;
+            }
+            let settle_accounts = [
+                authority_info.clone(),
+                group[0].clone(),
+                group[1].clone(),
+                group[2].clone(),
+                escrow_info.clone(),
+                group[4].clone(),
+                token_program_info.clone(),
+                pda_info.clone(),
+            ];
+            log_escrow_error(
+                Some(escrow_info.key),
+                Self::process_settlement(&settle_accounts, false, [0; 32], program_id),
+            )?;
+            settled += 1;
+        }
+        msg!("settled={} skipped={}", settled, count - settled);
+        Ok(())
+    }
+
+    /// The checks deciding whether `key` may perform `action` on the escrow, shared by the
+    /// actions themselves and `CheckAuthorization`
+    fn authorize(escrow: &Escrow, action: EscrowAction, key: &Pubkey) -> ProgramResult {
+        if escrow.is_canceled() {
+            return Err(AccountAlreadyCanceled.into());
+        }
+        if escrow.is_settled() {
+            return Err(AccountAlreadySettled.into());
+        }
+        if escrow.is_disputed {
+            return Err(EscrowDisputed.into());
+        }
+        let resolution = match action {
+            EscrowAction::Settle => DisputeResolution::Settle,
+            EscrowAction::Cancel | EscrowAction::Reclaim => DisputeResolution::Refund,
+        };
+        if escrow.dispute_resolution != 0 && escrow.dispute_resolution != resolution as u8 {
+            return Err(DisputeResolvedOtherwise.into());
+        }
+        if action == EscrowAction::Settle {
+            let now = Clock::get()?.unix_timestamp;
+            if !escrow.in_settlement_window(now) {
+                msg!(
+                    "Settlement window is {}..={}, now is {}",
+                    escrow.settle_not_before,
+                    escrow.settle_not_after,
+                    now
+                );
+                return Err(OutsideSettlementWindow.into());
+            }
+            if escrow.counterparty_mint != Pubkey::default()
+                && escrow.counterparty_vault == Pubkey::default()
+            {
+                return Err(CounterpartyNotDeposited.into());
+            }
+        }
+        if action == EscrowAction::Reclaim {
+            if escrow.expires_at == 0 {
+                return Err(NoExpiry.into());
+            }
+            let now = Clock::get()?.unix_timestamp;
+            if now < escrow.expires_at {
+                msg!("Escrow expires at {}, now is {}", escrow.expires_at, now);
+                return Err(NotExpired.into());
+            }
+            if *key != escrow.payer {
+                return Err(InvalidAuthority.into());
+            }
+            return Ok(());
+        }
+        if *key != escrow.authority {
+            return Err(InvalidAuthority.into());
+        }
+        Ok(())
+    }
+
+    fn process_check_authorization(
+        accounts: &[AccountInfo],
+        action: EscrowAction,
+        key: &Pubkey,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+
+        let authorized = Self::authorize(&escrow, action, key).is_ok();
+        msg!("authorized={}", authorized);
+        set_return_data(&[authorized as u8]);
+        Ok(())
+    }
+
+    fn process_log_escrow(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let escrow = Escrow::unpack_unchecked(&escrow_info.data.borrow())?;
+
+        let memo: String = escrow.settle_memo.iter().map(|byte| format!("{:02x}", byte)).collect();
+        msg!(
+            "escrow={} state={:?} version={}",
+            escrow_info.key,
+            escrow.state(),
+            escrow.version
+        );
+        msg!(
+            "amount={} fee={} fee_bps={} max_abs_fee={} settlement_fee={}",
+            escrow.amount,
+            escrow.fee,
+            escrow.fee_bps,
+            escrow.max_abs_fee,
+            escrow.settlement_fee()
+        );
+        msg!(
+            "payer={} payer_token={} payee_token={}",
+            escrow.payer,
+            escrow.payer_token,
+            escrow.payee_token
+        );
+        msg!(
+            "vault_token={} fee_token={} mint={} derived_vault={}",
+            escrow.vault_token,
+            escrow.fee_token,
+            escrow.mint,
+            escrow.derived_vault
+        );
+        msg!(
+            "authority={} rotation_count={} authority_bump={}",
+            escrow.authority,
+            escrow.rotation_count,
+            escrow.authority_bump
+        );
+        msg!(
+            "callback_program={} callback_optional={}",
+            escrow.callback_program,
+            escrow.callback_optional
+        );
+        msg!(
+            "points_mint={} points_amount={}",
+            escrow.points_mint,
+            escrow.points_amount
+        );
+        msg!(
+            "require_payer_on_cancel={} restock_fee={} restock_token={}",
+            escrow.require_payer_on_cancel,
+            escrow.restock_fee,
+            escrow.restock_token
+        );
+        msg!(
+            "settle_not_before={} settle_not_after={} expires_at={} resolved_at={}",
+            escrow.settle_not_before,
+            escrow.settle_not_after,
+            escrow.expires_at,
+            escrow.resolved_at
+        );
+        msg!(
+            "condition_account={} condition_owner={}",
+            escrow.condition_account,
+            escrow.condition_owner
+        );
+        msg!(
+            "counterparty_mint={} counterparty_amount={} counterparty_token={}",
+            escrow.counterparty_mint,
+            escrow.counterparty_amount,
+            escrow.counterparty_token
+        );
+        msg!(
+            "counterparty_vault={} counterparty_refund={}",
+            escrow.counterparty_vault,
+            escrow.counterparty_refund
+        );
+        msg!("token_program={} checksum={} settle_memo={}", escrow.token_program, escrow.checksum, memo);
+        Ok(())
+    }
+
+    fn process_deposit_counterparty(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payee_info = next_account_info(account_info_iter)?;
+        assert_signer(payee_info)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+        if escrow.is_canceled() {
+            return Err(AccountAlreadyCanceled.into());
+        }
+        if escrow.is_settled() {
+            return Err(AccountAlreadySettled.into());
+        }
+        if escrow.counterparty_mint == Pubkey::default() {
+            return Err(CounterpartyNotConfigured.into());
+        }
+        if escrow.counterparty_vault != Pubkey::default() {
+            return Err(CounterpartyAlreadyDeposited.into());
+        }
+
+        let payee_token_info = next_account_info(account_info_iter)?;
+        assert_account_key(payee_token_info, &escrow.payee_token)?;
+        let payee = if escrow.mint == spl_token::native_mint::id() {
+            *payee_token_info.key
+        } else {
+            assert_owned_by(payee_token_info, &escrow.token_program)?;
+            unpack_token_account(payee_token_info)?.owner
+        };
+        assert_account_key(payee_info, &payee)?;
+
+        let counterparty_vault_info = next_account_info(account_info_iter)?;
+        assert_owned_by(counterparty_vault_info, &escrow.token_program)?;
+        let counterparty_vault: TokenAccount = assert_initialized(counterparty_vault_info)?;
+        assert_mint_matches(&counterparty_vault, &escrow.counterparty_mint)?;
+        assert_fresh_vault(&counterparty_vault, payee_info.key, escrow.counterparty_amount)?;
+
+        let counterparty_refund_info = next_account_info(account_info_iter)?;
+        assert_owned_by(counterparty_refund_info, &escrow.token_program)?;
+        let counterparty_refund: TokenAccount = assert_initialized(counterparty_refund_info)?;
+        assert_mint_matches(&counterparty_refund, &escrow.counterparty_mint)?;
+
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+
+        escrow.counterparty_vault = *counterparty_vault_info.key;
+        escrow.counterparty_refund = *counterparty_refund_info.key;
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+
+        let (pda, _bump_seed) = find_program_authority(program_id);
+        let owner_change_ix = token_instruction(
+            token_program_info.key,
+            spl_token::instruction::set_authority(
+                &spl_token::id(),
+                counterparty_vault_info.key,
+                Some(&pda),
+                spl_token::instruction::AuthorityType::AccountOwner,
+                payee_info.key,
+                &[payee_info.key],
+            ),
+        )?;
+        msg!("Calling the token program to transfer counterparty vault ownership...");
+        invoke(
+            &owner_change_ix,
+            &[
+                counterparty_vault_info.clone(),
+                payee_info.clone(),
+                token_program_info.clone(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Moves every token of a vault owned by the program authority to `destination_info`, then
+    /// closes the vault to `rent_receiver_info`. A failed transfer maps to `transfer_error`.
+    fn drain_vault<'a>(
+        token_program_info: &AccountInfo<'a>,
+        vault_token_info: &AccountInfo<'a>,
+        destination_info: &AccountInfo<'a>,
+        rent_receiver_info: &AccountInfo<'a>,
+        authority_info: &AccountInfo<'a>,
+        authority_signer_seeds: &[&[u8]],
+        transfer_error: EscrowError,
+    ) -> ProgramResult {
+        let amount = unpack_token_account(vault_token_info)?.amount;
+        let transfer_ix = token_instruction(
+            token_program_info.key,
+            spl_token::instruction::transfer(
+                &spl_token::id(),
+                vault_token_info.key,
+                destination_info.key,
+                authority_info.key,
+                &[authority_info.key],
+                amount,
+            ),
+        )?;
+        msg!("Calling the token program to drain the vault...");
+        map_cpi_error(
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    vault_token_info.clone(),
+                    destination_info.clone(),
+                    authority_info.clone(),
+                    token_program_info.clone(),
+                ],
+                &[authority_signer_seeds],
+            ),
+            transfer_error,
+        )?;
+        let close_ix = token_instruction(
+            token_program_info.key,
+            spl_token::instruction::close_account(
+                &spl_token::id(),
+                vault_token_info.key,
+                rent_receiver_info.key,
+                authority_info.key,
+                &[authority_info.key],
+            ),
+        )?;
+        map_cpi_error(
+            invoke_signed(
+                &close_ix,
+                &[
+                    vault_token_info.clone(),
+                    rent_receiver_info.clone(),
+                    authority_info.clone(),
+                    token_program_info.clone(),
+                ],
+                &[authority_signer_seeds],
+            ),
+            VaultCloseFailed,
+        )
+    }
+
+    //inside: impl Processor {}
+    fn process_force_reclaim(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let admin_info = next_account_info(account_info_iter)?;
+        assert_signer(admin_info)?;
+        if *admin_info.key != admin::id() {
+            return Err(InvalidAuthorityId.into());
+        }
+
+        let reclaimed_info = next_account_info(account_info_iter)?;
+        assert_owned_by(reclaimed_info, program_id)?;
+        let destination_info = next_account_info(account_info_iter)?;
+
+        msg!("Force reclaiming {}...", reclaimed_info.key);
+        **destination_info.lamports.borrow_mut() = destination_info
+            .lamports()
+            .checked_add(reclaimed_info.lamports())
+            .ok_or(AmountOverflow)?;
+        **reclaimed_info.lamports.borrow_mut() = 0;
+        // The wiped account keeps its length, the runtime purges it without lamports at the
+        // end of the transaction
+        reclaimed_info.data.borrow_mut().fill(0);
+        Ok(())
+    }
+
+    fn process_change_authority(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+
+        if escrow.is_canceled() {
+            return Err(AccountAlreadyCanceled.into());
+        }
+        if escrow.is_settled() {
+            return Err(AccountAlreadySettled.into());
+        }
+        assert_authority(authority_info, &escrow.authority)?;
+
+        let new_authority_info = next_account_info(account_info_iter)?;
+        assert_valid_authority(new_authority_info.key, program_id)?;
+
+        escrow.rotate_authority(*new_authority_info.key);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    fn process_top_up(accounts: &[AccountInfo], amount: u64, program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let payer_info = next_account_info(account_info_iter)?;
+        assert_signer(payer_info)?;
+
+        let source_token_info = next_account_info(account_info_iter)?;
+        let vault_token_info = next_account_info(account_info_iter)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+
+        if escrow.is_canceled() {
+            return Err(AccountAlreadyCanceled.into());
+        }
+        if escrow.is_settled() {
+            return Err(AccountAlreadySettled.into());
+        }
+        assert_account_key(payer_info, &escrow.payer)?;
+        if amount == 0 {
+            return Err(ZeroAmount.into());
+        }
+
+        assert_account_key(vault_token_info, &escrow.vault_token)?;
+        assert_owned_by(vault_token_info, &escrow.token_program)?;
+        let vault_token: TokenAccount = assert_initialized(vault_token_info)?;
+        assert_vault_mint_unchanged(&vault_token, &escrow.mint)?;
+        assert_owned_by(source_token_info, &escrow.token_program)?;
+        let source_token: TokenAccount = assert_initialized(source_token_info)?;
+        assert_mint_matches(&source_token, &vault_token.mint)?;
+
+        let token_program_info = next_account_info(account_info_iter)?;
+        assert_token_program(token_program_info, &escrow.token_program)?;
+
+        escrow.amount = escrow.amount.checked_add(amount).ok_or(AmountOverflow)?;
+        escrow.checksum = escrow.compute_checksum();
+
+        msg!("Calling the token program to top up the vault...");
+        invoke(
+            &token_instruction(
+                token_program_info.key,
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    source_token_info.key,
+                    vault_token_info.key,
+                    payer_info.key,
+                    &[],
+                    amount,
+                ),
+            )?,
+            &[
+                source_token_info.clone(),
+                vault_token_info.clone(),
+                payer_info.clone(),
+                token_program_info.clone(),
+            ],
+        )?;
+
+        msg!("added={} escrowed={}", amount, escrow.amount);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    fn process_raise_dispute(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let party_info = next_account_info(account_info_iter)?;
+        assert_signer(party_info)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+
+        if escrow.is_canceled() {
+            return Err(AccountAlreadyCanceled.into());
+        }
+        if escrow.is_settled() {
+            return Err(AccountAlreadySettled.into());
+        }
+        if escrow.is_disputed {
+            return Err(EscrowDisputed.into());
+        }
+        if *party_info.key != escrow.payer && *party_info.key != escrow.authority {
+            return Err(InvalidAuthority.into());
+        }
+
+        escrow.is_disputed = true;
+        escrow.dispute_resolution = 0;
+        msg!("disputed_by={}", party_info.key);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    fn process_resolve_dispute(
+        accounts: &[AccountInfo],
+        resolution: DisputeResolution,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+
+        if !escrow.is_disputed {
+            return Err(NotDisputed.into());
+        }
+        assert_authority(authority_info, &escrow.authority)?;
+
+        escrow.is_disputed = false;
+        escrow.dispute_resolution = resolution as u8;
+        msg!("resolution={:?}", resolution);
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
         Ok(())
     }
-}
+
+    //inside: impl Processor {}
+    fn process_update_fee_token(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let authority_info = next_account_info(account_info_iter)?;
+        assert_signer(authority_info)?;
+
+        let escrow_info = next_account_info(account_info_iter)?;
+        assert_owned_by(escrow_info, program_id)?;
+        let mut escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+        escrow.verify_checksum()?;
+
+        if escrow.is_canceled() {
+            return Err(AccountAlreadyCanceled.into());
+        }
+        if escrow.is_settled() {
+            return Err(AccountAlreadySettled.into());
+        }
+        assert_authority(authority_info, &escrow.authority)?;
+
+        let fee_token_info = next_account_info(account_info_iter)?;
+        // The same rule as at init, see `validate_init_params`
+        if *fee_token_info.key == escrow.payer_token || *fee_token_info.key == escrow.vault_token {
+            return Err(DuplicateTokenAccount.into());
+        }
+        let fee_recipient = if escrow.mint != spl_token::native_mint::id() {
+            assert_owned_by(fee_token_info, &escrow.token_program)?;
+            let fee_token: TokenAccount = assert_initialized(fee_token_info)?;
+            assert_mint_matches(&fee_token, &escrow.mint)?;
+            fee_token.owner
+        } else {
+            *fee_token_info.key
+        };
+        assert_fee_recipient(fee_token_info, &fee_recipient, &find_program_authority(program_id).0)?;
+
+        msg!("Updating the fee token account...");
+        escrow.fee_token = *fee_token_info.key;
+        Escrow::pack(escrow, &mut escrow_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Validates that a resolved escrow can be closed by the authority, then drains its
+    /// lamports and clears its data. Returns the drained lamports for the caller to credit.
+    fn close_escrow_account(
+        escrow_info: &AccountInfo,
+        authority_info: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<u64, ProgramError> {
+        // Checked first, an escrow closed earlier in the same transaction is still owned by the
+        // program until the transaction ends, only without lamports and with its data emptied.
+        // An older layout still has its own, shorter length, see `Escrow::packed_len`.
+        if escrow_info.lamports() == 0 || escrow_info.data_is_empty() {
+            msg!("Escrow {} is already closed", escrow_info.key);
+            return Err(AccountNotInitialized.into());
+        }
+        assert_owned_by(escrow_info, program_id)?;
+
+        let escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+
+        assert_authority(authority_info, &escrow.authority)?;
+
+        if !(escrow.is_settled() || escrow.is_canceled()) {
+            return Err(AccountNotSettledOrCanceled.into());
+        }
+
+        let lamports = escrow_info.lamports();
+        **escrow_info.lamports.borrow_mut() = 0;
+        escrow_info.data.borrow_mut().fill(0);
+        *escrow_info.data.borrow_mut() = &mut [];
+        Ok(lamports)
+    }
+}
\ No newline at end of file
@@ -1,10 +1,53 @@
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
+    entrypoint::ProgramResult,
+    hash::hashv,
+    msg,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
+use std::{convert::TryInto, fmt};
 
+use crate::error::EscrowError;
+
+/// Lifecycle stage of an escrow account
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscrowState {
+    Uninitialized,
+    Active,
+    Settled,
+    Canceled,
+    /// The account was closed and its data released
+    Closed,
+}
+
+/// What a client usually wants to know about an escrow, see `Escrow::summary`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EscrowSummary {
+    pub state: EscrowState,
+    pub is_disputed: bool,
+    pub amount: u64,
+    /// The fee charged at settlement, `Escrow::settlement_fee`
+    pub fee: u64,
+}
+
+impl fmt::Display for EscrowSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.state)?;
+        if self.is_disputed {
+            write!(f, " (disputed)")?;
+        }
+        write!(f, " {{ amount: {}, fee: {} }}", self.amount, self.fee)
+    }
+}
+
+/// Fields are declared in layout order, so the Borsh encoding is byte for byte the `Pack` layout
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, PartialEq)]
 pub struct Escrow {
+    /// Layout version, `Escrow::current_version()` for escrows initialized by this program
+    /// and zero for accounts not initialized yet
+    pub version: u8,
     pub is_initialized: bool,
     pub is_settled: bool,
     pub is_canceled: bool,
@@ -12,28 +55,388 @@ pub struct Escrow {
     pub payer_token: Pubkey,
     pub payee_token: Pubkey,
     pub vault_token: Pubkey,
-    pub fee_token: Pubkey,
     pub authority: Pubkey,
+    pub fee_token: Pubkey,
     pub amount: u64,
     pub fee: u64,
+    pub callback_program: Pubkey,
+    pub callback_optional: bool,
+    pub mint: Pubkey,
+    pub max_abs_fee: u64,
+    pub points_mint: Pubkey,
+    pub points_amount: u64,
+    pub require_payer_on_cancel: bool,
+    pub checksum: u64,
+    pub restock_fee: u64,
+    pub restock_token: Pubkey,
+    pub derived_vault: bool,
+    pub settle_not_before: i64,
+    pub settle_not_after: i64,
+    pub settle_memo: [u8; 32],
+    pub rotation_count: u16,
+    pub condition_account: Pubkey,
+    pub condition_owner: Pubkey,
+    pub fee_bps: u16,
+    pub token_program: Pubkey,
+    pub counterparty_mint: Pubkey,
+    pub counterparty_amount: u64,
+    pub counterparty_token: Pubkey,
+    pub counterparty_vault: Pubkey,
+    pub counterparty_refund: Pubkey,
+    /// Bump seed of the program authority, see `find_program_authority`.
+    /// Escrows packed before it was stored predate the version byte too, no layout version
+    /// matches their length and they fail to unpack. These accounts are unsupported, they can
+    /// only be closed with `ForceReclaim`
+    pub authority_bump: u8,
+    pub expires_at: i64,
+    /// Unix timestamp of the settlement or cancellation, zero while unresolved. Added in
+    /// version 2, version 1 escrows read it as zero, see `Escrow::set_resolved_at`
+    pub resolved_at: i64,
+    /// Added in version 3
+    pub allow_balance_drift: bool,
+    /// Added in version 4
+    pub derived_escrow: bool,
+    /// Bump of the escrow address when `derived_escrow` is set, see
+    /// `find_escrow_address`. Added in version 4
+    pub escrow_bump: u8,
+    /// Added in version 5
+    pub require_dual_sig: bool,
+    /// Frozen by `RaiseDispute` until `ResolveDispute`. Added in version 6
+    pub is_disputed: bool,
+    /// The last `DisputeResolution` as a byte, zero if no dispute was resolved. Added in
+    /// version 6
+    pub dispute_resolution: u8,
+    /// Added in version 7
+    pub dynamic_fee_recipient: bool,
 }
 
 impl Escrow {
+    /// Layout version written by this program
+    pub const fn current_version() -> u8 {
+        7
+    }
+    /// Stamps the resolution time on an escrow whose account data is `data_len` long. An
+    /// account of `Escrow::LEN` is upgraded to the current version, one still at the length of
+    /// the version 1 layout has no room for the timestamp and keeps it zero.
+    pub fn set_resolved_at(&mut self, data_len: usize, unix_timestamp: i64) {
+        if data_len == Self::LEN {
+            self.version = Self::current_version();
+        }
+        if self.version != 1 {
+            self.resolved_at = unix_timestamp;
+        }
+    }
+    /// Packed length of the layout of `version`, `None` for versions this program doesn't know.
+    /// Accounts not initialized yet are version zero and allocated at `Escrow::LEN`.
+    pub const fn packed_len(version: u8) -> Option<usize> {
+        match version {
+            0 => Some(Self::LEN),
+            1 => Some(668),
+            2 => Some(676),
+            3 => Some(677),
+            4 => Some(679),
+            5 => Some(680),
+            6 => Some(682),
+            7 => Some(683),
+            _ => None,
+        }
+    }
+    /// Copies the layout of `src` into a buffer of `Escrow::LEN`. Versions only ever append
+    /// fields, the ones an older version lacks read as zero, whether its account still has its
+    /// original length or was reallocated with stale bytes past it.
+    fn current_layout(src: &[u8]) -> Result<[u8; Self::LEN], ProgramError> {
+        let version = *src.first().ok_or(ProgramError::InvalidAccountData)?;
+        let len = Self::packed_len(version).ok_or(ProgramError::InvalidAccountData)?;
+        if src.len() < len {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut layout = [0; Self::LEN];
+        layout[..len].copy_from_slice(&src[..len]);
+        Ok(layout)
+    }
+    /// Decodes an initialized escrow from account data, the `Pack::unpack` checks without
+    /// the trait in scope. Closed and uninitialized accounts are errors.
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::unpack(data)
+    }
+    /// Reads an escrow through Borsh, with the same checks as `Pack::unpack_unchecked`
+    pub fn unpack_borsh(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::check_packed_len(data)?;
+        Self::try_from_slice(&Self::current_layout(data)?)
+            .map_err(|_| ProgramError::InvalidAccountData)
+    }
+    /// Account data is either of the packed length of its version or reallocated to
+    /// `Escrow::LEN`
+    fn check_packed_len(data: &[u8]) -> ProgramResult {
+        match data.first().and_then(|version| Self::packed_len(*version)) {
+            Some(len) if data.len() == len || data.len() == Self::LEN => Ok(()),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+    /// Writes an escrow through Borsh, the bytes `Pack::pack` writes
+    pub fn pack_borsh(&self) -> Result<Vec<u8>, ProgramError> {
+        self.try_to_vec()
+            .map_err(|_| ProgramError::InvalidAccountData)
+    }
     pub fn is_settled(&self) -> bool {
         self.is_settled
     }
     pub fn is_canceled(&self) -> bool {
         self.is_canceled
     }
+    /// Truncated hash of the fields that must not change while the escrow is active
+    pub fn compute_checksum(&self) -> u64 {
+        let hash = hashv(&[
+            self.payer.as_ref(),
+            self.payer_token.as_ref(),
+            self.payee_token.as_ref(),
+            self.vault_token.as_ref(),
+            self.authority.as_ref(),
+            &self.amount.to_le_bytes(),
+        ]);
+        u64::from_le_bytes(hash.to_bytes()[..8].try_into().unwrap())
+    }
+    pub fn verify_checksum(&self) -> Result<(), ProgramError> {
+        if self.checksum != self.compute_checksum() {
+            return Err(EscrowError::IntegrityCheckFailed.into());
+        }
+        Ok(())
+    }
+    /// The fee charged at settlement, the flat `fee` plus `fee_bps` of the amount, at most
+    /// the amount and capped by `max_abs_fee` when set
+    pub fn settlement_fee(&self) -> u64 {
+        self.settlement_fee_for(self.amount)
+    }
+    /// Like `settlement_fee` for `amount` settled instead of the escrowed amount, settlement
+    /// pays out the whole vault including later top-ups
+    pub fn settlement_fee_for(&self, amount: u64) -> u64 {
+        // `fee_bps` is at most 10_000, so the percentage never exceeds the amount
+        let percentage = (amount as u128 * self.fee_bps as u128 / 10_000) as u64;
+        let fee = self.fee.saturating_add(percentage).min(amount);
+        if self.max_abs_fee != 0 {
+            fee.min(self.max_abs_fee)
+        } else {
+            fee
+        }
+    }
+    /// What settling a vault holding `vault_amount` pays out, `(payee, fee)`, by the rules
+    /// `Settle` applies. Clients previewing a settlement should call it rather than mirror
+    /// the fee rules.
+    pub fn settlement_breakdown(&self, vault_amount: u64) -> Result<(u64, u64), ProgramError> {
+        let fee = self.settlement_fee_for(vault_amount);
+        let payee = vault_amount.checked_sub(fee).ok_or_else(|| {
+            msg!(
+                "Fee too high..., {} should be less than or equal to {}",
+                fee,
+                vault_amount
+            );
+            EscrowError::FeeOverflow
+        })?;
+        Ok((payee, fee))
+    }
+    /// The fee charged on a partial release of `amount`, the `fee_bps` share of it. The flat
+    /// fee and `max_abs_fee` only apply to the final settlement.
+    pub fn partial_fee(&self, amount: u64) -> u64 {
+        (amount as u128 * self.fee_bps as u128 / 10_000) as u64
+    }
+    /// Whether the authority is the program authority, see `InitOptions::program_controlled`
+    pub fn is_program_controlled(&self, program_id: &Pubkey) -> bool {
+        let seeds = [crate::PREFIX.as_bytes(), program_id.as_ref(), &[self.authority_bump]];
+        Pubkey::create_program_address(&seeds, program_id) == Ok(self.authority)
+    }
+    /// Whether `now` falls within the settlement window, both bounds inclusive
+    pub fn in_settlement_window(&self, now: i64) -> bool {
+        (self.settle_not_before == 0 || now >= self.settle_not_before)
+            && (self.settle_not_after == 0 || now <= self.settle_not_after)
+    }
+    /// Hands the escrow over to `new_authority`, counting the rotation for audit. The count
+    /// saturates instead of wrapping.
+    pub fn rotate_authority(&mut self, new_authority: Pubkey) {
+        let old_authority = self.authority;
+        self.authority = new_authority;
+        self.rotation_count = self.rotation_count.saturating_add(1);
+        self.checksum = self.compute_checksum();
+        msg!(
+            "authority_rotated old={} new={} count={}",
+            old_authority,
+            new_authority,
+            self.rotation_count
+        );
+    }
+    pub fn state(&self) -> EscrowState {
+        if !self.is_initialized {
+            EscrowState::Uninitialized
+        } else if self.is_settled {
+            EscrowState::Settled
+        } else if self.is_canceled {
+            EscrowState::Canceled
+        } else {
+            EscrowState::Active
+        }
+    }
+    pub fn summary(&self) -> EscrowSummary {
+        EscrowSummary {
+            state: self.state(),
+            is_disputed: self.is_disputed,
+            amount: self.amount,
+            fee: self.settlement_fee(),
+        }
+    }
+}
+
+/// Helpers letting tests set up and inspect escrow accounts without running instructions
+#[cfg(feature = "test-utils")]
+impl Escrow {
+    /// An active escrow as `InitEscrow` leaves it, every option disabled, the SPL token program
+    /// and the other token accounts left default. Recompute the checksum after changing the fields it covers.
+    pub fn new_active(
+        payer: Pubkey,
+        authority: Pubkey,
+        vault_token: Pubkey,
+        mint: Pubkey,
+        amount: u64,
+    ) -> Self {
+        EscrowBuilder::new()
+            .payer(payer)
+            .authority(authority)
+            .vault_token(vault_token)
+            .mint(mint)
+            .amount(amount)
+            .build()
+    }
+    /// Packs the escrow into a buffer the size of an escrow account
+    pub fn into_account_data(self) -> Vec<u8> {
+        let mut data = vec![0; Self::LEN];
+        Self::pack(self, &mut data).unwrap();
+        data
+    }
+    /// Reads the state back from account data, empty data reads as a closed account
+    pub fn state_of(data: &[u8]) -> Result<EscrowState, ProgramError> {
+        if data.is_empty() {
+            return Ok(EscrowState::Closed);
+        }
+        Ok(Self::unpack_unchecked(data)?.state())
+    }
+}
+
+/// Builds an active escrow for tests under the SPL token program, fields not set keep their
+/// default. `build` computes the checksum.
+#[cfg(feature = "test-utils")]
+pub struct EscrowBuilder {
+    escrow: Escrow,
+}
+
+#[cfg(feature = "test-utils")]
+impl Default for EscrowBuilder {
+    fn default() -> Self {
+        Self {
+            escrow: Escrow {
+                version: Escrow::current_version(),
+                token_program: spl_token::id(),
+                authority_bump: crate::find_program_authority(&crate::id()).1,
+                ..Escrow::default()
+            },
+        }
+    }
+}
+
+#[cfg(feature = "test-utils")]
+impl EscrowBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn payer(mut self, payer: Pubkey) -> Self {
+        self.escrow.payer = payer;
+        self
+    }
+    pub fn payer_token(mut self, payer_token: Pubkey) -> Self {
+        self.escrow.payer_token = payer_token;
+        self
+    }
+    pub fn payee_token(mut self, payee_token: Pubkey) -> Self {
+        self.escrow.payee_token = payee_token;
+        self
+    }
+    pub fn vault_token(mut self, vault_token: Pubkey) -> Self {
+        self.escrow.vault_token = vault_token;
+        self
+    }
+    pub fn authority(mut self, authority: Pubkey) -> Self {
+        self.escrow.authority = authority;
+        self
+    }
+    pub fn fee_token(mut self, fee_token: Pubkey) -> Self {
+        self.escrow.fee_token = fee_token;
+        self
+    }
+    pub fn mint(mut self, mint: Pubkey) -> Self {
+        self.escrow.mint = mint;
+        self
+    }
+    pub fn amount(mut self, amount: u64) -> Self {
+        self.escrow.amount = amount;
+        self
+    }
+    pub fn fee(mut self, fee: u64) -> Self {
+        self.escrow.fee = fee;
+        self
+    }
+    pub fn settled(mut self) -> Self {
+        self.escrow.is_settled = true;
+        self
+    }
+    pub fn canceled(mut self) -> Self {
+        self.escrow.is_canceled = true;
+        self
+    }
+    pub fn build(self) -> Escrow {
+        let mut escrow = self.escrow;
+        escrow.is_initialized = true;
+        escrow.checksum = escrow.compute_checksum();
+        escrow
+    }
 }
 
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 
+/// `pack_into_slice` panics on a buffer shorter than `Escrow::LEN`, go through `Pack::pack`,
+/// which checks the length. Accounts of an older version unpack and pack at their own length,
+/// see `Escrow::packed_len`, nothing reallocates them.
 impl Pack for Escrow {
-    const LEN: usize = 211;
+    const LEN: usize = 683;
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::check_packed_len(input)?;
+        Self::unpack_from_slice(input)
+    }
+    /// Packs into `Escrow::LEN` or the packed length of the escrow's version. An older layout
+    /// only keeps its own fields, a later field set on it fails rather than being dropped.
+    fn pack(src: Self, dst: &mut [u8]) -> ProgramResult {
+        if dst.len() == Self::LEN {
+            src.pack_into_slice(dst);
+            return Ok(());
+        }
+        match Self::packed_len(src.version) {
+            Some(len) if dst.len() == len => {
+                let mut layout = [0; Self::LEN];
+                src.pack_into_slice(&mut layout);
+                if layout[len..].iter().any(|byte| *byte != 0) {
+                    msg!(
+                        "Escrow layout version {} can't hold fields of later versions",
+                        src.version
+                    );
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                dst.copy_from_slice(&layout[..len]);
+                Ok(())
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let src = array_ref![src, 0, Escrow::LEN];
+        let layout = Escrow::current_layout(src)?;
+        let src = array_ref![layout, 0, Escrow::LEN];
         let (
+            version,
             is_initialized,
             is_settled,
             is_canceled,
@@ -45,7 +448,43 @@ impl Pack for Escrow {
             fee_token,
             amount,
             fee,
-        ) = array_refs![src, 1, 1, 1, 32, 32, 32, 32, 32, 32, 8, 8];
+            callback_program,
+            callback_optional,
+            mint,
+            max_abs_fee,
+            points_mint,
+            points_amount,
+            require_payer_on_cancel,
+            checksum,
+            restock_fee,
+            restock_token,
+            derived_vault,
+            settle_not_before,
+            settle_not_after,
+            settle_memo,
+            rotation_count,
+            condition_account,
+            condition_owner,
+            fee_bps,
+            token_program,
+            counterparty_mint,
+            counterparty_amount,
+            counterparty_token,
+            counterparty_vault,
+            counterparty_refund,
+            authority_bump,
+            expires_at,
+            resolved_at,
+            allow_balance_drift,
+            derived_escrow,
+            escrow_bump,
+            require_dual_sig,
+            is_disputed,
+            dispute_resolution,
+            dynamic_fee_recipient,
+        ) = array_refs![src, 1, 1, 1, 1, 32, 32, 32, 32, 32, 32, 8, 8, 32, 1, 32, 8, 32, 8, 1, 8, 8, 32, 1, 8, 8, 32, 2, 32, 32, 2, 32, 32, 8, 32, 32, 32, 1, 8, 8, 1, 1, 1, 1, 1, 1, 1];
+        // Checked by `current_layout`
+        let version = version[0];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
@@ -61,7 +500,48 @@ impl Pack for Escrow {
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        let callback_optional = match callback_optional {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let require_payer_on_cancel = match require_payer_on_cancel {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let derived_vault = match derived_vault {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let allow_balance_drift = match allow_balance_drift {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let derived_escrow = match derived_escrow {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let require_dual_sig = match require_dual_sig {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let is_disputed = match is_disputed {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let dynamic_fee_recipient = match dynamic_fee_recipient {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
         Ok(Escrow {
+            version,
             is_initialized,
             is_settled,
             is_canceled,
@@ -77,12 +557,47 @@ impl Pack for Escrow {
             fee_token: Pubkey::new_from_array(*fee_token),
             amount: u64::from_le_bytes(*amount),
             fee: u64::from_le_bytes(*fee),
+            callback_program: Pubkey::new_from_array(*callback_program),
+            callback_optional,
+            mint: Pubkey::new_from_array(*mint),
+            max_abs_fee: u64::from_le_bytes(*max_abs_fee),
+            points_mint: Pubkey::new_from_array(*points_mint),
+            points_amount: u64::from_le_bytes(*points_amount),
+            require_payer_on_cancel,
+            checksum: u64::from_le_bytes(*checksum),
+            restock_fee: u64::from_le_bytes(*restock_fee),
+            restock_token: Pubkey::new_from_array(*restock_token),
+            derived_vault,
+            settle_not_before: i64::from_le_bytes(*settle_not_before),
+            settle_not_after: i64::from_le_bytes(*settle_not_after),
+            settle_memo: *settle_memo,
+            rotation_count: u16::from_le_bytes(*rotation_count),
+            condition_account: Pubkey::new_from_array(*condition_account),
+            condition_owner: Pubkey::new_from_array(*condition_owner),
+            fee_bps: u16::from_le_bytes(*fee_bps),
+            token_program: Pubkey::new_from_array(*token_program),
+            counterparty_mint: Pubkey::new_from_array(*counterparty_mint),
+            counterparty_amount: u64::from_le_bytes(*counterparty_amount),
+            counterparty_token: Pubkey::new_from_array(*counterparty_token),
+            counterparty_vault: Pubkey::new_from_array(*counterparty_vault),
+            counterparty_refund: Pubkey::new_from_array(*counterparty_refund),
+            authority_bump: authority_bump[0],
+            expires_at: i64::from_le_bytes(*expires_at),
+            resolved_at: i64::from_le_bytes(*resolved_at),
+            allow_balance_drift,
+            derived_escrow,
+            escrow_bump: escrow_bump[0],
+            require_dual_sig,
+            is_disputed,
+            dispute_resolution: dispute_resolution[0],
+            dynamic_fee_recipient,
         })
     }
 
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let dst = array_mut_ref![dst, 0, Escrow::LEN];
         let (
+            version_dst,
             is_initialized_dst,
             is_settled_dst,
             is_canceled_dst,
@@ -94,9 +609,44 @@ impl Pack for Escrow {
             fee_taker_pubkey_dst,
             expected_amount_dst,
             expected_fees_dst,
-        ) = mut_array_refs![dst, 1, 1, 1, 32, 32, 32, 32, 32, 32, 8, 8];
+            callback_program_dst,
+            callback_optional_dst,
+            mint_dst,
+            max_abs_fee_dst,
+            points_mint_dst,
+            points_amount_dst,
+            require_payer_on_cancel_dst,
+            checksum_dst,
+            restock_fee_dst,
+            restock_token_dst,
+            derived_vault_dst,
+            settle_not_before_dst,
+            settle_not_after_dst,
+            settle_memo_dst,
+            rotation_count_dst,
+            condition_account_dst,
+            condition_owner_dst,
+            fee_bps_dst,
+            token_program_dst,
+            counterparty_mint_dst,
+            counterparty_amount_dst,
+            counterparty_token_dst,
+            counterparty_vault_dst,
+            counterparty_refund_dst,
+            authority_bump_dst,
+            expires_at_dst,
+            resolved_at_dst,
+            allow_balance_drift_dst,
+            derived_escrow_dst,
+            escrow_bump_dst,
+            require_dual_sig_dst,
+            is_disputed_dst,
+            dispute_resolution_dst,
+            dynamic_fee_recipient_dst,
+        ) = mut_array_refs![dst, 1, 1, 1, 1, 32, 32, 32, 32, 32, 32, 8, 8, 32, 1, 32, 8, 32, 8, 1, 8, 8, 32, 1, 8, 8, 32, 2, 32, 32, 2, 32, 32, 8, 32, 32, 32, 1, 8, 8, 1, 1, 1, 1, 1, 1, 1];
 
         let Escrow {
+            version,
             is_initialized,
             is_settled,
             is_canceled,
@@ -108,8 +658,43 @@ impl Pack for Escrow {
             fee_token,
             amount,
             fee,
+            callback_program,
+            callback_optional,
+            mint,
+            max_abs_fee,
+            points_mint,
+            points_amount,
+            require_payer_on_cancel,
+            checksum,
+            restock_fee,
+            restock_token,
+            derived_vault,
+            settle_not_before,
+            settle_not_after,
+            settle_memo,
+            rotation_count,
+            condition_account,
+            condition_owner,
+            fee_bps,
+            token_program,
+            counterparty_mint,
+            counterparty_amount,
+            counterparty_token,
+            counterparty_vault,
+            counterparty_refund,
+            authority_bump,
+            expires_at,
+            resolved_at,
+            allow_balance_drift,
+            derived_escrow,
+            escrow_bump,
+            require_dual_sig,
+            is_disputed,
+            dispute_resolution,
+            dynamic_fee_recipient,
         } = self;
 
+        version_dst[0] = *version;
         is_initialized_dst[0] = *is_initialized as u8;
         is_settled_dst[0] = *is_settled as u8;
         is_canceled_dst[0] = *is_canceled as u8;
@@ -124,6 +709,40 @@ impl Pack for Escrow {
         fee_taker_pubkey_dst.copy_from_slice(fee_token.as_ref());
         *expected_amount_dst = amount.to_le_bytes();
         *expected_fees_dst = fee.to_le_bytes();
+        callback_program_dst.copy_from_slice(callback_program.as_ref());
+        callback_optional_dst[0] = *callback_optional as u8;
+        mint_dst.copy_from_slice(mint.as_ref());
+        *max_abs_fee_dst = max_abs_fee.to_le_bytes();
+        points_mint_dst.copy_from_slice(points_mint.as_ref());
+        *points_amount_dst = points_amount.to_le_bytes();
+        require_payer_on_cancel_dst[0] = *require_payer_on_cancel as u8;
+        *checksum_dst = checksum.to_le_bytes();
+        *restock_fee_dst = restock_fee.to_le_bytes();
+        restock_token_dst.copy_from_slice(restock_token.as_ref());
+        derived_vault_dst[0] = *derived_vault as u8;
+        *settle_not_before_dst = settle_not_before.to_le_bytes();
+        *settle_not_after_dst = settle_not_after.to_le_bytes();
+        settle_memo_dst.copy_from_slice(settle_memo);
+        *rotation_count_dst = rotation_count.to_le_bytes();
+        condition_account_dst.copy_from_slice(condition_account.as_ref());
+        condition_owner_dst.copy_from_slice(condition_owner.as_ref());
+        *fee_bps_dst = fee_bps.to_le_bytes();
+        token_program_dst.copy_from_slice(token_program.as_ref());
+        counterparty_mint_dst.copy_from_slice(counterparty_mint.as_ref());
+        *counterparty_amount_dst = counterparty_amount.to_le_bytes();
+        counterparty_token_dst.copy_from_slice(counterparty_token.as_ref());
+        counterparty_vault_dst.copy_from_slice(counterparty_vault.as_ref());
+        counterparty_refund_dst.copy_from_slice(counterparty_refund.as_ref());
+        authority_bump_dst[0] = *authority_bump;
+        *expires_at_dst = expires_at.to_le_bytes();
+        *resolved_at_dst = resolved_at.to_le_bytes();
+        allow_balance_drift_dst[0] = *allow_balance_drift as u8;
+        derived_escrow_dst[0] = *derived_escrow as u8;
+        escrow_bump_dst[0] = *escrow_bump;
+        require_dual_sig_dst[0] = *require_dual_sig as u8;
+        is_disputed_dst[0] = *is_disputed as u8;
+        dispute_resolution_dst[0] = *dispute_resolution;
+        dynamic_fee_recipient_dst[0] = *dynamic_fee_recipient as u8;
     }
 }
 
@@ -3,13 +3,17 @@
 use solana_program::{
     account_info::AccountInfo,
     entrypoint::ProgramResult,
+    instruction::Instruction,
+    msg,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    sysvar::{rent::Rent},
+    system_program,
+    sysvar::{self, rent::Rent},
 };
+use spl_token::state::Account as TokenAccount;
 
-use crate::error::EscrowError;
+use crate::{error::EscrowError, find_vault_address, state::Escrow};
 
 
 /// Assert uninitialized
@@ -39,6 +43,68 @@ pub fn assert_owned_by(account: &AccountInfo, owner: &Pubkey) -> ProgramResult {
     }
 }
 
+/// Assert the account belongs to either token program, see `assert_supported_token_program`
+pub fn assert_owned_by_token_program(account: &AccountInfo) -> ProgramResult {
+    if is_token_program(account.owner) {
+        Ok(())
+    } else {
+        Err(ProgramError::IllegalOwner)
+    }
+}
+
+/// Whether the key is the classic token program or Token-2022
+pub fn is_token_program(key: &Pubkey) -> bool {
+    *key == spl_token::id() || *key == spl_token_2022::id()
+}
+
+/// Assert the token program is one the escrow can hold accounts of. Token-2022 accounts
+/// without extensions share the classic layout, so both unpack as `spl_token` accounts, see
+/// `assert_no_extensions`
+pub fn assert_supported_token_program(token_program_info: &AccountInfo) -> ProgramResult {
+    if is_token_program(token_program_info.key) {
+        Ok(())
+    } else {
+        msg!("Unsupported token program {}", token_program_info.key);
+        Err(ProgramError::IncorrectProgramId)
+    }
+}
+
+/// Send a token instruction built for the classic program to `token_program` instead,
+/// Token-2022 keeps the classic encoding for every instruction the escrow issues
+pub fn token_instruction(
+    token_program: &Pubkey,
+    instruction: Result<Instruction, ProgramError>,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction = instruction?;
+    instruction.program_id = *token_program;
+    Ok(instruction)
+}
+
+/// Assert the payer co-signs when the escrow requires both the authority and payer to settle
+pub fn assert_dual_sig(escrow: &Escrow, accounts: &[AccountInfo]) -> ProgramResult {
+    if !escrow.require_dual_sig {
+        return Ok(());
+    }
+    if accounts
+        .iter()
+        .any(|account| account.is_signer && *account.key == escrow.payer)
+    {
+        Ok(())
+    } else {
+        msg!("Payer {} must co-sign the settlement", escrow.payer);
+        Err(ProgramError::MissingRequiredSignature)
+    }
+}
+
+/// Assert the signer is the escrow's authority
+pub fn assert_authority(authority_info: &AccountInfo, authority: &Pubkey) -> ProgramResult {
+    if *authority_info.key != *authority {
+        Err(EscrowError::InvalidAuthority.into())
+    } else {
+        Ok(())
+    }
+}
+
 /// Assert account key
 pub fn assert_account_key(account_info: &AccountInfo, key: &Pubkey) -> ProgramResult {
     if *account_info.key != *key {
@@ -48,6 +114,124 @@ pub fn assert_account_key(account_info: &AccountInfo, key: &Pubkey) -> ProgramRe
     }
 }
 
+/// Assert the settlement condition account is the configured one, exists and, when an owner
+/// is configured, is owned by it
+pub fn assert_condition_met(
+    condition_info: &AccountInfo,
+    condition_account: &Pubkey,
+    condition_owner: &Pubkey,
+) -> ProgramResult {
+    assert_account_key(condition_info, condition_account)?;
+    if condition_info.lamports() == 0 {
+        msg!("Condition account {} doesn't exist", condition_info.key);
+        return Err(EscrowError::ConditionNotMet.into());
+    }
+    if *condition_owner != Pubkey::default() && condition_info.owner != condition_owner {
+        msg!(
+            "Condition account is owned by {}, expected {}",
+            condition_info.owner,
+            condition_owner
+        );
+        return Err(EscrowError::ConditionNotMet.into());
+    }
+    Ok(())
+}
+
+/// Assert the token program is the one the escrow was initialized with
+pub fn assert_token_program(token_program_info: &AccountInfo, token_program: &Pubkey) -> ProgramResult {
+    if token_program_info.key != token_program {
+        msg!(
+            "Token program {} doesn't match the escrow's {}",
+            token_program_info.key,
+            token_program
+        );
+        return Err(EscrowError::TokenProgramMismatch.into());
+    }
+    Ok(())
+}
+
+/// Assert the fee isn't paid to the program authority, nothing moves funds out of it
+pub fn assert_fee_recipient(
+    fee_token_info: &AccountInfo,
+    recipient: &Pubkey,
+    program_authority: &Pubkey,
+) -> ProgramResult {
+    if recipient == program_authority {
+        msg!("Fee token {} belongs to the program authority", fee_token_info.key);
+        return Err(EscrowError::InvalidFeeToken.into());
+    }
+    Ok(())
+}
+
+/// Assert the fee token account is still an initialized account of the escrow's token program,
+/// it may have been closed since init
+pub fn assert_fee_token_open(fee_token_info: &AccountInfo, token_program: &Pubkey) -> ProgramResult {
+    if fee_token_info.owner != token_program
+        || assert_initialized::<TokenAccount>(fee_token_info).is_err()
+    {
+        msg!("Fee token {} is closed or not a token account", fee_token_info.key);
+        return Err(EscrowError::InvalidFeeToken.into());
+    }
+    Ok(())
+}
+
+/// Assert a recipient of wrapped SOL lamports is a system account, a wallet that doesn't exist
+/// yet included. Crediting a token or program account would strand the lamports.
+pub fn assert_native_recipient(recipient_info: &AccountInfo) -> ProgramResult {
+    if *recipient_info.owner != system_program::id() {
+        msg!(
+            "Recipient {} is owned by {}, not the system program",
+            recipient_info.key,
+            recipient_info.owner
+        );
+        return Err(EscrowError::InvalidNativeRecipient.into());
+    }
+    Ok(())
+}
+
+/// Assert the escrow account wasn't closed, closing releases its data
+pub fn assert_escrow_open(escrow_info: &AccountInfo) -> ProgramResult {
+    if escrow_info.data_is_empty() {
+        msg!("Escrow {} is closed", escrow_info.key);
+        return Err(EscrowError::EscrowClosed.into());
+    }
+    Ok(())
+}
+
+/// Assert the vault no longer holds funds, either closed or an empty token account
+pub fn assert_vault_drained(vault_info: &AccountInfo) -> ProgramResult {
+    if !is_token_program(vault_info.owner) {
+        return Ok(());
+    }
+    if let Ok(vault_token) = TokenAccount::unpack(&vault_info.data.borrow()) {
+        if vault_token.amount > 0 {
+            msg!("Vault {} still holds {}", vault_info.key, vault_token.amount);
+            return Err(EscrowError::FundsStillLocked.into());
+        }
+    }
+    Ok(())
+}
+
+/// Assert the vault still holds the mint recorded at init, catching a vault reinitialized
+/// under the same key
+pub fn assert_vault_mint_unchanged(vault_token: &TokenAccount, mint: &Pubkey) -> ProgramResult {
+    if vault_token.mint != *mint {
+        msg!("Vault mint changed, expected {} got {}", mint, vault_token.mint);
+        return Err(EscrowError::VaultMintChanged.into());
+    }
+    Ok(())
+}
+
+/// Assert the vault is the one derived from the escrow key
+pub fn assert_derived_vault(
+    vault_info: &AccountInfo,
+    escrow: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (vault_address, _bump_seed) = find_vault_address(program_id, escrow);
+    assert_account_key(vault_info, &vault_address)
+}
+
 /// Assert account rent exempt
 pub fn assert_rent_exempt(rent: &Rent, account_info: &AccountInfo) -> ProgramResult {
     if !rent.is_exempt(account_info.lamports(), account_info.data_len()) {
@@ -67,4 +251,150 @@ pub fn assert_initialized<T: Pack + IsInitialized>(
     } else {
         Ok(account)
     }
-}
\ No newline at end of file
+}
+
+/// Assert the account is an initialized token account of `token_program`, with the given key
+/// and mint when set, and return it. A wrong key is reported before anything else.
+pub fn assert_token_account(
+    account_info: &AccountInfo,
+    token_program: &Pubkey,
+    mint: Option<&Pubkey>,
+    key: Option<&Pubkey>,
+) -> Result<TokenAccount, ProgramError> {
+    if let Some(key) = key {
+        assert_account_key(account_info, key)?;
+    }
+    assert_owned_by(account_info, token_program)?;
+    assert_no_extensions(account_info)?;
+    let token_account: TokenAccount = assert_initialized(account_info)?;
+    if let Some(mint) = mint {
+        assert_mint_matches(&token_account, mint)?;
+    }
+    Ok(token_account)
+}
+
+/// Assert a token account has the classic layout. A Token-2022 account carrying extensions is
+/// longer and is rejected rather than misread.
+pub fn assert_no_extensions(account_info: &AccountInfo) -> ProgramResult {
+    if account_info.data_len() > TokenAccount::LEN {
+        msg!("Token account {} has extensions", account_info.key);
+        return Err(EscrowError::TokenExtensionsUnsupported.into());
+    }
+    Ok(())
+}
+
+/// Unpack a token account of either token program, see `assert_no_extensions`
+pub fn unpack_token_account(account_info: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    assert_no_extensions(account_info)?;
+    TokenAccount::unpack(&account_info.data.borrow())
+}
+
+/// Assert token account mint
+pub fn assert_mint_matches(token_account: &TokenAccount, mint: &Pubkey) -> ProgramResult {
+    if token_account.mint != *mint {
+        Err(EscrowError::MintMismatch.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Assert the vault still holds the escrowed amount, unless the escrow allows balance drift
+pub fn assert_vault_balance(escrow: &Escrow, vault_token: &TokenAccount) -> ProgramResult {
+    if escrow.allow_balance_drift || vault_token.amount == escrow.amount {
+        Ok(())
+    } else {
+        msg!(
+            "Vault holds {}, the escrowed amount is {}",
+            vault_token.amount,
+            escrow.amount
+        );
+        Err(EscrowError::VaultBalanceMismatch.into())
+    }
+}
+
+/// Assert the vault is a pristine token account owned by `owner`
+pub fn assert_fresh_vault(vault_token: &TokenAccount, owner: &Pubkey, amount: u64) -> ProgramResult {
+    if vault_token.owner != *owner
+        || vault_token.amount != amount
+        || vault_token.delegate.is_some()
+        || vault_token.close_authority.is_some()
+    {
+        Err(EscrowError::VaultNotFresh.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Assert the authority isn't a well-known program id, which could never sign to resolve the escrow
+pub fn assert_valid_authority(authority: &Pubkey, program_id: &Pubkey) -> ProgramResult {
+    let denied = [
+        system_program::id(),
+        spl_token::id(),
+        spl_token_2022::id(),
+        sysvar::rent::id(),
+        *program_id,
+    ];
+    if denied.contains(authority) {
+        Err(EscrowError::InvalidAuthorityId.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Maps the error of a CPI to `error`, logging the underlying error.
+/// On chain the runtime aborts a program whose CPI fails and the callee's error surfaces as is,
+/// these errors only show up under native execution such as program-test.
+pub fn map_cpi_error(result: ProgramResult, error: EscrowError) -> ProgramResult {
+    result.map_err(|err| {
+        msg!("{}: {}", error, err);
+        error.into()
+    })
+}
+
+/// Logs the escrow key alongside a failure, attributing errors in multi-escrow transactions
+pub fn log_escrow_error<T>(
+    escrow_key: Option<&Pubkey>,
+    result: Result<T, ProgramError>,
+) -> Result<T, ProgramError> {
+    if let (Some(escrow_key), Err(err)) = (escrow_key, &result) {
+        msg!("Escrow {} failed: {}", escrow_key, err);
+    }
+    result
+}
+
+/// Sum of the amount and fee paid out of one source, checked against overflow
+pub fn safe_total(amount: u64, fee: u64) -> Result<u64, EscrowError> {
+    amount.checked_add(fee).ok_or(EscrowError::AmountOverflow)
+}
+
+/// Validates `InitEscrow` parameters, shared with clients so both apply the same rules. The fee
+/// may equal the amount, settlement then pays everything to the fee recipient and nothing to the
+/// payee.
+///
+/// The vault, payer and payee token accounts must all differ, and the fee can't go to the
+/// payer token account, where it would read as part of a refund. The fee may go to the payee
+/// token account, as when the payee charges the fee itself, both only receive on settlement.
+pub fn validate_init_params(
+    amount: u64,
+    fee: u64,
+    vault_token: &Pubkey,
+    payer_token: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+) -> Result<(), EscrowError> {
+    if amount == 0 {
+        return Err(EscrowError::ZeroAmount);
+    }
+    if fee > amount {
+        return Err(EscrowError::FeeOverflow);
+    }
+    if payer_token == payee_token
+        || payer_token == fee_token
+        || vault_token == payer_token
+        || vault_token == payee_token
+        || vault_token == fee_token
+    {
+        return Err(EscrowError::DuplicateTokenAccount);
+    }
+    Ok(())
+}
@@ -0,0 +1,164 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::instruction::{
+    AccountSpec, CANCEL_ACCOUNTS, CHANGE_AUTHORITY_ACCOUNTS, CHECK_AUTHORIZATION_ACCOUNTS, CLOSE_ACCOUNTS, CLOSE_MANY_TO_TREASURY_ACCOUNTS,
+    DEPOSIT_COUNTERPARTY_ACCOUNTS,
+    FORCE_RECLAIM_ACCOUNTS, INIT_ESCROW_ACCOUNTS, LOG_ESCROW_ACCOUNTS, RECLAIM_ACCOUNTS, SETTLE_ACCOUNTS,
+    SETTLE_MANY_ACCOUNTS, SETTLE_PARTIAL_ACCOUNTS, TOP_UP_ACCOUNTS,
+    RAISE_DISPUTE_ACCOUNTS, RESOLVE_DISPUTE_ACCOUNTS, SETTLE_SPLIT_ACCOUNTS,
+    CLAIM_FEE_ACCOUNTS, CANCEL_PARTIAL_ACCOUNTS, SETTLE_AND_CLOSE_ACCOUNTS, CANCEL_UNWRAP_ACCOUNTS,
+    SETTLE_WITH_MINT_ACCOUNTS,
+    UPDATE_FEE_TOKEN_ACCOUNTS,
+};
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+const SPECS: &[(&str, &[AccountSpec])] = &[
+    ("INIT_ESCROW_ACCOUNTS", INIT_ESCROW_ACCOUNTS),
+    ("SETTLE_ACCOUNTS", SETTLE_ACCOUNTS),
+    ("CANCEL_ACCOUNTS", CANCEL_ACCOUNTS),
+    ("CLOSE_ACCOUNTS", CLOSE_ACCOUNTS),
+    ("CLOSE_MANY_TO_TREASURY_ACCOUNTS", CLOSE_MANY_TO_TREASURY_ACCOUNTS),
+    ("FORCE_RECLAIM_ACCOUNTS", FORCE_RECLAIM_ACCOUNTS),
+    ("UPDATE_FEE_TOKEN_ACCOUNTS", UPDATE_FEE_TOKEN_ACCOUNTS),
+    ("SETTLE_WITH_MINT_ACCOUNTS", SETTLE_WITH_MINT_ACCOUNTS),
+    ("SETTLE_MANY_ACCOUNTS", SETTLE_MANY_ACCOUNTS),
+    ("CHECK_AUTHORIZATION_ACCOUNTS", CHECK_AUTHORIZATION_ACCOUNTS),
+    ("LOG_ESCROW_ACCOUNTS", LOG_ESCROW_ACCOUNTS),
+    ("DEPOSIT_COUNTERPARTY_ACCOUNTS", DEPOSIT_COUNTERPARTY_ACCOUNTS),
+    ("RECLAIM_ACCOUNTS", RECLAIM_ACCOUNTS),
+    ("SETTLE_PARTIAL_ACCOUNTS", SETTLE_PARTIAL_ACCOUNTS),
+    ("CHANGE_AUTHORITY_ACCOUNTS", CHANGE_AUTHORITY_ACCOUNTS),
+    ("TOP_UP_ACCOUNTS", TOP_UP_ACCOUNTS),
+    ("RAISE_DISPUTE_ACCOUNTS", RAISE_DISPUTE_ACCOUNTS),
+    ("RESOLVE_DISPUTE_ACCOUNTS", RESOLVE_DISPUTE_ACCOUNTS),
+    ("SETTLE_SPLIT_ACCOUNTS", SETTLE_SPLIT_ACCOUNTS),
+    ("CLAIM_FEE_ACCOUNTS", CLAIM_FEE_ACCOUNTS),
+    ("CANCEL_PARTIAL_ACCOUNTS", CANCEL_PARTIAL_ACCOUNTS),
+    ("SETTLE_AND_CLOSE_ACCOUNTS", SETTLE_AND_CLOSE_ACCOUNTS),
+    ("CANCEL_UNWRAP_ACCOUNTS", CANCEL_UNWRAP_ACCOUNTS),
+];
+
+/// The numbered account list of the variant whose docs reference `spec_name`
+fn documented_accounts(spec_name: &str) -> Vec<(usize, bool, bool)> {
+    let source = include_str!("../src/instruction.rs");
+    let reference = format!("[`{}`]", spec_name);
+    source
+        .lines()
+        .skip_while(|line| !(line.contains("/// Accounts expected") && line.contains(&reference)))
+        .skip(1)
+        .map(str::trim)
+        .take_while(|line| line.starts_with("///"))
+        .filter_map(|line| {
+            let line = line.trim_start_matches("///").trim();
+            let (index, rest) = line.split_once(". `[")?;
+            let (flags, _) = rest.split_once("]`")?;
+            Some((
+                index.parse().ok()?,
+                flags.contains("signer"),
+                flags.contains("writable"),
+            ))
+        })
+        .collect()
+}
+
+#[test]
+fn docs_match_specs() {
+    for (name, specs) in SPECS {
+        let documented = documented_accounts(name);
+        assert!(documented.len() >= specs.len(), "{} is not documented", name);
+        for (position, spec) in specs.iter().enumerate() {
+            assert_eq!(spec.index, position, "{} {}", name, spec.name);
+            assert_eq!(
+                documented[position],
+                (spec.index, spec.is_signer, spec.is_writable),
+                "{} {}",
+                name,
+                spec.name
+            );
+        }
+    }
+}
+
+/// Runs `ix` without its last account, which must be the last required one
+async fn process_truncated(
+    context: &mut ProgramTestContext,
+    mut ix: Instruction,
+    specs: &[AccountSpec],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    assert_eq!(ix.accounts.len(), specs.len());
+    ix.accounts.pop();
+    process(context, &[ix], signers).await
+}
+
+fn assert_not_enough_accounts(result: Result<(), TransportError>) {
+    let expected = InstructionError::from(u64::from(ProgramError::NotEnoughAccountKeys));
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(0, error))) => {
+            assert_eq!(error, expected)
+        }
+        other => panic!("expected NotEnoughAccountKeys, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn processor_reads_every_required_account() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let fee_payer = Pubkey::new_unique();
+
+    let settle = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &fee_payer,
+    );
+    let result = process_truncated(&mut context, settle, SETTLE_ACCOUNTS, &[&keys.authority]).await;
+    assert_not_enough_accounts(result);
+
+    let cancel = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer.pubkey(),
+        &fee_payer,
+        &keys.vault.pubkey(),
+    );
+    let result = process_truncated(&mut context, cancel, CANCEL_ACCOUNTS, &[&keys.authority]).await;
+    assert_not_enough_accounts(result);
+
+    let init = init_escrow_ix(
+        &keys.payer.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        1_000_000_000,
+        0,
+    );
+    let result = process_truncated(
+        &mut context,
+        init,
+        INIT_ESCROW_ACCOUNTS,
+        &[&keys.payer, &keys.authority],
+    )
+    .await;
+    assert_not_enough_accounts(result);
+
+    let close = close_ix(&keys.authority.pubkey(), &keys.escrow.pubkey(), &fee_payer);
+    let result = process_truncated(&mut context, close, CLOSE_ACCOUNTS, &[&keys.authority]).await;
+    assert_not_enough_accounts(result);
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, id, state::EscrowState};
+use solana_program::{system_program, sysvar};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+#[tokio::test]
+async fn well_known_program_ids_cant_be_the_authority() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let (payer, vault, escrow) = (Keypair::new(), Keypair::new(), Keypair::new());
+    let (payee, fee_taker) = (Keypair::new(), Keypair::new());
+    fund(&mut context, &payer.pubkey(), rent.minimum_balance(0)).await;
+    create_native_vault(&mut context, &vault, &payer.pubkey(), 1_000).await;
+    create_escrow_account(&mut context, &escrow).await;
+
+    let denied = [
+        system_program::id(),
+        spl_token::id(),
+        spl_token_2022::id(),
+        sysvar::rent::id(),
+        id(),
+    ];
+    for authority in &denied {
+        let mut ix = init_escrow_ix(
+            &payer.pubkey(),
+            &vault.pubkey(),
+            authority,
+            &escrow.pubkey(),
+            &payer.pubkey(),
+            &payee.pubkey(),
+            &fee_taker.pubkey(),
+            1_000,
+            0,
+        );
+        // None of them can sign, the id is rejected before the signature is checked
+        ix.accounts[2].is_signer = false;
+        let result = process(&mut context, &[ix], &[&payer]).await;
+        assert_custom_error(result, EscrowError::InvalidAuthorityId);
+    }
+    assert_eq!(
+        get_escrow_state(&mut context, &escrow.pubkey()).await,
+        EscrowState::Uninitialized
+    );
+}
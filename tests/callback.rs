@@ -0,0 +1,162 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{instruction::InitOptions, state::Escrow};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_program_test::{processor, tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use std::convert::TryInto;
+use utils::*;
+
+const AMOUNT: u64 = 1_000;
+const FEE: u64 = 10;
+
+/// Accepts only the payload of a settlement of `AMOUNT` with a fee of `FEE`, once the escrow
+/// reads as settled and is passed the way it is on chain
+fn mock_callback(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let escrow_info = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if !escrow_info.is_writable || escrow_info.is_signer {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if data.len() != 48 || data[..32] != escrow_info.key.to_bytes() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let amount = u64::from_le_bytes(data[32..40].try_into().unwrap());
+    let fee = u64::from_le_bytes(data[40..].try_into().unwrap());
+    let escrow = Escrow::unpack(&escrow_info.data.borrow())?;
+    if (amount, fee) != (AMOUNT - FEE, FEE) || !escrow.is_settled {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn failing_callback(_program_id: &Pubkey, _accounts: &[AccountInfo], _: &[u8]) -> ProgramResult {
+    Err(ProgramError::Custom(7))
+}
+
+async fn start(callback: Pubkey, failing: bool) -> ProgramTestContext {
+    let mut program_test = program_test();
+    if failing {
+        program_test.add_program("failing_callback", callback, processor!(failing_callback));
+    } else {
+        program_test.add_program("mock_callback", callback, processor!(mock_callback));
+    }
+    program_test.start_with_context().await
+}
+
+async fn init(context: &mut ProgramTestContext, callback: &Pubkey, optional: bool) -> SplEscrow {
+    let keys = SplEscrow::default();
+    let (payee_owner, fee_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(context, &keys, &payee_owner, &fee_owner, AMOUNT).await;
+    let options = InitOptions {
+        callback_program: *callback,
+        callback_optional: optional,
+        ..InitOptions::default()
+    };
+    process(
+        context,
+        &[with_options(spl_init_ix(&keys, AMOUNT, FEE), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    keys
+}
+
+fn settle(keys: &SplEscrow, callback: Option<&Pubkey>) -> Instruction {
+    let mut ix = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+    );
+    ix.accounts
+        .extend(callback.map(|callback| AccountMeta::new_readonly(*callback, false)));
+    ix
+}
+
+#[tokio::test]
+async fn callback_receives_the_settlement() {
+    let callback = Pubkey::new_unique();
+    let mut context = start(callback, false).await;
+    let keys = init(&mut context, &callback, false).await;
+
+    process(&mut context, &[settle(&keys, Some(&callback))], &[&keys.authority])
+        .await
+        .unwrap();
+
+    assert_token_balances(
+        &mut context,
+        &[(keys.payee_token.pubkey(), AMOUNT - FEE), (keys.fee_token.pubkey(), FEE)],
+    )
+    .await;
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn failing_callback_reverts_the_settlement() {
+    let callback = Pubkey::new_unique();
+    let mut context = start(callback, true).await;
+    let keys = init(&mut context, &callback, true).await;
+
+    let result = process(&mut context, &[settle(&keys, Some(&callback))], &[&keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(7)
+        )))
+    ));
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, AMOUNT);
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn required_callback_must_be_supplied() {
+    let callback = Pubkey::new_unique();
+    let mut context = start(callback, false).await;
+    let keys = init(&mut context, &callback, false).await;
+
+    let result = process(&mut context, &[settle(&keys, None)], &[&keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::NotEnoughAccountKeys
+        )))
+    ));
+
+    // Another program in the callback slot is rejected
+    let other = Pubkey::new_unique();
+    let result = process(&mut context, &[settle(&keys, Some(&other))], &[&keys.authority]).await;
+    assert!(result.is_err());
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn optional_callback_can_be_skipped() {
+    let callback = Pubkey::new_unique();
+    let mut context = start(callback, true).await;
+    let keys = init(&mut context, &callback, true).await;
+
+    // The failing callback isn't invoked when it is left out
+    process(&mut context, &[settle(&keys, None)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
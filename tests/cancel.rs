@@ -0,0 +1,142 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::state::Escrow;
+use solana_program::{program_pack::Pack, system_instruction};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token::state::Account as TokenAccount;
+use utils::*;
+
+#[tokio::test]
+async fn native_cancel_refunds_exact_amount() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000_000_000;
+    let keys = init_native_escrow(&mut context, amount, 0).await;
+    let fee_payer = Keypair::new();
+
+    let payer_before = get_balance(&mut context, &keys.payer.pubkey()).await;
+    let escrow_before = get_balance(&mut context, &keys.escrow.pubkey()).await;
+    let vault_before = get_balance(&mut context, &keys.vault.pubkey()).await;
+    assert_eq!(vault_before, rent.minimum_balance(TokenAccount::LEN) + amount);
+
+    process(
+        &mut context,
+        &[cancel_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer.pubkey(),
+            &fee_payer.pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let payer_after = get_balance(&mut context, &keys.payer.pubkey()).await;
+    let escrow_after = get_balance(&mut context, &keys.escrow.pubkey()).await;
+    let vault_after = get_balance(&mut context, &keys.vault.pubkey()).await;
+    let fee_payer_after = get_balance(&mut context, &fee_payer.pubkey()).await;
+
+    assert_eq!(payer_after, payer_before + amount);
+    assert_eq!(escrow_after, escrow_before);
+    assert_eq!(escrow_after, rent.minimum_balance(Escrow::LEN));
+    assert_eq!(vault_after, 0);
+    assert_eq!(fee_payer_after, rent.minimum_balance(TokenAccount::LEN));
+    assert_eq!(
+        payer_before + escrow_before + vault_before,
+        payer_after + escrow_after + fee_payer_after
+    );
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert!(escrow.is_canceled);
+    assert!(escrow.resolved_at > 0);
+}
+
+#[tokio::test]
+async fn spl_cancel_transfers_tokens() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000;
+    let keys = init_spl_escrow(&mut context, amount, 10).await;
+    let fee_payer = Keypair::new();
+    let payer_lamports = get_balance(&mut context, &keys.payer_token.pubkey()).await;
+
+    process(
+        &mut context,
+        &[cancel_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer_token.pubkey(),
+            &fee_payer.pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut context, &keys.payer_token.pubkey()).await,
+        amount
+    );
+    assert_eq!(
+        get_balance(&mut context, &keys.payer_token.pubkey()).await,
+        payer_lamports
+    );
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        rent.minimum_balance(TokenAccount::LEN)
+    );
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
+
+#[tokio::test]
+async fn native_cancel_returns_vault_surplus_to_payer() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000_000_000;
+    let surplus = 5_000;
+    let keys = init_native_escrow(&mut context, amount, 0).await;
+    let fee_payer = Keypair::new();
+    // An external top-up the token program never synced
+    let top_up = system_instruction::transfer(
+        &context.payer.pubkey(),
+        &keys.vault.pubkey(),
+        surplus,
+    );
+    process(&mut context, &[top_up], &[]).await.unwrap();
+
+    let payer_before = get_balance(&mut context, &keys.payer.pubkey()).await;
+    let escrow_before = get_balance(&mut context, &keys.escrow.pubkey()).await;
+    process(
+        &mut context,
+        &[cancel_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer.pubkey(),
+            &fee_payer.pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_balance(&mut context, &keys.payer.pubkey()).await,
+        payer_before + amount + surplus
+    );
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        rent.minimum_balance(TokenAccount::LEN)
+    );
+    assert_eq!(
+        get_balance(&mut context, &keys.escrow.pubkey()).await,
+        escrow_before
+    );
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, id, instruction::cancel_partial};
+use solana_program::instruction::Instruction;
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+fn refund(keys: &SplEscrow, amount: u64) -> Instruction {
+    cancel_partial(
+        &id(),
+        &keys.authority.pubkey(),
+        &keys.payer_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        amount,
+    )
+}
+
+#[tokio::test]
+async fn partial_refund_then_settle_of_the_rest() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+
+    process(&mut context, &[refund(&keys, 400)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context, &keys.payer_token.pubkey()).await, 400);
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 600);
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.amount, 600);
+    assert!(!escrow.is_canceled);
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(get_token_balance(&mut context, &keys.payee_token.pubkey()).await, 590);
+    assert_eq!(get_token_balance(&mut context, &keys.fee_token.pubkey()).await, 10);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn partial_refund_then_full_cancel() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+
+    let result = process(&mut context, &[refund(&keys, 1_001)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AmountExceedsVault);
+
+    process(&mut context, &[refund(&keys, 250)], &[&keys.authority])
+        .await
+        .unwrap();
+    process(
+        &mut context,
+        &[cancel_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer_token.pubkey(),
+            &Keypair::new().pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(get_token_balance(&mut context, &keys.payer_token.pubkey()).await, 1_000);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+
+    let result = process(&mut context, &[refund(&keys, 100)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AccountAlreadyCanceled);
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::instruction::InitOptions;
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+async fn init(context: &mut ProgramTestContext, require_payer_on_cancel: bool) -> SplEscrow {
+    let keys = SplEscrow::default();
+    let (payee_owner, fee_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(context, &keys, &payee_owner, &fee_owner, 1_000).await;
+    let options = InitOptions {
+        require_payer_on_cancel,
+        ..InitOptions::default()
+    };
+    process(
+        context,
+        &[with_options(spl_init_ix(&keys, 1_000, 10), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    keys
+}
+
+/// Cancels under the authority, passing `payer` as the payer account when given
+fn cancel(keys: &SplEscrow, payer: Option<AccountMeta>) -> Instruction {
+    let mut ix = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &Keypair::new().pubkey(),
+        &keys.vault.pubkey(),
+    );
+    ix.accounts.extend(payer);
+    ix
+}
+
+fn instruction_error(result: Result<(), TransportError>) -> InstructionError {
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(0, err))) => err,
+        other => panic!("expected an instruction error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn authority_alone_cancels_by_default() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context, false).await;
+
+    process(&mut context, &[cancel(&keys, None)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context, &keys.payer_token.pubkey()).await, 1_000);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
+
+#[tokio::test]
+async fn flagged_escrow_needs_the_payer_signature() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context, true).await;
+    let payer = keys.payer.pubkey();
+
+    let result = process(&mut context, &[cancel(&keys, None)], &[&keys.authority]).await;
+    assert_eq!(instruction_error(result), InstructionError::NotEnoughAccountKeys);
+
+    let unsigned = AccountMeta::new_readonly(payer, false);
+    let result = process(&mut context, &[cancel(&keys, Some(unsigned))], &[&keys.authority]).await;
+    assert_eq!(instruction_error(result), InstructionError::MissingRequiredSignature);
+
+    let impostor = Keypair::new();
+    let signed = AccountMeta::new_readonly(impostor.pubkey(), true);
+    let ix = cancel(&keys, Some(signed));
+    let result = process(&mut context, &[ix], &[&keys.authority, &impostor]).await;
+    assert_eq!(instruction_error(result), InstructionError::InvalidArgument);
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+
+    let signed = AccountMeta::new_readonly(payer, true);
+    let ix = cancel(&keys, Some(signed));
+    process(&mut context, &[ix], &[&keys.authority, &keys.payer])
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context, &keys.payer_token.pubkey()).await, 1_000);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
@@ -0,0 +1,58 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, id, instruction::cancel_unwrap};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+#[tokio::test]
+async fn wrapped_sol_refund_lands_in_the_payer_wallet() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 10_000_000).await;
+    let payer = keys.payer.pubkey();
+    let before = get_balance(&mut context, &payer).await;
+
+    process(
+        &mut context,
+        &[cancel_unwrap(
+            &id(),
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &payer,
+            &Keypair::new().pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_balance(&mut context, &payer).await, before + 1_000_000_000);
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
+
+#[tokio::test]
+async fn token_vault_cant_be_unwrapped() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+
+    let result = process(
+        &mut context,
+        &[cancel_unwrap(
+            &id(),
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer_token.pubkey(),
+            &Keypair::new().pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::UnwrapNonNative);
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 1_000);
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
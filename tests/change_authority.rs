@@ -0,0 +1,69 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, id, instruction::change_authority};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+#[tokio::test]
+async fn new_authority_settles() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let new_authority = Keypair::new();
+
+    process(
+        &mut context,
+        &[change_authority(
+            &id(),
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &new_authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.authority, new_authority.pubkey());
+    assert_eq!(escrow.rotation_count, 1);
+    process(
+        &mut context,
+        &[settle_ix(
+            &new_authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&new_authority],
+    )
+    .await
+    .unwrap();
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn other_signer_cannot_change_authority() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let intruder = Keypair::new();
+
+    let result = process(
+        &mut context,
+        &[change_authority(
+            &id(),
+            &intruder.pubkey(),
+            &keys.escrow.pubkey(),
+            &intruder.pubkey(),
+        )],
+        &[&intruder],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::InvalidAuthority);
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.authority, keys.authority.pubkey());
+}
@@ -0,0 +1,142 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    id,
+    instruction::TAG_CHECK_AUTHORIZATION,
+    state::{Escrow, EscrowBuilder},
+};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use solana_program_test::{processor, tokio};
+use utils::*;
+
+const SETTLE: u8 = 0;
+const CANCEL: u8 = 1;
+
+/// Calls `CheckAuthorization` with the rest of its data and fails unless the returned byte
+/// equals the first one
+fn probe(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let (expected, check_data) = data.split_first().unwrap();
+    let ix = Instruction::new_with_bytes(
+        id(),
+        check_data,
+        vec![AccountMeta::new_readonly(*accounts[0].key, false)],
+    );
+    invoke(&ix, accounts)?;
+    match get_return_data() {
+        Some((program, value)) if program == id() && value == [*expected] => Ok(()),
+        _ => Err(ProgramError::Custom(0)),
+    }
+}
+
+fn check_ix(
+    probe_id: &Pubkey,
+    escrow: &Pubkey,
+    action: u8,
+    key: &Pubkey,
+    authorized: bool,
+) -> Instruction {
+    let mut data = vec![authorized as u8, TAG_CHECK_AUTHORIZATION, action];
+    data.extend_from_slice(key.as_ref());
+    Instruction::new_with_bytes(
+        *probe_id,
+        &data,
+        vec![
+            AccountMeta::new_readonly(*escrow, false),
+            AccountMeta::new_readonly(id(), false),
+        ],
+    )
+}
+
+#[tokio::test]
+async fn reports_authorization_per_key_and_state() {
+    let probe_id = Pubkey::new_unique();
+    let authority = Pubkey::new_unique();
+    let other = Pubkey::new_unique();
+    let active = Pubkey::new_unique();
+    let settled = Pubkey::new_unique();
+    let mint = spl_token::native_mint::id();
+
+    let mut program_test = program_test();
+    program_test.add_program("probe", probe_id, processor!(probe));
+    add_escrow(
+        &mut program_test,
+        &active,
+        Escrow::new_active(
+            Pubkey::new_unique(),
+            authority,
+            Pubkey::new_unique(),
+            mint,
+            1_000,
+        ),
+    );
+    add_escrow(
+        &mut program_test,
+        &settled,
+        EscrowBuilder::new()
+            .payer(Pubkey::new_unique())
+            .vault_token(Pubkey::new_unique())
+            .authority(authority)
+            .mint(mint)
+            .amount(1_000)
+            .settled()
+            .build(),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let cases = [
+        (active, SETTLE, authority, true),
+        (active, CANCEL, authority, true),
+        (active, SETTLE, other, false),
+        (active, CANCEL, other, false),
+        (settled, SETTLE, authority, false),
+        (settled, CANCEL, authority, false),
+    ];
+    for (escrow, action, key, authorized) in cases {
+        process(
+            &mut context,
+            &[check_ix(&probe_id, &escrow, action, &key, authorized)],
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn rejects_unknown_action() {
+    let probe_id = Pubkey::new_unique();
+    let escrow = Pubkey::new_unique();
+    let authority = Pubkey::new_unique();
+
+    let mut program_test = program_test();
+    program_test.add_program("probe", probe_id, processor!(probe));
+    add_escrow(
+        &mut program_test,
+        &escrow,
+        Escrow::new_active(
+            Pubkey::new_unique(),
+            authority,
+            Pubkey::new_unique(),
+            spl_token::native_mint::id(),
+            1_000,
+        ),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    assert!(process(
+        &mut context,
+        &[check_ix(&probe_id, &escrow, 2, &authority, true)],
+        &[],
+    )
+    .await
+    .is_err());
+}
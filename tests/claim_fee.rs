@@ -0,0 +1,106 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, id, instruction::claim_fee};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+fn claim(keys: &SplEscrow) -> Instruction {
+    claim_fee(
+        &id(),
+        &keys.authority.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn fee_is_claimed_once_and_not_charged_at_settle() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let fee_token = keys.fee_token.pubkey();
+
+    process(&mut context, &[claim(&keys)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context, &fee_token).await, 10);
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.fee, 0);
+    assert_eq!(escrow.amount, 990);
+    assert!(!escrow.is_settled);
+
+    // An unused account keeps the transaction distinct from the first claim
+    let mut again = claim(&keys);
+    again
+        .accounts
+        .push(AccountMeta::new_readonly(Pubkey::new_unique(), false));
+    let result = process(&mut context, &[again], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::ZeroAmount);
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &fee_token,
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(get_token_balance(&mut context, &keys.payee_token.pubkey()).await, 990);
+    assert_eq!(get_token_balance(&mut context, &fee_token).await, 10);
+}
+
+#[tokio::test]
+async fn fee_claim_is_checked() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+
+    let mut other_fee_token = claim(&keys);
+    other_fee_token.accounts[1].pubkey = keys.payee_token.pubkey();
+    let result = process(&mut context, &[other_fee_token], &[&keys.authority]).await;
+    assert!(result.is_err());
+
+    let intruder = Keypair::new();
+    let mut by_intruder = claim(&keys);
+    by_intruder.accounts[0].pubkey = intruder.pubkey();
+    let result = process(&mut context, &[by_intruder], &[&intruder]).await;
+    assert_custom_error(result, EscrowError::InvalidAuthority);
+
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 1_000);
+    assert_eq!(get_escrow(&mut context, &keys.escrow.pubkey()).await.fee, 10);
+}
+
+#[tokio::test]
+async fn settled_escrow_fee_cant_be_claimed() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let result = process(&mut context, &[claim(&keys)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AccountAlreadySettled);
+}
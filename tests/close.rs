@@ -0,0 +1,344 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    id,
+    state::{Escrow, EscrowBuilder, EscrowState},
+};
+use solana_program::{
+    instruction::{AccountMeta, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+};
+use solana_program_test::tokio;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+#[tokio::test]
+async fn close_settled_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let fee_payer = Keypair::new();
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Active
+    );
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &fee_payer.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Settled
+    );
+
+    let escrow_lamports = get_balance(&mut context, &keys.escrow.pubkey()).await;
+    let fee_payer_before = get_balance(&mut context, &fee_payer.pubkey()).await;
+    process(
+        &mut context,
+        &[close_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &fee_payer.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        fee_payer_before + escrow_lamports
+    );
+    assert_eq!(get_balance(&mut context, &keys.escrow.pubkey()).await, 0);
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Closed
+    );
+}
+
+#[tokio::test]
+async fn close_active_escrow_fails() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+
+    let result = process(
+        &mut context,
+        &[close_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::AccountNotSettledOrCanceled);
+}
+
+#[tokio::test]
+async fn close_with_funded_vault_fails() {
+    let authority = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let mut program_test = program_test();
+    add_vault(&mut program_test, &vault, &spl_token::native_mint::id(), 1_000);
+    add_escrow(
+        &mut program_test,
+        &escrow,
+        EscrowBuilder::new()
+            .authority(authority.pubkey())
+            .vault_token(vault)
+            .mint(spl_token::native_mint::id())
+            .amount(1_000)
+            .settled()
+            .build(),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let mut ix = close_ix(&authority.pubkey(), &escrow, &authority.pubkey());
+    ix.accounts.push(AccountMeta::new_readonly(vault, false));
+    let result = process(&mut context, &[ix], &[&authority]).await;
+    assert_custom_error(result, EscrowError::FundsStillLocked);
+    assert_eq!(
+        get_escrow_state(&mut context, &escrow).await,
+        EscrowState::Settled
+    );
+}
+
+#[tokio::test]
+async fn close_of_a_foreign_account_fails_before_reading_it() {
+    let authority = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let mut program_test = program_test();
+    add_vault(&mut program_test, &vault, &spl_token::native_mint::id(), 1_000);
+    add_escrow_owned_by(
+        &mut program_test,
+        &escrow,
+        EscrowBuilder::new()
+            .authority(authority.pubkey())
+            .vault_token(vault)
+            .settled()
+            .build(),
+        &system_program::id(),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let mut ix = close_ix(&authority.pubkey(), &escrow, &authority.pubkey());
+    ix.accounts.push(AccountMeta::new_readonly(vault, false));
+    let result = process(&mut context, &[ix], &[&authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::IllegalOwner
+        )))
+    ));
+}
+
+#[tokio::test]
+async fn close_with_closed_vault_succeeds() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let mut ix = close_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.authority.pubkey(),
+    );
+    ix.accounts
+        .push(AccountMeta::new_readonly(keys.vault.pubkey(), false));
+    process(&mut context, &[ix], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Closed
+    );
+}
+
+#[tokio::test]
+async fn settle_closed_escrow_fails() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let settle = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.authority.pubkey(),
+    );
+    process(
+        &mut context,
+        std::slice::from_ref(&settle),
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+    process(
+        &mut context,
+        &[close_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    // A memo keeps the transaction distinct from the first settle
+    let result = process(
+        &mut context,
+        &[with_memo(settle, &[1; 32])],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::EscrowClosed);
+}
+
+#[tokio::test]
+async fn close_by_other_signer_fails() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let intruder = Keypair::new();
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let result = process(
+        &mut context,
+        &[close_ix(
+            &intruder.pubkey(),
+            &keys.escrow.pubkey(),
+            &intruder.pubkey(),
+        )],
+        &[&intruder],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::InvalidAuthority);
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Settled
+    );
+}
+
+#[tokio::test]
+async fn double_close_in_one_transaction_fails() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let close = close_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.authority.pubkey(),
+    );
+    let result = process(&mut context, &[close.clone(), close], &[&keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(code),
+        ))) if code == EscrowError::AccountNotInitialized as u32
+    ));
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Settled
+    );
+}
+
+#[tokio::test]
+async fn close_escrow_of_an_older_layout() {
+    let authority = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let lamports = Rent::default().minimum_balance(Escrow::LEN);
+    let mut data = EscrowBuilder::new()
+        .authority(authority.pubkey())
+        .settled()
+        .build()
+        .into_account_data();
+    data[0] = 6;
+    data.truncate(Escrow::packed_len(6).unwrap());
+    let mut program_test = program_test();
+    program_test.add_account(
+        escrow,
+        Account {
+            lamports,
+            data,
+            owner: id(),
+            ..Account::default()
+        },
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let fee_payer = Pubkey::new_unique();
+    process(
+        &mut context,
+        &[close_ix(&authority.pubkey(), &escrow, &fee_payer)],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(get_balance(&mut context, &fee_payer).await, lamports);
+    assert_eq!(get_balance(&mut context, &escrow).await, 0);
+}
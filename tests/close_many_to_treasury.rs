@@ -0,0 +1,66 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    state::{Escrow, EscrowBuilder, EscrowState},
+    MAX_BATCH,
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+#[tokio::test]
+async fn resolved_escrows_close_into_the_treasury() {
+    let authority = Keypair::new();
+    let escrows = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+    let mut program_test = program_test();
+    let builder = || EscrowBuilder::new().authority(authority.pubkey()).amount(1_000);
+    add_escrow(&mut program_test, &escrows[0], builder().settled().build());
+    add_escrow(&mut program_test, &escrows[1], builder().canceled().build());
+    add_escrow(&mut program_test, &escrows[2], builder().settled().build());
+    let mut context = program_test.start_with_context().await;
+    let treasury = Pubkey::new_unique();
+
+    let ix = close_many_to_treasury_ix(&authority.pubkey(), &treasury, &escrows);
+    process(&mut context, &[ix], &[&authority]).await.unwrap();
+
+    assert_eq!(
+        get_balance(&mut context, &treasury).await,
+        3 * Rent::default().minimum_balance(Escrow::LEN)
+    );
+    for escrow in &escrows {
+        assert_eq!(get_escrow_state(&mut context, escrow).await, EscrowState::Closed);
+    }
+}
+
+#[tokio::test]
+async fn an_active_escrow_fails_the_whole_batch() {
+    let authority = Keypair::new();
+    let (settled, active) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut program_test = program_test();
+    let builder = || EscrowBuilder::new().authority(authority.pubkey()).amount(1_000);
+    add_escrow(&mut program_test, &settled, builder().settled().build());
+    add_escrow(&mut program_test, &active, builder().build());
+    let mut context = program_test.start_with_context().await;
+    let treasury = Pubkey::new_unique();
+
+    let ix = close_many_to_treasury_ix(&authority.pubkey(), &treasury, &[settled, active]);
+    let result = process(&mut context, &[ix], &[&authority]).await;
+    assert_custom_error(result, EscrowError::AccountNotSettledOrCanceled);
+    assert_eq!(get_balance(&mut context, &treasury).await, 0);
+    assert_eq!(get_escrow_state(&mut context, &settled).await, EscrowState::Settled);
+}
+
+#[tokio::test]
+async fn batch_over_max_batch_fails() {
+    let mut context = program_test().start_with_context().await;
+    let authority = Keypair::new();
+    let escrows: Vec<_> = (0..=MAX_BATCH).map(|_| Pubkey::new_unique()).collect();
+
+    let ix = close_many_to_treasury_ix(&authority.pubkey(), &Pubkey::new_unique(), &escrows);
+    let result = process(&mut context, &[ix], &[&authority]).await;
+    assert_custom_error(result, EscrowError::BatchTooLarge);
+}
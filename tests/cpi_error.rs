@@ -0,0 +1,76 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::error::EscrowError;
+use solana_program::{
+    instruction::Instruction, program_option::COption, program_pack::Pack, pubkey::Pubkey,
+};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token::state::Account as TokenAccount;
+use utils::*;
+
+fn settle(keys: &SplEscrow) -> Instruction {
+    settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn frozen_payee_token_fails_the_payee_transfer() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    freeze_token_account(&mut context, &keys.payee_token.pubkey()).await;
+
+    let result = process(&mut context, &[settle(&keys)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::PayeeTransferFailed);
+}
+
+#[tokio::test]
+async fn frozen_fee_token_fails_the_fee_transfer() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    freeze_token_account(&mut context, &keys.fee_token.pubkey()).await;
+
+    let result = process(&mut context, &[settle(&keys)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::FeeTransferFailed);
+}
+
+#[tokio::test]
+async fn foreign_close_authority_fails_the_vault_close() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    // Only the close authority may close the vault once one is set
+    let vault = keys.vault.pubkey();
+    let mut account = context.banks_client.get_account(vault).await.unwrap().unwrap();
+    let mut token = TokenAccount::unpack(&account.data).unwrap();
+    token.close_authority = COption::Some(Pubkey::new_unique());
+    TokenAccount::pack(token, &mut account.data).unwrap();
+    context.set_account(&vault, &account.into());
+
+    let result = process(&mut context, &[settle(&keys)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::VaultCloseFailed);
+}
+
+#[tokio::test]
+async fn frozen_payer_token_fails_the_refund() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    freeze_token_account(&mut context, &keys.payer_token.pubkey()).await;
+
+    let ix = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &Keypair::new().pubkey(),
+        &keys.vault.pubkey(),
+    );
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::RefundFailed);
+}
@@ -0,0 +1,158 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    find_escrow_address, find_program_authority, find_vault_address, id, instruction::InitOptions,
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::tokio;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+/// Turns a plain `InitEscrow` into one creating the escrow at `escrow`
+fn derive_escrow(mut ix: Instruction, escrow: &Pubkey) -> Instruction {
+    let options = InitOptions {
+        derived_escrow: true,
+        ..InitOptions::default()
+    };
+    ix.accounts[0].is_writable = true;
+    ix.accounts[3].pubkey = *escrow;
+    let mut ix = with_options(ix, &options);
+    ix.accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    ix
+}
+
+fn assert_invalid_argument(result: Result<(), TransportError>) {
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidArgument,
+        ))) => {}
+        other => panic!("expected InvalidArgument, got {:?}", other),
+    }
+}
+
+#[test]
+fn escrow_address_is_unique_per_payer_and_vault() {
+    let payer = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let (escrow, bump) = find_escrow_address(&id(), &payer, &vault);
+    assert_eq!(find_escrow_address(&id(), &payer, &vault), (escrow, bump));
+    assert_eq!(
+        Pubkey::create_program_address(
+            &[b"escrow", payer.as_ref(), vault.as_ref(), &[bump]],
+            &id()
+        )
+        .unwrap(),
+        escrow
+    );
+    assert_ne!(find_escrow_address(&id(), &Pubkey::new_unique(), &vault).0, escrow);
+    assert_ne!(find_escrow_address(&id(), &payer, &Pubkey::new_unique()).0, escrow);
+    assert_ne!(find_vault_address(&id(), &payer).0, escrow);
+    assert_ne!(find_program_authority(&id()).0, escrow);
+}
+
+#[tokio::test]
+async fn spl_derived_escrow_initializes() {
+    if !bpf_runtime() {
+        eprintln!("skipped, creating the escrow needs the BPF runtime");
+        return;
+    }
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000;
+    let keys = SplEscrow::default();
+    create_spl_escrow_accounts(
+        &mut context,
+        &keys,
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        amount,
+    )
+    .await;
+    let (escrow, bump) = find_escrow_address(&id(), &keys.payer.pubkey(), &keys.vault.pubkey());
+    fund(&mut context, &keys.payer.pubkey(), 1_000_000_000).await;
+
+    let init = derive_escrow(spl_init_ix(&keys, amount, 10), &escrow);
+    process(&mut context, &[init], &[&keys.payer, &keys.authority])
+        .await
+        .unwrap();
+
+    let state = get_escrow(&mut context, &escrow).await;
+    assert!(state.derived_escrow);
+    assert_eq!(state.escrow_bump, bump);
+    assert_eq!(state.vault_token, keys.vault.pubkey());
+}
+
+#[tokio::test]
+async fn derived_escrow_rejects_other_address() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000;
+    let keys = SplEscrow::default();
+    create_spl_escrow_accounts(
+        &mut context,
+        &keys,
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        amount,
+    )
+    .await;
+    // Derived for another vault of the same payer
+    let (other, _) = find_escrow_address(&id(), &keys.payer.pubkey(), &Pubkey::new_unique());
+
+    let result = process(
+        &mut context,
+        &[derive_escrow(spl_init_ix(&keys, amount, 10), &other)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await;
+
+    assert_invalid_argument(result);
+}
+
+#[tokio::test]
+async fn derived_escrow_excludes_derived_vault() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000;
+    let payer = Keypair::new();
+    let authority = Keypair::new();
+    let vault = Pubkey::new_unique();
+    let (escrow, _) = find_escrow_address(&id(), &payer.pubkey(), &vault);
+    fund(&mut context, &payer.pubkey(), 1_000_000_000).await;
+
+    let mut ix = init_escrow_ix(
+        &payer.pubkey(),
+        &vault,
+        &authority.pubkey(),
+        &escrow,
+        &payer.pubkey(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        amount,
+        0,
+    );
+    let options = InitOptions {
+        derived_vault: true,
+        derived_escrow: true,
+        ..InitOptions::default()
+    };
+    ix = with_options(ix, &options);
+    ix.accounts[0].is_writable = true;
+    ix.accounts.extend([
+        AccountMeta::new_readonly(spl_token::native_mint::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ]);
+    let result = process(&mut context, &[ix], &[&payer, &authority]).await;
+
+    assert_invalid_argument(result);
+}
@@ -0,0 +1,271 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    find_program_authority, find_vault_address, id, instruction::InitOptions, state::Escrow,
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+};
+use solana_program_test::{tokio, ProgramTest};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use spl_token::state::Account as TokenAccount;
+use utils::*;
+
+/// Turns a plain `InitEscrow` into one creating the vault derived from the escrow key
+fn derive_vault(ix: Instruction, mint: &Pubkey) -> Instruction {
+    let options = InitOptions {
+        derived_vault: true,
+        ..InitOptions::default()
+    };
+    let mut ix = with_options(ix, &options);
+    ix.accounts[0].is_writable = true;
+    ix.accounts[4].is_writable = true;
+    ix.accounts.push(AccountMeta::new_readonly(*mint, false));
+    ix.accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+    ix
+}
+
+fn assert_invalid_argument(result: Result<(), TransportError>) {
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidArgument,
+        ))) => {}
+        other => panic!("expected InvalidArgument, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn spl_derived_vault_settles() {
+    if !bpf_runtime() {
+        eprintln!("skipped, creating the vault needs the BPF runtime");
+        return;
+    }
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000;
+    let fee = 10;
+    let payer = Keypair::new();
+    let authority = Keypair::new();
+    let mint = Keypair::new();
+    let mint_authority = Keypair::new();
+    let escrow = Keypair::new();
+    let payer_token = Keypair::new();
+    let payee_token = Keypair::new();
+    let fee_token = Keypair::new();
+    let (vault, _) = find_vault_address(&id(), &escrow.pubkey());
+
+    fund(&mut context, &payer.pubkey(), rent.minimum_balance(TokenAccount::LEN)).await;
+    create_mint(&mut context, &mint, &mint_authority.pubkey()).await;
+    create_token_account(&mut context, &payer_token, &mint.pubkey(), &payer.pubkey()).await;
+    create_token_account(&mut context, &payee_token, &mint.pubkey(), &Pubkey::new_unique()).await;
+    create_token_account(&mut context, &fee_token, &mint.pubkey(), &Pubkey::new_unique()).await;
+    mint_to(&mut context, &mint.pubkey(), &payer_token.pubkey(), &mint_authority, amount).await;
+    create_escrow_account(&mut context, &escrow).await;
+
+    let init = init_escrow_ix(
+        &payer.pubkey(),
+        &vault,
+        &authority.pubkey(),
+        &escrow.pubkey(),
+        &payer_token.pubkey(),
+        &payee_token.pubkey(),
+        &fee_token.pubkey(),
+        amount,
+        fee,
+    );
+    process(&mut context, &[derive_vault(init, &mint.pubkey())], &[&payer, &authority])
+        .await
+        .unwrap();
+
+    let vault_token = get_token_account(&mut context, &vault).await;
+    assert_eq!(vault_token.amount, amount);
+    assert_eq!(vault_token.owner, find_program_authority(&id()).0);
+    assert_eq!(get_token_balance(&mut context, &payer_token.pubkey()).await, 0);
+    assert!(get_escrow(&mut context, &escrow.pubkey()).await.derived_vault);
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &authority.pubkey(),
+            &payee_token.pubkey(),
+            &fee_token.pubkey(),
+            &vault,
+            &escrow.pubkey(),
+            &payer.pubkey(),
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut context, &payee_token.pubkey()).await,
+        amount - fee
+    );
+    assert_eq!(get_token_balance(&mut context, &fee_token.pubkey()).await, fee);
+    assert!(get_escrow(&mut context, &escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn token_2022_derived_vault_is_created_under_token_2022() {
+    if !bpf_runtime() {
+        eprintln!("skipped, creating the vault needs the BPF runtime");
+        return;
+    }
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000;
+    let program = spl_token_2022::id();
+    let payer = Keypair::new();
+    let authority = Keypair::new();
+    let mint = Keypair::new();
+    let mint_authority = Keypair::new();
+    let escrow = Keypair::new();
+    let payer_token = Keypair::new();
+    let payee_token = Keypair::new();
+    let fee_token = Keypair::new();
+    let (vault, _) = find_vault_address(&id(), &escrow.pubkey());
+
+    fund(&mut context, &payer.pubkey(), rent.minimum_balance(TokenAccount::LEN)).await;
+    create_mint_of(&mut context, &program, &mint, &mint_authority.pubkey()).await;
+    let (mint_key, payer_key) = (mint.pubkey(), payer.pubkey());
+    create_token_account_of(&mut context, &program, &payer_token, &mint_key, &payer_key).await;
+    let other = Pubkey::new_unique();
+    create_token_account_of(&mut context, &program, &payee_token, &mint_key, &other).await;
+    create_token_account_of(&mut context, &program, &fee_token, &mint_key, &other).await;
+    let payer_token_key = payer_token.pubkey();
+    mint_to_of(&mut context, &program, &mint_key, &payer_token_key, &mint_authority, amount)
+        .await;
+    create_escrow_account(&mut context, &escrow).await;
+
+    let init = init_escrow_ix(
+        &payer.pubkey(),
+        &vault,
+        &authority.pubkey(),
+        &escrow.pubkey(),
+        &payer_token.pubkey(),
+        &payee_token.pubkey(),
+        &fee_token.pubkey(),
+        amount,
+        10,
+    );
+    let ix = with_token_program(derive_vault(init, &mint.pubkey()), &program);
+    process(&mut context, &[ix], &[&payer, &authority])
+        .await
+        .unwrap();
+
+    let vault_account = context.banks_client.get_account(vault).await.unwrap().unwrap();
+    assert_eq!(vault_account.owner, program);
+    assert_eq!(get_token_balance(&mut context, &vault).await, amount);
+}
+
+/// Seeds a wrapped SOL escrow of `amount` lamports whose vault lives at `vault`
+fn add_native_escrow(
+    program_test: &mut ProgramTest,
+    escrow: &Pubkey,
+    vault: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    amount: u64,
+) {
+    add_vault(program_test, vault, &spl_token::native_mint::id(), amount);
+    let mut escrow_state =
+        Escrow::new_active(*payer, *authority, *vault, spl_token::native_mint::id(), amount);
+    escrow_state.payer_token = *payer;
+    escrow_state.payee_token = Pubkey::new_unique();
+    escrow_state.fee_token = Pubkey::new_unique();
+    escrow_state.derived_vault = true;
+    escrow_state.checksum = escrow_state.compute_checksum();
+    add_escrow(program_test, escrow, escrow_state);
+}
+
+#[tokio::test]
+async fn native_derived_vault_cancels() {
+    let amount = 1_000_000_000;
+    let payer = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let fee_payer = Pubkey::new_unique();
+    let (vault, _) = find_vault_address(&id(), &escrow);
+    let mut program_test = program_test();
+    add_native_escrow(&mut program_test, &escrow, &vault, &authority.pubkey(), &payer, amount);
+    let mut context = program_test.start_with_context().await;
+
+    process(
+        &mut context,
+        &[cancel_ix(&authority.pubkey(), &escrow, &payer, &fee_payer, &vault)],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_balance(&mut context, &payer).await, amount);
+    assert_eq!(get_balance(&mut context, &vault).await, 0);
+    assert!(get_escrow(&mut context, &escrow).await.is_canceled);
+}
+
+#[tokio::test]
+async fn derived_vault_escrow_rejects_underived_vault() {
+    let amount = 1_000_000_000;
+    let payer = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let mut program_test = program_test();
+    add_native_escrow(&mut program_test, &escrow, &vault, &authority.pubkey(), &payer, amount);
+    let mut context = program_test.start_with_context().await;
+
+    let result = process(
+        &mut context,
+        &[cancel_ix(&authority.pubkey(), &escrow, &payer, &Pubkey::new_unique(), &vault)],
+        &[&authority],
+    )
+    .await;
+
+    assert_invalid_argument(result);
+    assert!(!get_escrow(&mut context, &escrow).await.is_canceled);
+}
+
+#[tokio::test]
+async fn derived_vault_rejects_other_address() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000_000_000;
+    let payer = Keypair::new();
+    let authority = Keypair::new();
+    let escrow = Keypair::new();
+
+    fund(&mut context, &payer.pubkey(), rent.minimum_balance(TokenAccount::LEN) + amount).await;
+    create_escrow_account(&mut context, &escrow).await;
+
+    let init = init_escrow_ix(
+        &payer.pubkey(),
+        &Pubkey::new_unique(),
+        &authority.pubkey(),
+        &escrow.pubkey(),
+        &payer.pubkey(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        amount,
+        0,
+    );
+    let result = process(
+        &mut context,
+        &[derive_vault(init, &spl_token::native_mint::id())],
+        &[&payer, &authority],
+    )
+    .await;
+
+    assert_invalid_argument(result);
+}
@@ -0,0 +1,119 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    id,
+    instruction::{raise_dispute, resolve_dispute, DisputeResolution},
+};
+use solana_program::instruction::Instruction;
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+fn settle(keys: &NativeEscrow) -> Instruction {
+    settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+    )
+}
+
+fn cancel(keys: &NativeEscrow) -> Instruction {
+    cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer.pubkey(),
+        &Keypair::new().pubkey(),
+        &keys.vault.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn disputed_escrow_settles_once_resolved() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let escrow = keys.escrow.pubkey();
+
+    process(
+        &mut context,
+        &[raise_dispute(&id(), &keys.payer.pubkey(), &escrow)],
+        &[&keys.payer],
+    )
+    .await
+    .unwrap();
+    assert!(get_escrow(&mut context, &escrow).await.is_disputed);
+
+    let result = process(&mut context, &[settle(&keys)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::EscrowDisputed);
+
+    let resolve = resolve_dispute(
+        &id(),
+        &keys.authority.pubkey(),
+        &escrow,
+        DisputeResolution::Settle,
+    );
+    process(&mut context, &[resolve], &[&keys.authority])
+        .await
+        .unwrap();
+    let state = get_escrow(&mut context, &escrow).await;
+    assert!(!state.is_disputed);
+    assert_eq!(state.dispute_resolution, DisputeResolution::Settle as u8);
+
+    // Resolved for the payee, the refund stays closed
+    let result = process(&mut context, &[cancel(&keys)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::DisputeResolvedOtherwise);
+
+    process(&mut context, &[settle(&keys)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert!(get_escrow(&mut context, &escrow).await.is_settled);
+}
+
+#[tokio::test]
+async fn refund_resolution_cancels() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let escrow = keys.escrow.pubkey();
+    let authority = keys.authority.pubkey();
+
+    process(
+        &mut context,
+        &[
+            raise_dispute(&id(), &authority, &escrow),
+            resolve_dispute(&id(), &authority, &escrow, DisputeResolution::Refund),
+        ],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let result = process(&mut context, &[settle(&keys)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::DisputeResolvedOtherwise);
+    process(&mut context, &[cancel(&keys)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert!(get_escrow(&mut context, &escrow).await.is_canceled);
+}
+
+#[tokio::test]
+async fn settled_escrow_cant_be_disputed() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    process(&mut context, &[settle(&keys)], &[&keys.authority])
+        .await
+        .unwrap();
+
+    let result = process(
+        &mut context,
+        &[raise_dispute(&id(), &keys.payer.pubkey(), &keys.escrow.pubkey())],
+        &[&keys.payer],
+    )
+    .await;
+
+    assert_custom_error(result, EscrowError::AccountAlreadySettled);
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::instruction::InitOptions;
+use solana_program::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+async fn init(context: &mut ProgramTestContext, require_dual_sig: bool) -> NativeEscrow {
+    let options = InitOptions {
+        require_dual_sig,
+        ..InitOptions::default()
+    };
+    init_native_escrow_with_options(context, 1_000_000_000, 0, &options).await
+}
+
+fn settle(keys: &NativeEscrow) -> Instruction {
+    settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn authority_alone_settles_single_sig_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context, false).await;
+
+    process(&mut context, &[settle(&keys)], &[&keys.authority])
+        .await
+        .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert!(!escrow.require_dual_sig);
+    assert!(escrow.is_settled);
+}
+
+#[tokio::test]
+async fn payer_and_authority_settle_dual_sig_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context, true).await;
+
+    let mut ix = settle(&keys);
+    ix.accounts
+        .push(AccountMeta::new_readonly(keys.payer.pubkey(), true));
+    process(&mut context, &[ix], &[&keys.authority, &keys.payer])
+        .await
+        .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert!(escrow.require_dual_sig);
+    assert!(escrow.is_settled);
+}
+
+#[tokio::test]
+async fn authority_alone_cant_settle_dual_sig_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context, true).await;
+
+    // Listed but not signing
+    let mut ix = settle(&keys);
+    ix.accounts
+        .push(AccountMeta::new_readonly(keys.payer.pubkey(), false));
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::MissingRequiredSignature
+        )))
+    ));
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, instruction::InitOptions};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+/// An escrow of 1_000 tokens with a fee of 10 and a rotated fee token account of its mint
+async fn init(
+    context: &mut ProgramTestContext,
+    dynamic_fee_recipient: bool,
+) -> (SplEscrow, Pubkey) {
+    let keys = SplEscrow::default();
+    let (payee_owner, fee_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(context, &keys, &payee_owner, &fee_owner, 1_000).await;
+    let options = InitOptions {
+        dynamic_fee_recipient,
+        ..InitOptions::default()
+    };
+    process(
+        context,
+        &[with_options(spl_init_ix(&keys, 1_000, 10), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    let rotated = Keypair::new();
+    let mint = keys.mint.pubkey();
+    create_token_account(context, &rotated, &mint, &Pubkey::new_unique()).await;
+    (keys, rotated.pubkey())
+}
+
+fn settle(keys: &SplEscrow, fee_token: &Pubkey) -> Instruction {
+    settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        fee_token,
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn strict_escrow_rejects_another_fee_token() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, rotated) = init(&mut context, false).await;
+
+    let result = process(&mut context, &[settle(&keys, &rotated)], &[&keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidArgument
+        )))
+    ));
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 1_000);
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn dynamic_escrow_pays_the_fee_token_passed_at_settle() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, rotated) = init(&mut context, true).await;
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.dynamic_fee_recipient);
+
+    // A token account of another mint can't take the fee
+    let other_mint = Keypair::new();
+    let other_token = Keypair::new();
+    create_mint(&mut context, &other_mint, &Pubkey::new_unique()).await;
+    create_token_account(
+        &mut context,
+        &other_token,
+        &other_mint.pubkey(),
+        &Pubkey::new_unique(),
+    )
+    .await;
+    let ix = settle(&keys, &other_token.pubkey());
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::MintMismatch);
+
+    process(&mut context, &[settle(&keys, &rotated)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context, &rotated).await, 10);
+    assert_eq!(get_token_balance(&mut context, &keys.fee_token.pubkey()).await, 0);
+    assert_eq!(get_token_balance(&mut context, &keys.payee_token.pubkey()).await, 990);
+}
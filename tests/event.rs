@@ -0,0 +1,44 @@
+#![cfg(feature = "test-bpf")]
+
+use solana_escrow_payment::event::{EscrowEvent, EventKind};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+fn event(kind: EventKind) -> EscrowEvent {
+    EscrowEvent {
+        kind,
+        escrow: Pubkey::new_unique(),
+        recipient: Pubkey::new_unique(),
+        amount: 990,
+        fee: 10,
+    }
+}
+
+#[test]
+fn events_round_trip() {
+    for kind in [
+        EventKind::Settled,
+        EventKind::Canceled,
+        EventKind::PartiallySettled,
+        EventKind::PartiallyCanceled,
+    ] {
+        let event = event(kind);
+        let data = event.pack();
+        assert_eq!(data[0], kind as u8);
+        assert_eq!(EscrowEvent::unpack(&data).unwrap(), event);
+    }
+}
+
+#[test]
+fn malformed_events_fail() {
+    let data = event(EventKind::Settled).pack();
+    assert_eq!(
+        EscrowEvent::unpack(&data[..EscrowEvent::LEN - 1]),
+        Err(ProgramError::InvalidAccountData)
+    );
+    let mut unknown = data;
+    unknown[0] = 4;
+    assert_eq!(
+        EscrowEvent::unpack(&unknown),
+        Err(ProgramError::InvalidAccountData)
+    );
+}
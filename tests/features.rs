@@ -0,0 +1,52 @@
+//! Smoke tests of the public API under each feature combination, run them through
+//! `scripts/check-features.sh`
+
+use solana_escrow_payment::{
+    find_program_authority, id,
+    instruction::{EscrowInstruction, InitOptions, TAG_SETTLE},
+    state::{Escrow, EscrowState},
+};
+use solana_program::program_pack::Pack;
+
+#[test]
+fn core_api_is_available() {
+    let options = InitOptions::default();
+    assert_eq!(options.pack().len(), InitOptions::LEN);
+    assert!(matches!(
+        EscrowInstruction::unpack(&[TAG_SETTLE]),
+        Ok(EscrowInstruction::Settle { .. })
+    ));
+    let mut data = vec![0; Escrow::LEN];
+    Escrow::pack(Escrow::default(), &mut data).unwrap();
+    assert_eq!(
+        Escrow::unpack_unchecked(&data).unwrap().state(),
+        EscrowState::Uninitialized
+    );
+    find_program_authority(&id());
+}
+
+#[cfg(not(feature = "no-entrypoint"))]
+#[test]
+fn entrypoint_is_exported() {
+    let _ = solana_escrow_payment::entrypoint::entrypoint;
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_utils_are_available() {
+    use solana_escrow_payment::state::EscrowBuilder;
+    use solana_program::pubkey::Pubkey;
+
+    let escrow = Escrow::new_active(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        1,
+    );
+    assert_eq!(
+        Escrow::state_of(&escrow.into_account_data()).unwrap(),
+        EscrowState::Active
+    );
+    assert_eq!(EscrowBuilder::new().settled().build().state(), EscrowState::Settled);
+}
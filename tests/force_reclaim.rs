@@ -0,0 +1,40 @@
+#![cfg(all(feature = "test-bpf", feature = "test-admin"))]
+
+mod utils;
+
+use solana_escrow_payment::{
+    admin,
+    error::EscrowError,
+    state::{Escrow, EscrowBuilder},
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+#[tokio::test]
+async fn only_the_admin_reclaims_and_the_account_is_wiped() {
+    let mut program_test = program_test();
+    let reclaimed = Pubkey::new_unique();
+    add_escrow(&mut program_test, &reclaimed, EscrowBuilder::new().build());
+    let mut context = program_test.start_with_context().await;
+    let destination = Pubkey::new_unique();
+    let admin_signer = admin_keypair();
+    assert_eq!(admin_signer.pubkey(), admin::id());
+
+    let impostor = Keypair::new();
+    let ix = force_reclaim_ix(&impostor.pubkey(), &reclaimed, &destination);
+    let result = process(&mut context, &[ix], &[&impostor]).await;
+    assert_custom_error(result, EscrowError::InvalidAuthorityId);
+    assert_eq!(get_balance(&mut context, &destination).await, 0);
+
+    let ix = force_reclaim_ix(&admin_signer.pubkey(), &reclaimed, &destination);
+    process(&mut context, &[ix], &[&admin_signer]).await.unwrap();
+
+    assert_eq!(
+        get_balance(&mut context, &destination).await,
+        Rent::default().minimum_balance(Escrow::LEN)
+    );
+    // The runtime purges the drained account with its data
+    assert_eq!(context.banks_client.get_account(reclaimed).await.unwrap(), None);
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, instruction::InitOptions};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::Signer;
+use spl_token::instruction::{approve, set_authority, AuthorityType};
+use utils::*;
+
+fn fresh() -> InitOptions {
+    InitOptions {
+        require_fresh_vault: true,
+        ..InitOptions::default()
+    }
+}
+
+async fn accounts(context: &mut ProgramTestContext) -> SplEscrow {
+    let keys = SplEscrow::default();
+    let (payee_owner, fee_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(context, &keys, &payee_owner, &fee_owner, 1_000).await;
+    keys
+}
+
+fn init(keys: &SplEscrow, options: &InitOptions) -> Instruction {
+    with_options(spl_init_ix(keys, 1_000, 10), options)
+}
+
+#[tokio::test]
+async fn pristine_vault_passes() {
+    let mut context = program_test().start_with_context().await;
+    let keys = accounts(&mut context).await;
+
+    process(&mut context, &[init(&keys, &fresh())], &[&keys.payer, &keys.authority])
+        .await
+        .unwrap();
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_initialized);
+}
+
+#[tokio::test]
+async fn vault_with_a_delegate_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let keys = accounts(&mut context).await;
+    let (vault, payer) = (keys.vault.pubkey(), keys.payer.pubkey());
+    let ix = approve(&spl_token::id(), &vault, &Pubkey::new_unique(), &payer, &[], 1).unwrap();
+    process(&mut context, &[ix], &[&keys.payer]).await.unwrap();
+
+    let signers = [&keys.payer, &keys.authority];
+    let result = process(&mut context, &[init(&keys, &fresh())], &signers).await;
+    assert_custom_error(result, EscrowError::VaultNotFresh);
+
+    // Without the flag the same vault is taken over
+    let options = InitOptions::default();
+    process(&mut context, &[init(&keys, &options)], &[&keys.payer, &keys.authority])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn vault_with_a_close_authority_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let keys = accounts(&mut context).await;
+    let (vault, payer) = (keys.vault.pubkey(), keys.payer.pubkey());
+    let ix = set_authority(
+        &spl_token::id(),
+        &vault,
+        Some(&Pubkey::new_unique()),
+        AuthorityType::CloseAccount,
+        &payer,
+        &[],
+    )
+    .unwrap();
+    process(&mut context, &[ix], &[&keys.payer]).await.unwrap();
+
+    let signers = [&keys.payer, &keys.authority];
+    let result = process(&mut context, &[init(&keys, &fresh())], &signers).await;
+    assert_custom_error(result, EscrowError::VaultNotFresh);
+}
@@ -0,0 +1,287 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    find_program_authority, id,
+    instruction::{create_escrow_instructions, init_native_escrow},
+    utils::validate_init_params,
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::tokio;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transport::TransportError,
+};
+use spl_token::state::Account as TokenAccount;
+use utils::*;
+
+#[tokio::test]
+async fn native_builder_initializes_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000_000_000;
+    let payer = Keypair::new();
+    let vault = Keypair::new();
+    let authority = Keypair::new();
+    let escrow = Keypair::new();
+    let payee = Pubkey::new_unique();
+    let fee_taker = Pubkey::new_unique();
+    let vault_rent = rent.minimum_balance(TokenAccount::LEN);
+    fund(&mut context, &payer.pubkey(), vault_rent + amount).await;
+    create_escrow_account(&mut context, &escrow).await;
+
+    let instructions = init_native_escrow(
+        &id(),
+        &payer.pubkey(),
+        &vault.pubkey(),
+        &authority.pubkey(),
+        &escrow.pubkey(),
+        &payee,
+        &fee_taker,
+        amount,
+        10,
+        vault_rent,
+    )
+    .unwrap();
+    // The native branch refunds the payer wallet itself
+    let init = instructions.last().unwrap();
+    assert_eq!(init.accounts[4].pubkey, payer.pubkey());
+    assert_eq!(init.accounts[5].pubkey, payee);
+    assert_eq!(init.accounts[6].pubkey, fee_taker);
+    process(&mut context, &instructions, &[&payer, &vault, &authority])
+        .await
+        .unwrap();
+
+    let escrow = get_escrow(&mut context, &escrow.pubkey()).await;
+    assert_eq!(escrow.payer_token, payer.pubkey());
+    assert_eq!(escrow.mint, spl_token::native_mint::id());
+    assert_eq!(escrow.amount, amount);
+    assert_eq!(get_token_balance(&mut context, &vault.pubkey()).await, amount);
+    assert_eq!(get_balance(&mut context, &payer.pubkey()).await, 0);
+}
+
+#[tokio::test]
+async fn fee_token_of_program_authority_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let keys = SplEscrow::default();
+    let pda = find_program_authority(&id()).0;
+    create_spl_escrow_accounts(&mut context, &keys, &Pubkey::new_unique(), &pda, 1_000).await;
+
+    let result = process(
+        &mut context,
+        &[spl_init_ix(&keys, 1_000, 10)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::InvalidFeeToken);
+}
+
+#[tokio::test]
+async fn stored_bump_reproduces_program_authority() {
+    let mut context = program_test().start_with_context().await;
+    let keys = utils::init_native_escrow(&mut context, 1_000, 0).await;
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    let (authority, bump) = find_program_authority(&id());
+    assert_eq!(escrow.authority_bump, bump);
+    assert_eq!(
+        Pubkey::create_program_address(
+            &[
+                solana_escrow_payment::PREFIX.as_bytes(),
+                id().as_ref(),
+                &[escrow.authority_bump],
+            ],
+            &id(),
+        )
+        .unwrap(),
+        authority
+    );
+}
+
+#[tokio::test]
+async fn vault_of_third_party_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let keys = SplEscrow::default();
+    let (payee, fee_taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(&mut context, &keys, &payee, &fee_taker, 1_000).await;
+    let hand_over = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        &keys.vault.pubkey(),
+        Some(&Pubkey::new_unique()),
+        spl_token::instruction::AuthorityType::AccountOwner,
+        &keys.payer.pubkey(),
+        &[],
+    )
+    .unwrap();
+    process(&mut context, &[hand_over], &[&keys.payer]).await.unwrap();
+
+    let result = process(
+        &mut context,
+        &[spl_init_ix(&keys, 1_000, 10)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::InvalidVaultOwner);
+}
+
+/// Inits an SPL escrow whose payee or fee token account holds another mint
+async fn init_with_foreign_mint(foreign_payee: bool) -> Result<(), TransportError> {
+    let mut context = program_test().start_with_context().await;
+    let mut keys = SplEscrow::default();
+    let (payee, fee_taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(&mut context, &keys, &payee, &fee_taker, 1_000).await;
+
+    let other_mint = Keypair::new();
+    let foreign = Keypair::new();
+    create_mint(&mut context, &other_mint, &keys.mint_authority.pubkey()).await;
+    let owner = if foreign_payee { payee } else { fee_taker };
+    create_token_account(&mut context, &foreign, &other_mint.pubkey(), &owner).await;
+    if foreign_payee {
+        keys.payee_token = foreign;
+    } else {
+        keys.fee_token = foreign;
+    }
+
+    process(
+        &mut context,
+        &[spl_init_ix(&keys, 1_000, 10)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn payee_token_of_other_mint_is_rejected() {
+    assert_custom_error(init_with_foreign_mint(true).await, EscrowError::MintMismatch);
+}
+
+#[tokio::test]
+async fn fee_token_of_other_mint_is_rejected() {
+    assert_custom_error(init_with_foreign_mint(false).await, EscrowError::MintMismatch);
+}
+
+#[tokio::test]
+async fn zero_amount_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let keys = SplEscrow::default();
+    let (payee, fee_taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(&mut context, &keys, &payee, &fee_taker, 1_000).await;
+
+    let result = process(
+        &mut context,
+        &[spl_init_ix(&keys, 0, 0)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::ZeroAmount);
+}
+
+#[tokio::test]
+async fn fee_of_the_whole_amount_settles_to_the_fee_token() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 1_000).await;
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_token_balance(&mut context, &keys.payee_token.pubkey()).await, 0);
+    assert_eq!(get_token_balance(&mut context, &keys.fee_token.pubkey()).await, 1_000);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn create_escrow_instructions_set_up_an_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = SplEscrow::default();
+    let mint = keys.mint.pubkey();
+    create_mint(&mut context, &keys.mint, &keys.mint_authority.pubkey()).await;
+    for (account, owner) in [
+        (&keys.payer_token, keys.payer.pubkey()),
+        (&keys.payee_token, Pubkey::new_unique()),
+        (&keys.fee_token, Pubkey::new_unique()),
+    ] {
+        create_token_account(&mut context, account, &mint, &owner).await;
+    }
+    mint_to(&mut context, &mint, &keys.payer_token.pubkey(), &keys.mint_authority, 1_000).await;
+    fund(&mut context, &keys.payer.pubkey(), 1_000_000_000).await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    let instructions = create_escrow_instructions(
+        &id(),
+        &keys.payer.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &mint,
+        &keys.payer_token.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        1_000,
+        10,
+        &rent,
+    )
+    .unwrap();
+    process(
+        &mut context,
+        &instructions,
+        &[&keys.payer, &keys.vault, &keys.escrow, &keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.amount, 1_000);
+    assert_eq!(escrow.fee, 10);
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 1_000);
+}
+
+#[test]
+fn duplicate_token_accounts_are_rejected() {
+    let [vault, payer, payee, fee] = [(); 4].map(|_| Pubkey::new_unique());
+    let validate =
+        |vault, payer, payee, fee| validate_init_params(1_000, 10, vault, payer, payee, fee);
+    assert!(validate(&vault, &payer, &payee, &fee).is_ok());
+    // The payee may take the fee itself
+    assert!(validate(&vault, &payer, &payee, &payee).is_ok());
+
+    for (vault, payer, payee, fee) in [
+        (&vault, &payer, &payer, &fee),
+        (&vault, &payer, &payee, &payer),
+        (&vault, &payer, &payer, &payer),
+        (&vault, &vault, &payee, &fee),
+        (&vault, &payer, &vault, &fee),
+        (&vault, &payer, &payee, &vault),
+    ] {
+        assert!(matches!(
+            validate(vault, payer, payee, fee),
+            Err(EscrowError::DuplicateTokenAccount)
+        ));
+    }
+}
+
+#[tokio::test]
+async fn fee_to_the_payer_token_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let keys = SplEscrow::default();
+    let (payee, fee_taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(&mut context, &keys, &payee, &fee_taker, 1_000).await;
+
+    let mut ix = spl_init_ix(&keys, 1_000, 10);
+    ix.accounts[6].pubkey = keys.payer_token.pubkey();
+    let result = process(&mut context, &[ix], &[&keys.payer, &keys.authority]).await;
+    assert_custom_error(result, EscrowError::DuplicateTokenAccount);
+}
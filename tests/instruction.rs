@@ -0,0 +1,241 @@
+#![cfg(feature = "test-bpf")]
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    find_program_authority, id,
+    instruction::{
+        cancel, close, create_escrow_instructions, init_escrow, settle, DisputeResolution, EscrowAction, EscrowInstruction,
+        InitOptions,
+        CANCEL_ACCOUNTS, CLOSE_ACCOUNTS, INIT_ESCROW_ACCOUNTS, SETTLE_ACCOUNTS,
+    },
+};
+use solana_program::{
+    instruction::Instruction, program_error::ProgramError, pubkey::Pubkey, rent::Rent,
+    system_program, sysvar,
+};
+
+fn assert_accounts(ix: &Instruction, keys: &[Pubkey], specs_len: usize) {
+    assert_eq!(ix.program_id, id());
+    assert_eq!(ix.accounts.len(), specs_len);
+    let actual: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+    assert_eq!(actual, keys);
+}
+
+#[test]
+fn init_escrow_round_trips() {
+    let keys: Vec<Pubkey> = (0..7).map(|_| Pubkey::new_unique()).collect();
+    let ix = init_escrow(
+        &id(),
+        &keys[0],
+        &keys[1],
+        &keys[2],
+        &keys[3],
+        &keys[4],
+        &keys[5],
+        &keys[6],
+        1_000,
+        25,
+    );
+
+    assert_eq!(
+        EscrowInstruction::unpack(&ix.data).unwrap(),
+        EscrowInstruction::InitEscrow {
+            amount: 1_000,
+            fee: 25,
+            options: InitOptions::default(),
+        }
+    );
+    let mut expected = keys.clone();
+    expected.extend_from_slice(&[sysvar::rent::id(), spl_token::id()]);
+    assert_accounts(&ix, &expected, INIT_ESCROW_ACCOUNTS.len());
+    assert!(ix.accounts[0].is_signer && ix.accounts[2].is_signer);
+}
+
+#[test]
+fn settle_round_trips() {
+    let keys: Vec<Pubkey> = (0..6).map(|_| Pubkey::new_unique()).collect();
+    let ix = settle(&id(), &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5]);
+
+    assert_eq!(
+        EscrowInstruction::unpack(&ix.data).unwrap(),
+        EscrowInstruction::Settle { memo: [0; 32] }
+    );
+    let mut expected = keys.clone();
+    expected.extend_from_slice(&[spl_token::id(), find_program_authority(&id()).0]);
+    assert_accounts(&ix, &expected, SETTLE_ACCOUNTS.len());
+}
+
+#[test]
+fn cancel_round_trips() {
+    let keys: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+    let ix = cancel(&id(), &keys[0], &keys[1], &keys[2], &keys[3], &keys[4]);
+
+    assert_eq!(
+        EscrowInstruction::unpack(&ix.data).unwrap(),
+        EscrowInstruction::Cancel
+    );
+    let mut expected = keys.clone();
+    expected.extend_from_slice(&[spl_token::id(), find_program_authority(&id()).0]);
+    assert_accounts(&ix, &expected, CANCEL_ACCOUNTS.len());
+}
+
+#[test]
+fn close_round_trips() {
+    let keys: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    let ix = close(&id(), &keys[0], &keys[1], &keys[2]);
+
+    assert_eq!(
+        EscrowInstruction::unpack(&ix.data).unwrap(),
+        EscrowInstruction::Close
+    );
+    assert_accounts(&ix, &keys, CLOSE_ACCOUNTS.len());
+}
+
+#[test]
+fn create_escrow_instructions_end_with_init() {
+    let keys: Vec<Pubkey> = (0..7).map(|_| Pubkey::new_unique()).collect();
+    let build = |mint: &Pubkey| {
+        create_escrow_instructions(
+            &id(),
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            mint,
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            1_000,
+            10,
+            &Rent::default(),
+        )
+        .unwrap()
+    };
+
+    let spl = build(&Pubkey::new_unique());
+    let programs: Vec<Pubkey> = spl.iter().map(|ix| ix.program_id).collect();
+    assert_eq!(
+        programs,
+        [system_program::id(), spl_token::id(), spl_token::id(), system_program::id(), id()]
+    );
+    // The wrapped SOL vault is funded on creation, there is no transfer
+    let native = build(&spl_token::native_mint::id());
+    assert_eq!(native.len(), 4);
+
+    for instructions in [spl, native] {
+        let init = instructions.last().unwrap();
+        assert_eq!(
+            EscrowInstruction::unpack(&init.data).unwrap(),
+            EscrowInstruction::InitEscrow {
+                amount: 1_000,
+                fee: 10,
+                options: InitOptions::default(),
+            }
+        );
+        assert_accounts(
+            init,
+            &[
+                keys[0],
+                keys[1],
+                keys[2],
+                keys[3],
+                keys[4],
+                keys[5],
+                keys[6],
+                sysvar::rent::id(),
+                spl_token::id(),
+            ],
+            INIT_ESCROW_ACCOUNTS.len(),
+        );
+    }
+}
+
+#[test]
+fn pack_round_trips_every_variant() {
+    let options = InitOptions {
+        fee_bps: 150,
+        derived_vault: true,
+        ..InitOptions::default()
+    };
+    let instructions = vec![
+        EscrowInstruction::InitEscrow {
+            amount: 7,
+            fee: 1,
+            options,
+        },
+        EscrowInstruction::Settle { memo: [9; 32] },
+        EscrowInstruction::CloseManyToTreasury,
+        EscrowInstruction::ForceReclaim,
+        EscrowInstruction::UpdateFeeToken,
+        EscrowInstruction::SettleWithMint,
+        EscrowInstruction::SettleMany,
+        EscrowInstruction::CheckAuthorization {
+            action: EscrowAction::Cancel,
+            key: Pubkey::new_unique(),
+        },
+        EscrowInstruction::LogEscrow,
+        EscrowInstruction::DepositCounterparty,
+        EscrowInstruction::Reclaim,
+        EscrowInstruction::SettlePartial { amount: 300 },
+        EscrowInstruction::ChangeAuthority,
+        EscrowInstruction::TopUp { amount: 400 },
+        EscrowInstruction::RaiseDispute,
+        EscrowInstruction::ClaimFee,
+        EscrowInstruction::SettleAndClose,
+        EscrowInstruction::CancelPartial { amount: 600 },
+        EscrowInstruction::CancelUnwrap,
+        EscrowInstruction::SettleSplit {
+            recipients: vec![(Pubkey::new_unique(), 500), (Pubkey::new_unique(), 0)],
+        },
+        EscrowInstruction::ResolveDispute {
+            resolution: DisputeResolution::Refund,
+        },
+    ];
+    for instruction in instructions {
+        assert_eq!(EscrowInstruction::unpack(&instruction.pack()).unwrap(), instruction);
+    }
+}
+
+#[test]
+fn unpack_requires_the_exact_payload_length() {
+    let wrong_length = Err(ProgramError::from(EscrowError::InvalidInstructionLength));
+    let init = EscrowInstruction::InitEscrow {
+        amount: 1_000,
+        fee: 10,
+        options: InitOptions::default(),
+    };
+    let data = init.pack();
+    assert_eq!(data.len(), 17);
+    assert_eq!(EscrowInstruction::unpack(&data), Ok(init));
+    assert_eq!(EscrowInstruction::unpack(&data[..16]), wrong_length);
+    assert_eq!(EscrowInstruction::unpack(&data[..1]), wrong_length);
+    let mut long = data.clone();
+    long.push(0);
+    assert_eq!(EscrowInstruction::unpack(&long), wrong_length);
+
+    let with_options = EscrowInstruction::InitEscrow {
+        amount: 1_000,
+        fee: 10,
+        options: InitOptions {
+            fee_bps: 150,
+            ..InitOptions::default()
+        },
+    };
+    let mut data = with_options.pack();
+    assert_eq!(EscrowInstruction::unpack(&data), Ok(with_options));
+    data.pop();
+    assert_eq!(EscrowInstruction::unpack(&data), wrong_length);
+
+    for instruction in [
+        EscrowInstruction::Cancel,
+        EscrowInstruction::Close,
+        EscrowInstruction::Settle { memo: [0; 32] },
+        EscrowInstruction::SettlePartial { amount: 5 },
+    ] {
+        let mut data = instruction.pack();
+        assert_eq!(EscrowInstruction::unpack(&data), Ok(instruction));
+        data.push(0);
+        assert_eq!(EscrowInstruction::unpack(&data), wrong_length);
+    }
+    assert_eq!(EscrowInstruction::unpack(&[]), Err(EscrowError::InvalidInstruction.into()));
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, state::Escrow};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+/// Redirects the escrow to `payee_token` in place, leaving its checksum as it was
+async fn tamper(context: &mut ProgramTestContext, escrow: &Pubkey, payee_token: &Pubkey) {
+    let mut account = context.banks_client.get_account(*escrow).await.unwrap().unwrap();
+    let mut state = Escrow::unpack(&account.data).unwrap();
+    state.payee_token = *payee_token;
+    Escrow::pack(state, &mut account.data).unwrap();
+    context.set_account(escrow, &account.into());
+}
+
+#[tokio::test]
+async fn tampered_escrow_neither_settles_nor_cancels() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let thief = Keypair::new();
+    let mint = keys.mint.pubkey();
+    create_token_account(&mut context, &thief, &mint, &Pubkey::new_unique()).await;
+    tamper(&mut context, &keys.escrow.pubkey(), &thief.pubkey()).await;
+
+    let ix = settle_ix(
+        &keys.authority.pubkey(),
+        &thief.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+    );
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::IntegrityCheckFailed);
+
+    let ix = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &Keypair::new().pubkey(),
+        &keys.vault.pubkey(),
+    );
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::IntegrityCheckFailed);
+
+    assert_token_balances(&mut context, &[(thief.pubkey(), 0), (keys.vault.pubkey(), 1_000)])
+        .await;
+}
+
+#[tokio::test]
+async fn untampered_escrow_settles() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    // Rewriting the same payee keeps the checksum valid
+    let (escrow, payee_token) = (keys.escrow.pubkey(), keys.payee_token.pubkey());
+    tamper(&mut context, &escrow, &payee_token).await;
+
+    let ix = settle_ix(
+        &keys.authority.pubkey(),
+        &payee_token,
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &escrow,
+        &Keypair::new().pubkey(),
+    );
+    process(&mut context, &[ix], &[&keys.authority]).await.unwrap();
+    assert_eq!(get_token_balance(&mut context, &payee_token).await, 990);
+}
+
+#[tokio::test]
+async fn tampered_escrow_keeps_its_fee_token() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let (escrow, thief) = (keys.escrow.pubkey(), Keypair::new());
+    let mint = keys.mint.pubkey();
+    create_token_account(&mut context, &thief, &mint, &Pubkey::new_unique()).await;
+    tamper(&mut context, &escrow, &thief.pubkey()).await;
+
+    let ix = update_fee_token_ix(&keys.authority.pubkey(), &escrow, &thief.pubkey());
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::IntegrityCheckFailed);
+    assert_eq!(get_escrow(&mut context, &escrow).await.fee_token, keys.fee_token.pubkey());
+}
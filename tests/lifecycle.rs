@@ -0,0 +1,200 @@
+#![cfg(feature = "test-bpf")]
+
+//! Whole escrow lifecycles, from init to settlement, cancellation and close
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, state::EscrowState};
+use solana_program::instruction::Instruction;
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+fn spl_settle(keys: &SplEscrow) -> Instruction {
+    settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.authority.pubkey(),
+    )
+}
+
+fn spl_cancel(keys: &SplEscrow) -> Instruction {
+    cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &keys.authority.pubkey(),
+        &keys.vault.pubkey(),
+    )
+}
+
+fn native_settle(keys: &NativeEscrow) -> Instruction {
+    settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.authority.pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn spl_init_settle_close() {
+    let mut context = program_test().start_with_context().await;
+    for fee in [0, 10] {
+        let keys = init_spl_escrow(&mut context, 1_000, fee).await;
+        assert_token_balances(&mut context, &[(keys.vault.pubkey(), 1_000)]).await;
+
+        process(&mut context, &[spl_settle(&keys)], &[&keys.authority])
+            .await
+            .unwrap();
+        assert_token_balances(
+            &mut context,
+            &[
+                (keys.payee_token.pubkey(), 1_000 - fee),
+                (keys.fee_token.pubkey(), fee),
+                (keys.payer_token.pubkey(), 0),
+            ],
+        )
+        .await;
+
+        // A memo keeps the transaction distinct from the first settle
+        let result = process(
+            &mut context,
+            &[with_memo(spl_settle(&keys), &[1; 32])],
+            &[&keys.authority],
+        )
+        .await;
+        assert_custom_error(result, EscrowError::AccountAlreadySettled);
+
+        let escrow = keys.escrow.pubkey();
+        let close = close_ix(&keys.authority.pubkey(), &escrow, &keys.authority.pubkey());
+        process(&mut context, &[close], &[&keys.authority])
+            .await
+            .unwrap();
+        assert_eq!(get_escrow_state(&mut context, &escrow).await, EscrowState::Closed);
+    }
+}
+
+#[tokio::test]
+async fn spl_init_cancel_close() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+
+    process(&mut context, &[spl_cancel(&keys)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_token_balances(
+        &mut context,
+        &[
+            (keys.payer_token.pubkey(), 1_000),
+            (keys.payee_token.pubkey(), 0),
+            (keys.fee_token.pubkey(), 0),
+        ],
+    )
+    .await;
+
+    let result = process(
+        &mut context,
+        &[with_memo(spl_settle(&keys), &[1; 32])],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::AccountAlreadyCanceled);
+
+    let escrow = keys.escrow.pubkey();
+    let close = close_ix(&keys.authority.pubkey(), &escrow, &keys.authority.pubkey());
+    process(&mut context, &[close], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_escrow_state(&mut context, &escrow).await, EscrowState::Closed);
+}
+
+#[tokio::test]
+async fn active_escrow_cant_be_closed() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+
+    let escrow = keys.escrow.pubkey();
+    let close = close_ix(&keys.authority.pubkey(), &escrow, &keys.authority.pubkey());
+    let result = process(&mut context, &[close], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AccountNotSettledOrCanceled);
+    assert_eq!(get_escrow_state(&mut context, &escrow).await, EscrowState::Active);
+}
+
+#[tokio::test]
+async fn fee_above_the_amount_is_rejected_at_init() {
+    let mut context = program_test().start_with_context().await;
+    let keys = SplEscrow::default();
+    let payee_owner = Keypair::new().pubkey();
+    let fee_owner = Keypair::new().pubkey();
+    create_spl_escrow_accounts(&mut context, &keys, &payee_owner, &fee_owner, 1_000).await;
+
+    let result = process(
+        &mut context,
+        &[spl_init_ix(&keys, 1_000, 1_001)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::FeeOverflow);
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Uninitialized
+    );
+}
+
+#[tokio::test]
+async fn native_init_settle_close() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 10_000_000).await;
+
+    process(&mut context, &[native_settle(&keys)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_balance(&mut context, &keys.payee.pubkey()).await, 990_000_000);
+    assert_eq!(get_balance(&mut context, &keys.fee_taker.pubkey()).await, 10_000_000);
+
+    let result = process(
+        &mut context,
+        &[with_memo(native_settle(&keys), &[1; 32])],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::AccountAlreadySettled);
+
+    let escrow = keys.escrow.pubkey();
+    let close = close_ix(&keys.authority.pubkey(), &escrow, &keys.authority.pubkey());
+    process(&mut context, &[close], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_escrow_state(&mut context, &escrow).await, EscrowState::Closed);
+}
+
+#[tokio::test]
+async fn native_init_cancel() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 10_000_000).await;
+    let payer = keys.payer.pubkey();
+    let before = get_balance(&mut context, &payer).await;
+
+    let cancel = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &payer,
+        &keys.authority.pubkey(),
+        &keys.vault.pubkey(),
+    );
+    process(&mut context, &[cancel], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_balance(&mut context, &payer).await, before + 1_000_000_000);
+    assert_eq!(get_balance(&mut context, &keys.payee.pubkey()).await, 0);
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Canceled
+    );
+}
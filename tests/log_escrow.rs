@@ -0,0 +1,68 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    id,
+    instruction::{account_metas, LOG_ESCROW_ACCOUNTS, TAG_LOG_ESCROW},
+    state::EscrowBuilder,
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+#[tokio::test]
+async fn logs_every_field() {
+    let authority = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let payee_token = Pubkey::new_unique();
+    let mut program_test = program_test();
+    let mut state = EscrowBuilder::new()
+        .authority(authority.pubkey())
+        .payee_token(payee_token)
+        .amount(1_000)
+        .fee(7)
+        .build();
+    state.fee_bps = 50;
+    state.settle_not_after = 1_700_000_000;
+    state.settle_memo = [0xab; 32];
+    add_escrow(&mut program_test, &escrow, state);
+    let mut context = program_test.start_with_context().await;
+
+    let log_ix = Instruction {
+        program_id: id(),
+        accounts: account_metas(LOG_ESCROW_ACCOUNTS, &[escrow]),
+        data: vec![TAG_LOG_ESCROW],
+    };
+    // Only failed transactions hand back their logs, closing the active escrow fails after
+    // the logging instruction ran
+    let logs = process_failing(
+        &mut context,
+        &[log_ix, close_ix(&authority.pubkey(), &escrow, &authority.pubkey())],
+        &[&authority],
+    )
+    .await;
+
+    let stored = get_escrow(&mut context, &escrow).await;
+    for expected in [
+        format!("escrow={} state=Active", escrow),
+        "amount=1000 fee=7 fee_bps=50 max_abs_fee=0 settlement_fee=12".to_string(),
+        format!("payee_token={}", payee_token),
+        format!("authority={} rotation_count=0", authority.pubkey()),
+        "settle_not_before=0 settle_not_after=1700000000".to_string(),
+        format!(
+            "token_program={} checksum={} settle_memo={}",
+            spl_token::id(),
+            stored.checksum,
+            "ab".repeat(32)
+        ),
+    ] {
+        assert!(
+            logs.iter().any(|log| log.contains(&expected)),
+            "missing {:?} in {:#?}",
+            expected,
+            logs
+        );
+    }
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{id, instruction::raise_dispute, state::Escrow};
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+/// Rewrites the escrow account as written by `version`, at the packed length of that version
+async fn downgrade(context: &mut ProgramTestContext, escrow: &Pubkey, version: u8) {
+    let mut account = context.banks_client.get_account(*escrow).await.unwrap().unwrap();
+    account.data[0] = version;
+    account.data.truncate(Escrow::packed_len(version).unwrap());
+    context.set_account(escrow, &account.into());
+}
+
+async fn data_len(context: &mut ProgramTestContext, key: &Pubkey) -> usize {
+    let account = context.banks_client.get_account(*key).await.unwrap().unwrap();
+    account.data.len()
+}
+
+#[tokio::test]
+async fn settle_escrow_of_an_older_layout() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let escrow = keys.escrow.pubkey();
+    downgrade(&mut context, &escrow, 6).await;
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &escrow,
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_balance(&mut context, &keys.payee.pubkey()).await, 1_000_000_000);
+    assert_eq!(data_len(&mut context, &escrow).await, Escrow::packed_len(6).unwrap());
+    let state = get_escrow(&mut context, &escrow).await;
+    assert!(state.is_settled);
+    assert_eq!(state.version, 6);
+    assert!(state.resolved_at > 0);
+}
+
+#[tokio::test]
+async fn cancel_escrow_of_the_version_1_layout() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000_000_000;
+    let keys = init_native_escrow(&mut context, amount, 0).await;
+    let escrow = keys.escrow.pubkey();
+    downgrade(&mut context, &escrow, 1).await;
+    let payer_before = get_balance(&mut context, &keys.payer.pubkey()).await;
+
+    process(
+        &mut context,
+        &[cancel_ix(
+            &keys.authority.pubkey(),
+            &escrow,
+            &keys.payer.pubkey(),
+            &Keypair::new().pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_balance(&mut context, &keys.payer.pubkey()).await, payer_before + amount);
+    assert_eq!(data_len(&mut context, &escrow).await, Escrow::packed_len(1).unwrap());
+    let state = get_escrow(&mut context, &escrow).await;
+    assert!(state.is_canceled);
+    assert_eq!(state.version, 1);
+    // The version 1 layout has no room for the timestamp
+    assert_eq!(state.resolved_at, 0);
+}
+
+#[tokio::test]
+async fn dispute_cant_be_raised_on_a_layout_without_the_flag() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let escrow = keys.escrow.pubkey();
+    downgrade(&mut context, &escrow, 5).await;
+
+    let result = process(
+        &mut context,
+        &[raise_dispute(&id(), &keys.payer.pubkey(), &escrow)],
+        &[&keys.payer],
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidAccountData
+        )))
+    ));
+    assert!(!get_escrow(&mut context, &escrow).await.is_disputed);
+}
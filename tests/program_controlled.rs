@@ -0,0 +1,212 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    find_program_authority, id,
+    instruction::{settle_and_close, InitOptions},
+    state::{Escrow, EscrowState},
+};
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use spl_token::state::Account as TokenAccount;
+use utils::*;
+
+/// Opens a native escrow of 1 SOL under `authority`, signed by `signer` when there is one
+async fn init(
+    context: &mut ProgramTestContext,
+    authority: &Pubkey,
+    signer: Option<&Keypair>,
+) -> Result<NativeEscrow, TransportError> {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let keys = NativeEscrow {
+        payer: Keypair::new(),
+        authority: Keypair::new(),
+        vault: Keypair::new(),
+        escrow: Keypair::new(),
+        payee: Keypair::new(),
+        fee_taker: Keypair::new(),
+    };
+    fund(context, &keys.payer.pubkey(), rent.minimum_balance(0)).await;
+    create_native_vault(context, &keys.vault, &keys.payer.pubkey(), 1_000_000_000).await;
+    create_escrow_account(context, &keys.escrow).await;
+    let mut ix = with_options(
+        init_escrow_ix(
+            &keys.payer.pubkey(),
+            &keys.vault.pubkey(),
+            authority,
+            &keys.escrow.pubkey(),
+            &keys.payer.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            1_000_000_000,
+            0,
+        ),
+        &InitOptions {
+            program_controlled: signer.is_none(),
+            ..InitOptions::default()
+        },
+    );
+    ix.accounts[2].is_signer = signer.is_some();
+    let mut signers = vec![&keys.payer];
+    signers.extend(signer);
+    process(context, &[ix], &signers).await?;
+    Ok(keys)
+}
+
+fn unsigned_settle(keys: &NativeEscrow, authority: &Pubkey, fee_payer: &Pubkey) -> Instruction {
+    let mut ix = settle_ix(
+        authority,
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        fee_payer,
+    );
+    ix.accounts[0].is_signer = false;
+    ix
+}
+
+#[tokio::test]
+async fn program_authority_settles_without_a_signer() {
+    let mut context = program_test().start_with_context().await;
+    let (pda, _) = find_program_authority(&id());
+    let keys = init(&mut context, &pda, None).await.unwrap();
+    assert_eq!(get_escrow(&mut context, &keys.escrow.pubkey()).await.authority, pda);
+
+    let ix = unsigned_settle(&keys, &pda, &keys.payer.pubkey());
+    process(&mut context, &[ix], &[])
+        .await
+        .unwrap();
+
+    assert_eq!(get_balance(&mut context, &keys.payee.pubkey()).await, 1_000_000_000);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn third_party_cant_collect_the_freed_rent() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let (pda, _) = find_program_authority(&id());
+    let keys = init(&mut context, &pda, None).await.unwrap();
+    let submitter = Pubkey::new_unique();
+    let settle_and_close_to = |fee_payer: &Pubkey| {
+        let mut ix = settle_and_close(
+            &id(),
+            &pda,
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            fee_payer,
+        );
+        ix.accounts[0].is_signer = false;
+        ix
+    };
+
+    let result = process(&mut context, &[settle_and_close_to(&submitter)], &[]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidArgument
+        )))
+    ));
+    let result = process(&mut context, &[unsigned_settle(&keys, &pda, &submitter)], &[]).await;
+    assert!(result.is_err());
+    assert_eq!(get_balance(&mut context, &submitter).await, 0);
+
+    let payer = keys.payer.pubkey();
+    let before = get_balance(&mut context, &payer).await;
+    process(&mut context, &[settle_and_close_to(&payer)], &[])
+        .await
+        .unwrap();
+    assert_eq!(
+        get_balance(&mut context, &payer).await,
+        before + rent.minimum_balance(TokenAccount::LEN) + rent.minimum_balance(Escrow::LEN)
+    );
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Closed
+    );
+}
+
+#[tokio::test]
+async fn external_authority_must_sign_the_settlement() {
+    let mut context = program_test().start_with_context().await;
+    let authority = Keypair::new();
+    let keys = init(&mut context, &authority.pubkey(), Some(&authority))
+        .await
+        .unwrap();
+
+    let result = process(
+        &mut context,
+        &[unsigned_settle(&keys, &authority.pubkey(), &Pubkey::new_unique())],
+        &[],
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::MissingRequiredSignature
+        )))
+    ));
+
+    let mut ix = unsigned_settle(&keys, &authority.pubkey(), &Pubkey::new_unique());
+    ix.accounts[0].is_signer = true;
+    process(&mut context, &[ix], &[&authority]).await.unwrap();
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn program_controlled_escrow_needs_the_program_authority() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let keys = NativeEscrow {
+        payer: Keypair::new(),
+        authority: Keypair::new(),
+        vault: Keypair::new(),
+        escrow: Keypair::new(),
+        payee: Keypair::new(),
+        fee_taker: Keypair::new(),
+    };
+    fund(&mut context, &keys.payer.pubkey(), rent.minimum_balance(0)).await;
+    create_native_vault(&mut context, &keys.vault, &keys.payer.pubkey(), 1_000).await;
+    create_escrow_account(&mut context, &keys.escrow).await;
+    let ix = with_options(
+        init_escrow_ix(
+            &keys.payer.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            1_000,
+            0,
+        ),
+        &InitOptions {
+            program_controlled: true,
+            ..InitOptions::default()
+        },
+    );
+
+    let result = process(&mut context, &[ix], &[&keys.payer, &keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidArgument
+        )))
+    ));
+}
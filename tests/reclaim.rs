@@ -0,0 +1,94 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    id,
+    instruction::{self, InitOptions},
+};
+use solana_program::clock::Clock;
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transport::TransportError,
+};
+use utils::*;
+
+const EXPIRES_AT: i64 = 1_650_000_000;
+
+/// Reclaims an escrow expiring at `expires_at` with the clock set to `now`, signed by `payer`
+/// unless `authority_signs`
+async fn reclaim_at(
+    context: &mut ProgramTestContext,
+    expires_at: i64,
+    now: i64,
+    authority_signs: bool,
+) -> (NativeEscrow, Result<(), TransportError>) {
+    let options = InitOptions {
+        expires_at,
+        ..InitOptions::default()
+    };
+    let keys = init_native_escrow_with_options(context, 1_000_000_000, 0, &options).await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = now;
+    context.set_sysvar(&clock);
+
+    let signer = if authority_signs {
+        &keys.authority
+    } else {
+        &keys.payer
+    };
+    let result = process(
+        context,
+        &[instruction::reclaim(
+            &id(),
+            &signer.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer.pubkey(),
+            &Keypair::new().pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[signer],
+    )
+    .await;
+    (keys, result)
+}
+
+#[tokio::test]
+async fn reclaim_after_expiry_refunds_payer() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let (keys, result) = reclaim_at(&mut context, EXPIRES_AT, EXPIRES_AT, false).await;
+    result.unwrap();
+
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    // `init_native_escrow_with_options` leaves the payer wallet with its rent exemption
+    assert_eq!(
+        get_balance(&mut context, &keys.payer.pubkey()).await,
+        rent.minimum_balance(0) + 1_000_000_000
+    );
+}
+
+#[tokio::test]
+async fn reclaim_before_expiry_fails() {
+    let mut context = program_test().start_with_context().await;
+    let (_, result) = reclaim_at(&mut context, EXPIRES_AT, EXPIRES_AT - 1, false).await;
+    assert_custom_error(result, EscrowError::NotExpired);
+}
+
+#[tokio::test]
+async fn reclaim_without_expiry_fails() {
+    let mut context = program_test().start_with_context().await;
+    let (_, result) = reclaim_at(&mut context, 0, EXPIRES_AT, false).await;
+    assert_custom_error(result, EscrowError::NoExpiry);
+}
+
+#[tokio::test]
+async fn authority_cannot_reclaim() {
+    let mut context = program_test().start_with_context().await;
+    let (_, result) = reclaim_at(&mut context, EXPIRES_AT, EXPIRES_AT, true).await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "test-bpf")]
+
+use solana_escrow_payment::{estimate_lifecycle_rent, state::Escrow, LifecycleOpts};
+use solana_program::{program_pack::Pack, rent::Rent};
+use spl_token::state::Account as TokenAccount;
+
+#[test]
+fn escrow_and_vault_only() {
+    let rent = Rent::default();
+    let base = rent.minimum_balance(Escrow::LEN) + rent.minimum_balance(TokenAccount::LEN);
+    assert_eq!(estimate_lifecycle_rent(&rent, LifecycleOpts::default()), base);
+    let native = LifecycleOpts {
+        native: true,
+        ..LifecycleOpts::default()
+    };
+    assert_eq!(estimate_lifecycle_rent(&rent, native), base);
+}
+
+#[test]
+fn created_recipients_follow_the_mint() {
+    let rent = Rent::default();
+    let base = estimate_lifecycle_rent(&rent, LifecycleOpts::default());
+    let spl = LifecycleOpts {
+        create_payee: true,
+        create_fee_recipient: true,
+        ..LifecycleOpts::default()
+    };
+    assert_eq!(
+        estimate_lifecycle_rent(&rent, spl),
+        base + 2 * rent.minimum_balance(TokenAccount::LEN)
+    );
+    let native = LifecycleOpts {
+        native: true,
+        ..spl
+    };
+    assert_eq!(
+        estimate_lifecycle_rent(&rent, native),
+        base + 2 * rent.minimum_balance(0)
+    );
+}
+
+#[test]
+fn points_token_is_a_token_account() {
+    let rent = Rent::default();
+    let base = estimate_lifecycle_rent(&rent, LifecycleOpts::default());
+    let opts = LifecycleOpts {
+        native: true,
+        create_points_token: true,
+        ..LifecycleOpts::default()
+    };
+    assert_eq!(
+        estimate_lifecycle_rent(&rent, opts),
+        base + rent.minimum_balance(TokenAccount::LEN)
+    );
+}
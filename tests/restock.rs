@@ -0,0 +1,91 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::instruction::InitOptions;
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+const RESTOCK_FEE: u64 = 100;
+
+/// An escrow of 1_000 keeping `RESTOCK_FEE` for a restock account of its mint on cancel
+async fn init(context: &mut ProgramTestContext) -> (SplEscrow, Pubkey) {
+    let keys = SplEscrow::default();
+    let (payee_owner, fee_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(context, &keys, &payee_owner, &fee_owner, 1_000).await;
+    let restock = Keypair::new();
+    let mint = keys.mint.pubkey();
+    create_token_account(context, &restock, &mint, &Pubkey::new_unique()).await;
+    let options = InitOptions {
+        restock_fee: RESTOCK_FEE,
+        restock_token: restock.pubkey(),
+        ..InitOptions::default()
+    };
+    process(
+        context,
+        &[with_options(spl_init_ix(&keys, 1_000, 10), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    (keys, restock.pubkey())
+}
+
+fn cancel(keys: &SplEscrow, restock: &Pubkey) -> Instruction {
+    let mut ix = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &Keypair::new().pubkey(),
+        &keys.vault.pubkey(),
+    );
+    ix.accounts.push(AccountMeta::new(*restock, false));
+    ix
+}
+
+#[tokio::test]
+async fn cancel_keeps_the_restocking_fee() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, restock) = init(&mut context).await;
+
+    process(&mut context, &[cancel(&keys, &restock)], &[&keys.authority])
+        .await
+        .unwrap();
+
+    assert_token_balances(
+        &mut context,
+        &[(keys.payer_token.pubkey(), 1_000 - RESTOCK_FEE), (restock, RESTOCK_FEE)],
+    )
+    .await;
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
+
+#[tokio::test]
+async fn restocking_fee_goes_only_to_the_configured_account() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, restock) = init(&mut context).await;
+    let other = Keypair::new();
+    let mint = keys.mint.pubkey();
+    create_token_account(&mut context, &other, &mint, &Pubkey::new_unique()).await;
+
+    let result = process(&mut context, &[cancel(&keys, &other.pubkey())], &[&keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidArgument
+        )))
+    ));
+    assert_token_balances(&mut context, &[(other.pubkey(), 0), (restock, 0)]).await;
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
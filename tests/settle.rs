@@ -0,0 +1,679 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    instruction::InitOptions,
+    state::{Escrow, EscrowBuilder},
+};
+use solana_program::{
+    clock::Clock, instruction::AccountMeta, program_pack::Pack, pubkey::Pubkey,
+    system_instruction, system_program,
+};
+use solana_program_test::tokio;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use spl_token::state::Account as TokenAccount;
+use utils::*;
+
+#[tokio::test]
+async fn native_settle_moves_lamports() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000_000_000;
+    let fee = 10_000_000;
+    let keys = init_native_escrow(&mut context, amount, fee).await;
+    let fee_payer = Keypair::new();
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &fee_payer.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_balance(&mut context, &keys.payee.pubkey()).await,
+        amount - fee
+    );
+    assert_eq!(get_balance(&mut context, &keys.fee_taker.pubkey()).await, fee);
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn native_settle_rejects_other_destinations() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 10_000_000).await;
+    let settle = |payee: &Pubkey| {
+        settle_ix(
+            &keys.authority.pubkey(),
+            payee,
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )
+    };
+
+    let other = settle(&Pubkey::new_unique());
+    let result = process(&mut context, &[other], &[&keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidArgument
+        )))
+    ));
+
+    process(&mut context, &[settle(&keys.payee.pubkey())], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_eq!(get_balance(&mut context, &keys.payee.pubkey()).await, 990_000_000);
+}
+
+#[tokio::test]
+async fn native_settle_to_a_token_account_fails() {
+    let authority = Keypair::new();
+    let (escrow, vault, payee_token) =
+        (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let native_mint = spl_token::native_mint::id();
+    let mut program_test = program_test();
+    add_vault(&mut program_test, &vault, &native_mint, 1_000);
+    // A token account recorded as the payee of a wrapped SOL escrow
+    add_vault(&mut program_test, &payee_token, &native_mint, 0);
+    add_escrow(
+        &mut program_test,
+        &escrow,
+        EscrowBuilder::new()
+            .authority(authority.pubkey())
+            .payee_token(payee_token)
+            .vault_token(vault)
+            .mint(native_mint)
+            .amount(1_000)
+            .build(),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let result = process(
+        &mut context,
+        &[settle_ix(
+            &authority.pubkey(),
+            &payee_token,
+            &Pubkey::new_unique(),
+            &vault,
+            &escrow,
+            &authority.pubkey(),
+        )],
+        &[&authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::InvalidNativeRecipient);
+}
+
+#[tokio::test]
+async fn native_settle_keeps_escrow_rent_exempt() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 10_000_000).await;
+    let fee_payer = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let escrow_before = get_balance(&mut context, &keys.escrow.pubkey()).await;
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &fee_payer.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let escrow_after = get_balance(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow_after, escrow_before);
+    assert!(rent.is_exempt(escrow_after, Escrow::LEN));
+    // The vault's rent goes to the fee payer, not the escrow
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        rent.minimum_balance(TokenAccount::LEN)
+    );
+}
+
+#[tokio::test]
+async fn native_settle_returns_vault_surplus_to_payer() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let (amount, fee, surplus) = (1_000_000_000, 10_000_000, 5_000);
+    let keys = init_native_escrow(&mut context, amount, fee).await;
+    let fee_payer = Keypair::new();
+    // An external top-up the token program never synced
+    let vault = keys.vault.pubkey();
+    let top_up = system_instruction::transfer(&context.payer.pubkey(), &vault, surplus);
+    process(&mut context, &[top_up], &[]).await.unwrap();
+    let settle = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &fee_payer.pubkey(),
+    );
+
+    // The surplus has nowhere to go without the payer
+    let result = process(
+        &mut context,
+        std::slice::from_ref(&settle),
+        &[&keys.authority],
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::NotEnoughAccountKeys
+        )))
+    ));
+
+    let payer = keys.payer.pubkey();
+    let payer_before = get_balance(&mut context, &payer).await;
+    let mut ix = settle;
+    ix.accounts.push(AccountMeta::new(payer, false));
+    process(&mut context, &[ix], &[&keys.authority]).await.unwrap();
+
+    assert_eq!(get_balance(&mut context, &payer).await, payer_before + surplus);
+    assert_eq!(get_balance(&mut context, &keys.payee.pubkey()).await, amount - fee);
+    assert_eq!(get_balance(&mut context, &keys.fee_taker.pubkey()).await, fee);
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        rent.minimum_balance(TokenAccount::LEN)
+    );
+    assert_eq!(
+        get_balance(&mut context, &keys.escrow.pubkey()).await,
+        rent.minimum_balance(Escrow::LEN)
+    );
+}
+
+#[tokio::test]
+async fn spl_settle_transfers_tokens() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000;
+    let fee = 10;
+    let keys = init_spl_escrow(&mut context, amount, fee).await;
+    let fee_payer = Keypair::new();
+    let payee_lamports = get_balance(&mut context, &keys.payee_token.pubkey()).await;
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &fee_payer.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut context, &keys.payee_token.pubkey()).await,
+        amount - fee
+    );
+    assert_eq!(
+        get_token_balance(&mut context, &keys.fee_token.pubkey()).await,
+        fee
+    );
+    assert_eq!(
+        get_balance(&mut context, &keys.payee_token.pubkey()).await,
+        payee_lamports
+    );
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        rent.minimum_balance(TokenAccount::LEN)
+    );
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn settle_into_closed_fee_token_fails() {
+    let mut context = program_test().start_with_context().await;
+    let fee_owner = Keypair::new();
+    let keys = init_spl_escrow_with_owners(
+        &mut context,
+        SplEscrow::default(),
+        &Pubkey::new_unique(),
+        &fee_owner.pubkey(),
+        1_000,
+        10,
+    )
+    .await;
+    let close_fee_token = spl_token::instruction::close_account(
+        &spl_token::id(),
+        &keys.fee_token.pubkey(),
+        &fee_owner.pubkey(),
+        &fee_owner.pubkey(),
+        &[],
+    )
+    .unwrap();
+    process(&mut context, &[close_fee_token], &[&fee_owner])
+        .await
+        .unwrap();
+
+    let result = process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::InvalidFeeToken);
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 1_000);
+}
+
+#[tokio::test]
+async fn spl_settle_pays_fee_owned_by_authority() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000;
+    let fee = 25;
+    let keys = SplEscrow::default();
+    let authority = keys.authority.pubkey();
+    let payee_owner = Pubkey::new_unique();
+    let keys =
+        init_spl_escrow_with_owners(&mut context, keys, &payee_owner, &authority, amount, fee).await;
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &authority,
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &authority,
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let payee_token = get_token_account(&mut context, &keys.payee_token.pubkey()).await;
+    assert_eq!(payee_token.owner, payee_owner);
+    assert_eq!(payee_token.amount, amount - fee);
+    let fee_token = get_token_account(&mut context, &keys.fee_token.pubkey()).await;
+    assert_eq!(fee_token.owner, authority);
+    assert_eq!(fee_token.amount, fee);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn native_settle_creates_missing_payee_wallet() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000_000_000;
+    let keys = init_native_escrow(&mut context, amount, 0).await;
+    assert!(context
+        .banks_client
+        .get_account(keys.payee.pubkey())
+        .await
+        .unwrap()
+        .is_none());
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let payee = context
+        .banks_client
+        .get_account(keys.payee.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(payee.lamports, amount);
+    assert_eq!(payee.owner, system_program::id());
+    assert!(payee.data.is_empty());
+    assert!(!payee.executable);
+}
+
+#[tokio::test]
+async fn settle_records_memo() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    let mut memo = [0; 32];
+    memo[..11].copy_from_slice(b"INV-2022-17");
+
+    process(
+        &mut context,
+        &[with_memo(
+            settle_ix(
+                &keys.authority.pubkey(),
+                &keys.payee.pubkey(),
+                &keys.fee_taker.pubkey(),
+                &keys.vault.pubkey(),
+                &keys.escrow.pubkey(),
+                &keys.authority.pubkey(),
+            ),
+            &memo,
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert!(escrow.is_settled);
+    assert_eq!(escrow.settle_memo, memo);
+}
+
+#[tokio::test]
+async fn settle_without_memo_records_zeroes() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert!(escrow.is_settled);
+    assert_eq!(escrow.settle_memo, [0; 32]);
+}
+
+#[tokio::test]
+async fn settle_rejects_short_memo() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+
+    let result = process(
+        &mut context,
+        &[with_memo(
+            settle_ix(
+                &keys.authority.pubkey(),
+                &keys.payee.pubkey(),
+                &keys.fee_taker.pubkey(),
+                &keys.vault.pubkey(),
+                &keys.escrow.pubkey(),
+                &keys.authority.pubkey(),
+            ),
+            b"INV-2022-17",
+        )],
+        &[&keys.authority],
+    )
+    .await;
+
+    assert_custom_error(result, EscrowError::InvalidInstructionLength);
+}
+
+#[tokio::test]
+async fn settle_rejects_reinitialized_vault() {
+    let authority = Keypair::new();
+    let escrow = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let mut escrow_state =
+        Escrow::new_active(Pubkey::new_unique(), authority.pubkey(), vault, Pubkey::new_unique(), 1_000);
+    escrow_state.payee_token = Pubkey::new_unique();
+    escrow_state.fee_token = Pubkey::new_unique();
+    escrow_state.checksum = escrow_state.compute_checksum();
+    let payee_token = escrow_state.payee_token;
+    let fee_token = escrow_state.fee_token;
+    let mut program_test = program_test();
+    // The vault key now holds an account of another mint
+    add_vault(&mut program_test, &vault, &Pubkey::new_unique(), 1_000);
+    add_escrow(&mut program_test, &escrow, escrow_state);
+    let mut context = program_test.start_with_context().await;
+
+    let result = process(
+        &mut context,
+        &[settle_ix(
+            &authority.pubkey(),
+            &payee_token,
+            &fee_token,
+            &vault,
+            &escrow,
+            &authority.pubkey(),
+        )],
+        &[&authority],
+    )
+    .await;
+
+    assert_custom_error(result, EscrowError::VaultMintChanged);
+}
+
+#[tokio::test]
+async fn settle_failure_logs_escrow_key() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+
+    let logs = process_failing(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &Pubkey::new_unique(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.authority.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await;
+
+    let expected = format!("Escrow {} failed", keys.escrow.pubkey());
+    assert!(logs.iter().any(|log| log.contains(&expected)), "{:?}", logs);
+}
+
+#[tokio::test]
+async fn settle_rejects_other_token_program() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    assert_eq!(
+        get_escrow(&mut context, &keys.escrow.pubkey()).await.token_program,
+        spl_token::id()
+    );
+
+    let mut ix = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee.pubkey(),
+        &keys.fee_taker.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.authority.pubkey(),
+    );
+    ix.accounts[6].pubkey = system_program::id();
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::TokenProgramMismatch);
+}
+
+/// Settles an SPL escrow of 1_000 tokens topped up by `top_up` after init, returning the
+/// payee and fee taker balances. The escrow allows balance drift for the top-up.
+async fn settle_payouts(fee: u64, fee_bps: u16, top_up: u64) -> (u64, u64) {
+    let mut context = program_test().start_with_context().await;
+    let keys = SplEscrow::default();
+    let (payee, fee_taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(&mut context, &keys, &payee, &fee_taker, 1_000).await;
+    let options = InitOptions {
+        fee_bps,
+        allow_balance_drift: true,
+        ..InitOptions::default()
+    };
+    process(
+        &mut context,
+        &[with_options(spl_init_ix(&keys, 1_000, fee), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    if top_up > 0 {
+        let mint = keys.mint.pubkey();
+        mint_to(&mut context, &mint, &keys.vault.pubkey(), &keys.mint_authority, top_up).await;
+    }
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+    (
+        get_token_balance(&mut context, &keys.payee_token.pubkey()).await,
+        get_token_balance(&mut context, &keys.fee_token.pubkey()).await,
+    )
+}
+
+#[tokio::test]
+async fn fixed_and_bps_fees_pay_out_alike() {
+    assert_eq!(settle_payouts(10, 0, 0).await, (990, 10));
+    assert_eq!(settle_payouts(0, 100, 0).await, (990, 10));
+}
+
+#[tokio::test]
+async fn bps_fee_follows_vault_top_ups() {
+    assert_eq!(settle_payouts(10, 0, 500).await, (1_490, 10));
+    assert_eq!(settle_payouts(0, 100, 500).await, (1_485, 15));
+}
+
+#[tokio::test]
+async fn settle_records_resolution_time() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 0).await;
+    assert_eq!(get_escrow(&mut context, &keys.escrow.pubkey()).await.resolved_at, 0);
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = 1_650_000_000;
+    context.set_sysvar(&clock);
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.resolved_at, 1_650_000_000);
+    assert_eq!(escrow.version, Escrow::current_version());
+}
+
+#[tokio::test]
+async fn settle_of_topped_up_vault_fails() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let mint = keys.mint.pubkey();
+    mint_to(&mut context, &mint, &keys.vault.pubkey(), &keys.mint_authority, 500).await;
+
+    let result = process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::VaultBalanceMismatch);
+}
+
+#[tokio::test]
+async fn escrow_of_other_owner_is_rejected() {
+    let authority = Keypair::new();
+    let (escrow, vault, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let escrow_state =
+        Escrow::new_active(Pubkey::new_unique(), authority.pubkey(), vault, mint, 1_000);
+    let payer_token = escrow_state.payer_token;
+    let mut program_test = program_test();
+    add_vault(&mut program_test, &vault, &mint, 1_000);
+    add_escrow_owned_by(&mut program_test, &escrow, escrow_state, &system_program::id());
+    let mut context = program_test.start_with_context().await;
+
+    let settle = settle_ix(
+        &authority.pubkey(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &vault,
+        &escrow,
+        &authority.pubkey(),
+    );
+    let cancel = cancel_ix(
+        &authority.pubkey(),
+        &escrow,
+        &payer_token,
+        &authority.pubkey(),
+        &vault,
+    );
+    for ix in [settle, cancel] {
+        let result = process(&mut context, &[ix], &[&authority]).await;
+        assert!(matches!(
+            result,
+            Err(TransportError::TransactionError(TransactionError::InstructionError(
+                0,
+                InstructionError::IllegalOwner
+            )))
+        ));
+    }
+}
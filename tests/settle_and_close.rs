@@ -0,0 +1,79 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    id,
+    instruction::settle_and_close,
+    state::{Escrow, EscrowState},
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use spl_token::state::Account as TokenAccount;
+use utils::*;
+
+#[tokio::test]
+async fn one_instruction_pays_the_payee_and_closes_the_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_native_escrow(&mut context, 1_000_000_000, 10_000_000).await;
+    let fee_payer = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    process(
+        &mut context,
+        &[settle_and_close(
+            &id(),
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &fee_payer.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_balance(&mut context, &keys.payee.pubkey()).await, 990_000_000);
+    assert_eq!(get_balance(&mut context, &keys.fee_taker.pubkey()).await, 10_000_000);
+    assert_eq!(get_balance(&mut context, &keys.escrow.pubkey()).await, 0);
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Closed
+    );
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        rent.minimum_balance(TokenAccount::LEN) + rent.minimum_balance(Escrow::LEN)
+    );
+}
+
+#[tokio::test]
+async fn settle_and_close_needs_the_authority() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let intruder = Keypair::new();
+
+    let result = process(
+        &mut context,
+        &[settle_and_close(
+            &id(),
+            &intruder.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Pubkey::new_unique(),
+        )],
+        &[&intruder],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::InvalidAuthority);
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Active
+    );
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 1_000);
+}
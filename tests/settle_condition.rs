@@ -0,0 +1,114 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, state::Escrow};
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transport::TransportError,
+};
+use utils::*;
+
+const AMOUNT: u64 = 1_000_000_000;
+
+struct Keys {
+    authority: Keypair,
+    payee: Pubkey,
+    vault: Pubkey,
+    escrow: Pubkey,
+    condition: Pubkey,
+}
+
+/// Seeds an active wrapped SOL escrow gated by a condition account owned by `condition_owner`
+fn add_conditional_escrow(program_test: &mut ProgramTest, condition_owner: &Pubkey) -> Keys {
+    let keys = Keys {
+        authority: Keypair::new(),
+        payee: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        escrow: Pubkey::new_unique(),
+        condition: Pubkey::new_unique(),
+    };
+    let mut escrow = Escrow::new_active(
+        Pubkey::new_unique(),
+        keys.authority.pubkey(),
+        keys.vault,
+        spl_token::native_mint::id(),
+        AMOUNT,
+    );
+    escrow.payee_token = keys.payee;
+    escrow.condition_account = keys.condition;
+    escrow.condition_owner = *condition_owner;
+    escrow.checksum = escrow.compute_checksum();
+    add_vault(program_test, &keys.vault, &spl_token::native_mint::id(), AMOUNT);
+    add_escrow(program_test, &keys.escrow, escrow);
+    keys
+}
+
+fn add_condition(program_test: &mut ProgramTest, key: &Pubkey, owner: &Pubkey) {
+    program_test.add_account(
+        *key,
+        Account {
+            lamports: 1_000_000,
+            data: vec![1],
+            owner: *owner,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+async fn settle(
+    keys: &Keys,
+    program_test: ProgramTest,
+) -> (ProgramTestContext, Result<(), TransportError>) {
+    let mut context = program_test.start_with_context().await;
+    let mut ix = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee,
+        &Pubkey::new_unique(),
+        &keys.vault,
+        &keys.escrow,
+        &keys.authority.pubkey(),
+    );
+    ix.accounts.push(AccountMeta::new_readonly(keys.condition, false));
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    (context, result)
+}
+
+#[tokio::test]
+async fn settles_once_condition_holds() {
+    let owner = Pubkey::new_unique();
+    let mut program_test = program_test();
+    let keys = add_conditional_escrow(&mut program_test, &owner);
+    add_condition(&mut program_test, &keys.condition, &owner);
+
+    let (mut context, result) = settle(&keys, program_test).await;
+    result.unwrap();
+
+    assert_eq!(get_balance(&mut context, &keys.payee).await, AMOUNT);
+    assert!(get_escrow(&mut context, &keys.escrow).await.is_settled);
+}
+
+#[tokio::test]
+async fn missing_condition_account_blocks_settlement() {
+    let mut program_test = program_test();
+    let keys = add_conditional_escrow(&mut program_test, &Pubkey::new_unique());
+
+    let (mut context, result) = settle(&keys, program_test).await;
+    assert_custom_error(result, EscrowError::ConditionNotMet);
+    assert!(!get_escrow(&mut context, &keys.escrow).await.is_settled);
+}
+
+#[tokio::test]
+async fn condition_account_of_other_owner_blocks_settlement() {
+    let mut program_test = program_test();
+    let keys = add_conditional_escrow(&mut program_test, &Pubkey::new_unique());
+    add_condition(&mut program_test, &keys.condition, &Pubkey::new_unique());
+
+    let (mut context, result) = settle(&keys, program_test).await;
+    assert_custom_error(result, EscrowError::ConditionNotMet);
+    assert!(!get_escrow(&mut context, &keys.escrow).await.is_settled);
+}
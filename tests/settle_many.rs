@@ -0,0 +1,167 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, state::EscrowBuilder, MAX_BATCH};
+use solana_program::{instruction::InstructionError, pubkey::Pubkey, system_program};
+use solana_program_test::{tokio, ProgramTest};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+const AMOUNT: u64 = 1_000_000_000;
+const FEE: u64 = 10_000_000;
+
+/// Seeds an active wrapped SOL escrow under `authority` and returns its settle group
+fn add_native_escrow(program_test: &mut ProgramTest, authority: &Pubkey) -> [Pubkey; 5] {
+    let escrow = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let escrow_state = EscrowBuilder::new()
+        .payer(Pubkey::new_unique())
+        .payee_token(Pubkey::new_unique())
+        .fee_token(Pubkey::new_unique())
+        .vault_token(vault)
+        .authority(*authority)
+        .mint(spl_token::native_mint::id())
+        .amount(AMOUNT)
+        .fee(FEE)
+        .build();
+    let group = [
+        escrow_state.payee_token,
+        escrow_state.fee_token,
+        vault,
+        escrow,
+        *authority,
+    ];
+    add_vault(program_test, &vault, &spl_token::native_mint::id(), AMOUNT);
+    add_escrow(program_test, &escrow, escrow_state);
+    group
+}
+
+#[tokio::test]
+async fn settle_three_escrows() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    let groups: Vec<_> = (0..3)
+        .map(|_| add_native_escrow(&mut program_test, &authority.pubkey()))
+        .collect();
+    let mut context = program_test.start_with_context().await;
+
+    process(
+        &mut context,
+        &[settle_many_ix(&authority.pubkey(), &groups)],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    for [payee, fee_taker, vault, escrow, _] in &groups {
+        assert_eq!(get_balance(&mut context, payee).await, AMOUNT - FEE);
+        assert_eq!(get_balance(&mut context, fee_taker).await, FEE);
+        assert_eq!(get_balance(&mut context, vault).await, 0);
+        assert!(get_escrow(&mut context, escrow).await.is_settled);
+    }
+}
+
+#[tokio::test]
+async fn settle_many_skips_resolved_escrows() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    let active = add_native_escrow(&mut program_test, &authority.pubkey());
+    let resolved = add_native_escrow(&mut program_test, &authority.pubkey());
+    let mut context = program_test.start_with_context().await;
+
+    let [payee, fee_taker, vault, escrow, _] = resolved;
+    process(
+        &mut context,
+        &[settle_ix(&authority.pubkey(), &payee, &fee_taker, &vault, &escrow, &authority.pubkey())],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    process(
+        &mut context,
+        &[settle_many_ix(&authority.pubkey(), &[resolved, active])],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_balance(&mut context, &payee).await, AMOUNT - FEE);
+    assert_eq!(get_balance(&mut context, &active[0]).await, AMOUNT - FEE);
+    assert!(get_escrow(&mut context, &active[3]).await.is_settled);
+}
+
+#[tokio::test]
+async fn settle_many_aborts_on_error() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    let first = add_native_escrow(&mut program_test, &authority.pubkey());
+    let mut second = add_native_escrow(&mut program_test, &authority.pubkey());
+    second[0] = Pubkey::new_unique();
+    let mut context = program_test.start_with_context().await;
+
+    let logs = process_failing(
+        &mut context,
+        &[settle_many_ix(&authority.pubkey(), &[first, second])],
+        &[&authority],
+    )
+    .await;
+
+    let expected = format!("Escrow {} failed", second[3]);
+    assert!(logs.iter().any(|log| log.contains(&expected)), "{:?}", logs);
+    assert!(!get_escrow(&mut context, &first[3]).await.is_settled);
+    assert_eq!(get_balance(&mut context, &first[0]).await, 0);
+}
+
+#[tokio::test]
+async fn settle_many_aborts_on_foreign_resolved_account() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    let active = add_native_escrow(&mut program_test, &authority.pubkey());
+    let mut foreign = active;
+    foreign[3] = Pubkey::new_unique();
+    add_escrow_owned_by(
+        &mut program_test,
+        &foreign[3],
+        EscrowBuilder::new().authority(authority.pubkey()).settled().build(),
+        &system_program::id(),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let result = process(
+        &mut context,
+        &[settle_many_ix(&authority.pubkey(), &[foreign, active])],
+        &[&authority],
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::IllegalOwner
+        )))
+    ));
+    assert!(!get_escrow(&mut context, &active[3]).await.is_settled);
+}
+
+#[tokio::test]
+async fn settle_many_rejects_oversized_batch() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    let group = add_native_escrow(&mut program_test, &authority.pubkey());
+    let mut context = program_test.start_with_context().await;
+
+    let result = process(
+        &mut context,
+        &[settle_many_ix(&authority.pubkey(), &vec![group; MAX_BATCH + 1])],
+        &[&authority],
+    )
+    .await;
+
+    assert_custom_error(result, EscrowError::BatchTooLarge);
+}
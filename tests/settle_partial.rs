@@ -0,0 +1,120 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    id,
+    instruction::{settle_partial, InitOptions},
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+/// Opens an SPL escrow of 1_000 tokens with a flat fee of 10 and a 1% fee
+async fn init(context: &mut ProgramTestContext) -> SplEscrow {
+    let keys = SplEscrow::default();
+    let (payee, fee_taker) = (Pubkey::new_unique(), Pubkey::new_unique());
+    create_spl_escrow_accounts(context, &keys, &payee, &fee_taker, 1_000).await;
+    let options = InitOptions {
+        fee_bps: 100,
+        ..InitOptions::default()
+    };
+    process(
+        context,
+        &[with_options(spl_init_ix(&keys, 1_000, 10), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    keys
+}
+
+fn partial_ix(keys: &SplEscrow, amount: u64) -> Instruction {
+    settle_partial(
+        &id(),
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        amount,
+    )
+}
+
+#[tokio::test]
+async fn partial_releases_then_final_settle() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context).await;
+
+    for amount in [300, 200] {
+        process(&mut context, &[partial_ix(&keys, amount)], &[&keys.authority])
+            .await
+            .unwrap();
+    }
+    assert_eq!(get_token_balance(&mut context, &keys.payee_token.pubkey()).await, 297 + 198);
+    assert_eq!(get_token_balance(&mut context, &keys.fee_token.pubkey()).await, 3 + 2);
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 500);
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert!(!escrow.is_settled);
+    assert_eq!(escrow.amount, 500);
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    // The final settlement charges the flat fee and 1% of the remaining 500
+    assert_eq!(get_token_balance(&mut context, &keys.payee_token.pubkey()).await, 495 + 485);
+    assert_eq!(get_token_balance(&mut context, &keys.fee_token.pubkey()).await, 5 + 15);
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn partial_release_beyond_the_balance_fails() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context).await;
+    process(&mut context, &[partial_ix(&keys, 600)], &[&keys.authority])
+        .await
+        .unwrap();
+
+    let result = process(&mut context, &[partial_ix(&keys, 500)], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AmountExceedsVault);
+}
+
+#[tokio::test]
+async fn partial_release_of_canceled_escrow_fails() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context).await;
+    process(
+        &mut context,
+        &[cancel_ix(
+            &keys.authority.pubkey(),
+            &keys.escrow.pubkey(),
+            &keys.payer_token.pubkey(),
+            &Keypair::new().pubkey(),
+            &keys.vault.pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    // The canceled escrow's vault is closed, a live token account of the mint stands in for it
+    let mut ix = partial_ix(&keys, 100);
+    ix.accounts[3].pubkey = keys.payer_token.pubkey();
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AccountAlreadyCanceled);
+}
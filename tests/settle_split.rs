@@ -0,0 +1,83 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, id, instruction::settle_split, MAX_SPLIT};
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+/// An escrow of 1_000 tokens with a fee of 10 and two affiliate accounts of its mint
+async fn init(context: &mut ProgramTestContext) -> (SplEscrow, Pubkey, Pubkey) {
+    let keys = init_spl_escrow(context, 1_000, 10).await;
+    let first = Keypair::new();
+    let second = Keypair::new();
+    let mint = keys.mint.pubkey();
+    create_token_account(context, &first, &mint, &Pubkey::new_unique()).await;
+    create_token_account(context, &second, &mint, &Pubkey::new_unique()).await;
+    (keys, first.pubkey(), second.pubkey())
+}
+
+fn split(keys: &SplEscrow, recipients: &[(Pubkey, u64)]) -> Instruction {
+    settle_split(
+        &id(),
+        &keys.authority.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+        recipients,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn three_way_split_pays_each_recipient() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, first, second) = init(&mut context).await;
+    let seller = keys.payee_token.pubkey();
+
+    let ix = split(&keys, &[(seller, 690), (first, 200), (second, 100)]);
+    process(&mut context, &[ix], &[&keys.authority])
+        .await
+        .unwrap();
+
+    assert_eq!(get_token_balance(&mut context, &seller).await, 690);
+    assert_eq!(get_token_balance(&mut context, &first).await, 200);
+    assert_eq!(get_token_balance(&mut context, &second).await, 100);
+    assert_eq!(get_token_balance(&mut context, &keys.fee_token.pubkey()).await, 10);
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn split_must_consume_the_vault() {
+    let mut context = program_test().start_with_context().await;
+    let (keys, first, second) = init(&mut context).await;
+    let seller = keys.payee_token.pubkey();
+
+    // Short by 10 of the 990 left after the fee
+    let ix = split(&keys, &[(seller, 690), (first, 200), (second, 90)]);
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::ExpectedAmountMismatch);
+
+    let ix = split(&keys, &[(seller, u64::MAX), (first, 200)]);
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AmountOverflow);
+
+    assert_eq!(get_token_balance(&mut context, &keys.vault.pubkey()).await, 1_000);
+    assert!(!get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[test]
+fn builder_rejects_more_recipients_than_a_split_pays() {
+    let key = Pubkey::new_unique();
+    for count in [MAX_SPLIT + 1, 256] {
+        let recipients = vec![(Pubkey::new_unique(), 1); count];
+        assert_eq!(
+            settle_split(&id(), &key, &key, &key, &key, &key, &recipients),
+            Err(ProgramError::from(EscrowError::BatchTooLarge))
+        );
+    }
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, instruction::InitOptions};
+use solana_program::clock::Clock;
+use solana_program_test::tokio;
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transport::TransportError,
+};
+use utils::*;
+
+const START: i64 = 1_650_000_000;
+const END: i64 = START + 3_600;
+
+/// Settles an escrow open from `START` to `END` with the clock set to `now`
+async fn settle_at(now: i64) -> Result<(), TransportError> {
+    let mut context = program_test().start_with_context().await;
+    let options = InitOptions {
+        settle_not_before: START,
+        settle_not_after: END,
+        ..InitOptions::default()
+    };
+    let keys = init_native_escrow_with_options(&mut context, 1_000_000_000, 0, &options).await;
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = now;
+    context.set_sysvar(&clock);
+
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee.pubkey(),
+            &keys.fee_taker.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+}
+
+#[tokio::test]
+async fn settle_at_window_start() {
+    settle_at(START).await.unwrap();
+}
+
+#[tokio::test]
+async fn settle_before_window_fails() {
+    assert_custom_error(
+        settle_at(START - 1).await,
+        EscrowError::OutsideSettlementWindow,
+    );
+}
+
+#[tokio::test]
+async fn settle_at_window_end() {
+    settle_at(END).await.unwrap();
+}
+
+#[tokio::test]
+async fn settle_after_window_fails() {
+    assert_custom_error(
+        settle_at(END + 1).await,
+        EscrowError::OutsideSettlementWindow,
+    );
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    find_program_authority, id,
+    instruction::{account_metas, InitOptions, SETTLE_WITH_MINT_ACCOUNTS, TAG_SETTLE_WITH_MINT},
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+const POINTS: u64 = 50;
+
+/// An escrow of 1_000 with a fee of 10 paying `POINTS` of a mint held by the program authority,
+/// with the points account of the payee
+struct PointsEscrow {
+    keys: SplEscrow,
+    points_mint: Pubkey,
+    points_token: Pubkey,
+}
+
+async fn init(context: &mut ProgramTestContext, points_amount: u64) -> PointsEscrow {
+    let keys = SplEscrow::default();
+    let payee_owner = Pubkey::new_unique();
+    create_spl_escrow_accounts(context, &keys, &payee_owner, &Pubkey::new_unique(), 1_000).await;
+    let (points_mint, points_token) = (Keypair::new(), Keypair::new());
+    create_mint(context, &points_mint, &find_program_authority(&id()).0).await;
+    let mint = points_mint.pubkey();
+    create_token_account(context, &points_token, &mint, &payee_owner).await;
+
+    let options = InitOptions {
+        points_mint: if points_amount > 0 { mint } else { Pubkey::default() },
+        points_amount,
+        ..InitOptions::default()
+    };
+    process(
+        context,
+        &[with_options(spl_init_ix(&keys, 1_000, 10), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    PointsEscrow {
+        keys,
+        points_mint: mint,
+        points_token: points_token.pubkey(),
+    }
+}
+
+fn settle_with_mint_ix(escrow: &PointsEscrow, points_token: &Pubkey) -> Instruction {
+    let keys = &escrow.keys;
+    Instruction {
+        program_id: id(),
+        accounts: account_metas(
+            SETTLE_WITH_MINT_ACCOUNTS,
+            &[
+                keys.authority.pubkey(),
+                keys.payee_token.pubkey(),
+                keys.fee_token.pubkey(),
+                keys.vault.pubkey(),
+                keys.escrow.pubkey(),
+                Pubkey::new_unique(),
+                spl_token::id(),
+                find_program_authority(&id()).0,
+                escrow.points_mint,
+                *points_token,
+            ],
+        ),
+        data: vec![TAG_SETTLE_WITH_MINT],
+    }
+}
+
+#[tokio::test]
+async fn settlement_pays_the_payee_and_mints_the_points() {
+    let mut context = program_test().start_with_context().await;
+    let escrow = init(&mut context, POINTS).await;
+    let keys = &escrow.keys;
+
+    let ix = settle_with_mint_ix(&escrow, &escrow.points_token);
+    process(&mut context, &[ix], &[&keys.authority]).await.unwrap();
+
+    assert_token_balances(
+        &mut context,
+        &[
+            (keys.payee_token.pubkey(), 990),
+            (keys.fee_token.pubkey(), 10),
+            (escrow.points_token, POINTS),
+        ],
+    )
+    .await;
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn escrow_without_points_fails() {
+    let mut context = program_test().start_with_context().await;
+    let escrow = init(&mut context, 0).await;
+
+    let ix = settle_with_mint_ix(&escrow, &escrow.points_token);
+    let result = process(&mut context, &[ix], &[&escrow.keys.authority]).await;
+    assert_custom_error(result, EscrowError::PointsNotConfigured);
+    assert!(!get_escrow(&mut context, &escrow.keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn points_account_of_another_owner_fails() {
+    let mut context = program_test().start_with_context().await;
+    let escrow = init(&mut context, POINTS).await;
+    let other = Keypair::new();
+    let mint = escrow.points_mint;
+    create_token_account(&mut context, &other, &mint, &Pubkey::new_unique()).await;
+
+    let ix = settle_with_mint_ix(&escrow, &other.pubkey());
+    let result = process(&mut context, &[ix], &[&escrow.keys.authority]).await;
+    assert_custom_error(result, EscrowError::InvalidPointsAccount);
+    assert_eq!(get_token_balance(&mut context, &other.pubkey()).await, 0);
+}
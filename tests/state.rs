@@ -0,0 +1,408 @@
+#![cfg(feature = "test-bpf")]
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    state::{Escrow, EscrowBuilder, EscrowState, EscrowSummary},
+    utils::safe_total,
+};
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+fn active_escrow() -> Escrow {
+    Escrow::new_active(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        1_000,
+    )
+}
+
+#[test]
+fn built_escrow_round_trips() {
+    let payee_token = Pubkey::new_unique();
+    let escrow = EscrowBuilder::new()
+        .payee_token(payee_token)
+        .amount(100)
+        .fee(2)
+        .settled()
+        .build();
+
+    let escrow = Escrow::unpack(&escrow.into_account_data()).unwrap();
+    assert_eq!(escrow.state(), EscrowState::Settled);
+    assert_eq!(escrow.payee_token, payee_token);
+    assert_eq!(escrow.amount, 100);
+    assert_eq!(escrow.fee, 2);
+    assert!(escrow.verify_checksum().is_ok());
+}
+
+#[test]
+fn default_escrow_is_uninitialized() {
+    let escrow = Escrow::unpack_unchecked(&Escrow::default().into_account_data()).unwrap();
+    assert_eq!(escrow.state(), EscrowState::Uninitialized);
+    assert_eq!(escrow.amount, 0);
+}
+
+#[test]
+fn settlement_fee_combines_flat_and_percentage() {
+    let fee = |amount, flat, bps, cap| {
+        let mut escrow = EscrowBuilder::new().amount(amount).fee(flat).build();
+        escrow.fee_bps = bps;
+        escrow.max_abs_fee = cap;
+        escrow.settlement_fee()
+    };
+    assert_eq!(fee(1_000, 7, 0, 0), 7);
+    assert_eq!(fee(1_000, 0, 50, 0), 5);
+    assert_eq!(fee(1_000, 1, 50, 0), 6);
+    assert_eq!(fee(999, 0, 50, 0), 4);
+    assert_eq!(fee(1_000, 1, 50, 3), 3);
+    assert_eq!(fee(1_000, 500, 10_000, 0), 1_000);
+    assert_eq!(fee(u64::MAX, u64::MAX, 10_000, 0), u64::MAX);
+}
+
+#[test]
+fn safe_total_catches_overflow() {
+    assert!(matches!(safe_total(u64::MAX - 1, 1), Ok(u64::MAX)));
+    assert!(matches!(
+        safe_total(u64::MAX, 1),
+        Err(EscrowError::AmountOverflow)
+    ));
+    assert!(matches!(
+        safe_total(u64::MAX / 2 + 1, u64::MAX / 2 + 1),
+        Err(EscrowError::AmountOverflow)
+    ));
+}
+
+/// Deterministic splitmix64, enough to spread values over every byte of the layout
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    fn bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+    fn bytes(&mut self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+        bytes
+    }
+    fn pubkey(&mut self) -> Pubkey {
+        Pubkey::new_from_array(self.bytes())
+    }
+}
+
+/// A random escrow of a random version, the fields added after that version left zero
+fn random_escrow(rng: &mut Rng) -> Escrow {
+    let version = rng.next_u64() as u8 % (Escrow::current_version() + 1);
+    let since = |added: u8| version == 0 || version >= added;
+    Escrow {
+        version,
+        is_initialized: rng.bool(),
+        is_settled: rng.bool(),
+        is_canceled: rng.bool(),
+        payer: rng.pubkey(),
+        payer_token: rng.pubkey(),
+        payee_token: rng.pubkey(),
+        vault_token: rng.pubkey(),
+        fee_token: rng.pubkey(),
+        authority: rng.pubkey(),
+        amount: rng.next_u64(),
+        fee: rng.next_u64(),
+        callback_program: rng.pubkey(),
+        callback_optional: rng.bool(),
+        mint: rng.pubkey(),
+        max_abs_fee: rng.next_u64(),
+        points_mint: rng.pubkey(),
+        points_amount: rng.next_u64(),
+        require_payer_on_cancel: rng.bool(),
+        checksum: rng.next_u64(),
+        restock_fee: rng.next_u64(),
+        restock_token: rng.pubkey(),
+        derived_vault: rng.bool(),
+        settle_not_before: rng.next_u64() as i64,
+        settle_not_after: rng.next_u64() as i64,
+        settle_memo: rng.bytes(),
+        rotation_count: rng.next_u64() as u16,
+        condition_account: rng.pubkey(),
+        condition_owner: rng.pubkey(),
+        fee_bps: rng.next_u64() as u16,
+        token_program: rng.pubkey(),
+        counterparty_mint: rng.pubkey(),
+        counterparty_amount: rng.next_u64(),
+        counterparty_token: rng.pubkey(),
+        counterparty_vault: rng.pubkey(),
+        counterparty_refund: rng.pubkey(),
+        authority_bump: rng.next_u64() as u8,
+        expires_at: rng.next_u64() as i64,
+        resolved_at: 0,
+        allow_balance_drift: since(3) && rng.bool(),
+        derived_escrow: since(4) && rng.bool(),
+        escrow_bump: if since(4) { rng.next_u64() as u8 } else { 0 },
+        require_dual_sig: since(5) && rng.bool(),
+        is_disputed: since(6) && rng.bool(),
+        dispute_resolution: if since(6) { rng.next_u64() as u8 } else { 0 },
+        dynamic_fee_recipient: since(7) && rng.bool(),
+    }
+}
+
+#[test]
+fn random_escrows_round_trip() {
+    let mut rng = Rng(0x5eed);
+    for _ in 0..1_000 {
+        let mut escrow = random_escrow(&mut rng);
+        // Earlier versions lack the timestamp and read it as zero
+        if escrow.version != 1 {
+            escrow.resolved_at = rng.next_u64() as i64;
+        }
+        let data = escrow.clone().into_account_data();
+        assert_eq!(Escrow::unpack_unchecked(&data).unwrap(), escrow);
+    }
+}
+
+#[test]
+fn pack_into_short_buffer_fails() {
+    let mut short = vec![0; Escrow::LEN - 1];
+    assert_eq!(
+        Escrow::pack(active_escrow(), &mut short),
+        Err(ProgramError::InvalidAccountData)
+    );
+    assert!(short.iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn unpack_short_buffer_fails() {
+    let data = active_escrow().into_account_data();
+    let short = &data[..Escrow::LEN - 1];
+    assert!(matches!(
+        Escrow::unpack(short),
+        Err(ProgramError::InvalidAccountData)
+    ));
+    assert!(matches!(
+        Escrow::unpack_unchecked(short),
+        Err(ProgramError::InvalidAccountData)
+    ));
+}
+
+#[test]
+fn rotating_twice_counts_two() {
+    let mut escrow = active_escrow();
+    let second = Pubkey::new_unique();
+    let third = Pubkey::new_unique();
+
+    escrow.rotate_authority(second);
+    escrow.rotate_authority(third);
+
+    assert_eq!(escrow.authority, third);
+    assert_eq!(escrow.rotation_count, 2);
+    assert!(escrow.verify_checksum().is_ok());
+    let escrow = Escrow::unpack(&escrow.into_account_data()).unwrap();
+    assert_eq!(escrow.rotation_count, 2);
+}
+
+#[test]
+fn rotation_count_saturates() {
+    let mut escrow = active_escrow();
+    escrow.rotation_count = u16::MAX;
+
+    escrow.rotate_authority(Pubkey::new_unique());
+
+    assert_eq!(escrow.rotation_count, u16::MAX);
+}
+
+#[test]
+fn version_leads_the_layout() {
+    let escrow = active_escrow();
+    assert_eq!(escrow.version, Escrow::current_version());
+    let data = escrow.clone().into_account_data();
+    assert_eq!(data[0], Escrow::current_version());
+    assert_eq!(Escrow::unpack(&data).unwrap(), escrow);
+}
+
+#[test]
+fn unknown_version_fails() {
+    let mut data = active_escrow().into_account_data();
+    data[0] = Escrow::current_version() + 1;
+    assert!(matches!(
+        Escrow::unpack(&data),
+        Err(ProgramError::InvalidAccountData)
+    ));
+}
+
+#[test]
+fn version_1_account_reads_without_resolved_at() {
+    // An account written by version 1, reallocated to the current length
+    let mut v1 = active_escrow();
+    v1.version = 1;
+    assert_eq!(Escrow::unpack(&v1.clone().into_account_data()).unwrap(), v1);
+
+    // Stale bytes where the timestamp now lives are not read for version 1
+    let mut stale = v1;
+    stale.resolved_at = 42;
+    let mut data = stale.into_account_data();
+    assert_eq!(Escrow::unpack(&data).unwrap().resolved_at, 0);
+    data[0] = 2;
+    assert_eq!(Escrow::unpack(&data).unwrap().resolved_at, 42);
+}
+#[test]
+fn version_1_length_account_unpacks_with_defaults() {
+    let mut v1 = active_escrow();
+    v1.version = 1;
+    v1.expires_at = 1_700_000_000;
+    let data = v1.clone().into_account_data();
+    let v1_data = &data[..Escrow::packed_len(1).unwrap()];
+
+    let escrow = Escrow::unpack(v1_data).unwrap();
+    assert_eq!(escrow.version, 1);
+    assert_eq!(escrow.expires_at, 1_700_000_000);
+    assert_eq!(escrow, v1);
+    assert_eq!(Escrow::unpack_borsh(v1_data).unwrap(), v1);
+    assert!(escrow.verify_checksum().is_ok());
+
+    // The length has to be the one of the version the account claims
+    let mut v2_data = v1_data.to_vec();
+    v2_data[0] = 2;
+    assert_eq!(Escrow::unpack(&v2_data), Err(ProgramError::InvalidAccountData));
+    assert_eq!(Escrow::unpack(&[]), Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn fields_of_later_versions_read_as_zero() {
+    let mut escrow = active_escrow();
+    escrow.resolved_at = 42;
+    escrow.allow_balance_drift = true;
+    escrow.derived_escrow = true;
+    escrow.escrow_bump = 254;
+    escrow.require_dual_sig = true;
+    escrow.is_disputed = true;
+    escrow.dispute_resolution = 1;
+    escrow.dynamic_fee_recipient = true;
+    for version in 1..Escrow::current_version() {
+        escrow.version = version;
+        let stale = Escrow::unpack(&escrow.clone().into_account_data()).unwrap();
+        let short = escrow.clone().into_account_data()[..Escrow::packed_len(version).unwrap()]
+            .to_vec();
+        assert_eq!(Escrow::unpack(&short).unwrap(), stale);
+        assert_eq!(stale.resolved_at != 0, version >= 2);
+        assert_eq!(stale.allow_balance_drift, version >= 3);
+        assert_eq!(stale.derived_escrow, version >= 4);
+        assert_eq!(stale.escrow_bump != 0, version >= 4);
+        assert_eq!(stale.require_dual_sig, version >= 5);
+        assert_eq!(stale.is_disputed, version >= 6);
+        assert_eq!(stale.dispute_resolution != 0, version >= 6);
+        assert!(!stale.dynamic_fee_recipient);
+    }
+    assert_eq!(
+        Escrow::packed_len(Escrow::current_version()),
+        Some(Escrow::LEN)
+    );
+}
+
+#[test]
+fn older_layout_packs_at_its_own_length() {
+    let mut escrow = active_escrow();
+    escrow.version = 6;
+    escrow.is_settled = true;
+    escrow.resolved_at = 42;
+    let mut short = vec![0; Escrow::packed_len(6).unwrap()];
+    Escrow::pack(escrow.clone(), &mut short).unwrap();
+    assert_eq!(Escrow::unpack(&short).unwrap(), escrow);
+    assert_eq!(escrow.clone().into_account_data()[..short.len()], short[..]);
+
+    // A field the layout lacks isn't dropped silently
+    escrow.dynamic_fee_recipient = true;
+    assert_eq!(
+        Escrow::pack(escrow.clone(), &mut short),
+        Err(ProgramError::InvalidAccountData)
+    );
+    let mut other = vec![0; Escrow::packed_len(5).unwrap()];
+    assert_eq!(Escrow::pack(escrow, &mut other), Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn decodes_account_data_with_summary() {
+    let mut escrow = EscrowBuilder::new().amount(1_000).fee(10).build();
+    escrow.is_disputed = true;
+
+    let decoded = Escrow::from_account_data(&escrow.clone().into_account_data()).unwrap();
+    assert_eq!(decoded, escrow);
+    assert_eq!(
+        decoded.summary(),
+        EscrowSummary {
+            state: EscrowState::Active,
+            is_disputed: true,
+            amount: 1_000,
+            fee: 10,
+        }
+    );
+    assert_eq!(
+        decoded.summary().to_string(),
+        "Active (disputed) { amount: 1000, fee: 10 }"
+    );
+
+    let settled = EscrowBuilder::new().amount(100).fee(2).settled().build();
+    let summary = Escrow::from_account_data(&settled.into_account_data())
+        .unwrap()
+        .summary();
+    assert_eq!(summary.to_string(), "Settled { amount: 100, fee: 2 }");
+
+    assert_eq!(
+        Escrow::from_account_data(&Escrow::default().into_account_data()),
+        Err(ProgramError::UninitializedAccount)
+    );
+    assert_eq!(Escrow::from_account_data(&[]), Err(ProgramError::InvalidAccountData));
+}
+
+#[test]
+fn settlement_breakdown_matches_settle() {
+    let escrow = |fee| EscrowBuilder::new().amount(1_000).fee(fee).build();
+    assert_eq!(escrow(0).settlement_breakdown(1_000), Ok((1_000, 0)));
+    assert_eq!(escrow(10).settlement_breakdown(1_000), Ok((990, 10)));
+    assert_eq!(escrow(1_000).settlement_breakdown(1_000), Ok((0, 1_000)));
+    // Settle caps the fee at the vault balance instead of failing
+    assert_eq!(escrow(10).settlement_breakdown(4), Ok((0, 4)));
+
+    let mut shared = escrow(10);
+    shared.fee_bps = 250;
+    assert_eq!(shared.settlement_breakdown(2_000), Ok((1_940, 60)));
+    shared.max_abs_fee = 25;
+    assert_eq!(shared.settlement_breakdown(2_000), Ok((1_975, 25)));
+}
+
+#[test]
+fn borsh_matches_the_pack_layout() {
+    let mut rng = Rng(0xb0b5);
+    for _ in 0..1_000 {
+        let mut escrow = random_escrow(&mut rng);
+        if escrow.version != 1 {
+            escrow.resolved_at = rng.next_u64() as i64;
+        }
+        let borsh = escrow.pack_borsh().unwrap();
+        assert_eq!(borsh.len(), Escrow::LEN);
+        assert_eq!(borsh, escrow.clone().into_account_data());
+        assert_eq!(Escrow::unpack_from_slice(&borsh).unwrap(), escrow);
+        assert_eq!(Escrow::unpack_borsh(&borsh).unwrap(), escrow);
+    }
+}
+
+#[test]
+fn borsh_applies_the_version_checks() {
+    let mut data = active_escrow().into_account_data();
+    data[0] = Escrow::current_version() + 1;
+    assert_eq!(Escrow::unpack_borsh(&data), Err(ProgramError::InvalidAccountData));
+    assert_eq!(
+        Escrow::unpack_borsh(&data[..Escrow::LEN - 1]),
+        Err(ProgramError::InvalidAccountData)
+    );
+
+    let mut stale = active_escrow();
+    stale.version = 1;
+    stale.resolved_at = 42;
+    assert_eq!(Escrow::unpack_borsh(&stale.into_account_data()).unwrap().resolved_at, 0);
+}
@@ -0,0 +1,236 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    id,
+    instruction::{account_metas, InitOptions, DEPOSIT_COUNTERPARTY_ACCOUNTS, TAG_DEPOSIT_COUNTERPARTY},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+const AMOUNT: u64 = 1_000;
+const FEE: u64 = 10;
+const COUNTERPARTY_AMOUNT: u64 = 250;
+
+/// Both sides of a swap: the payer's escrow and the payee's deposit of another mint
+struct Swap {
+    escrow: SplEscrow,
+    payee: Keypair,
+    fee_payer: Keypair,
+    counterparty_vault: Keypair,
+    counterparty_token: Keypair,
+    counterparty_refund: Keypair,
+}
+
+async fn init_swap(context: &mut ProgramTestContext) -> Swap {
+    let swap = Swap {
+        escrow: SplEscrow::default(),
+        payee: Keypair::new(),
+        fee_payer: Keypair::new(),
+        counterparty_vault: Keypair::new(),
+        counterparty_token: Keypair::new(),
+        counterparty_refund: Keypair::new(),
+    };
+    let keys = &swap.escrow;
+    create_spl_escrow_accounts(context, keys, &swap.payee.pubkey(), &Pubkey::new_unique(), AMOUNT)
+        .await;
+
+    let mint = Keypair::new();
+    let mint_authority = Keypair::new();
+    create_mint(context, &mint, &mint_authority.pubkey()).await;
+    let mint = mint.pubkey();
+    create_token_account(context, &swap.counterparty_vault, &mint, &swap.payee.pubkey()).await;
+    create_token_account(context, &swap.counterparty_refund, &mint, &swap.payee.pubkey()).await;
+    create_token_account(context, &swap.counterparty_token, &mint, &keys.payer.pubkey()).await;
+    mint_to(
+        context,
+        &mint,
+        &swap.counterparty_vault.pubkey(),
+        &mint_authority,
+        COUNTERPARTY_AMOUNT,
+    )
+    .await;
+
+    let options = InitOptions {
+        counterparty_mint: mint,
+        counterparty_amount: COUNTERPARTY_AMOUNT,
+        counterparty_token: swap.counterparty_token.pubkey(),
+        ..InitOptions::default()
+    };
+    process(
+        context,
+        &[with_options(spl_init_ix(keys, AMOUNT, FEE), &options)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    swap
+}
+
+fn deposit_ix(swap: &Swap) -> Instruction {
+    Instruction {
+        program_id: id(),
+        accounts: account_metas(
+            DEPOSIT_COUNTERPARTY_ACCOUNTS,
+            &[
+                swap.payee.pubkey(),
+                swap.escrow.escrow.pubkey(),
+                swap.escrow.payee_token.pubkey(),
+                swap.counterparty_vault.pubkey(),
+                swap.counterparty_refund.pubkey(),
+                spl_token::id(),
+            ],
+        ),
+        data: vec![TAG_DEPOSIT_COUNTERPARTY],
+    }
+}
+
+fn swap_settle_ix(swap: &Swap) -> Instruction {
+    let keys = &swap.escrow;
+    let mut ix = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &swap.fee_payer.pubkey(),
+    );
+    ix.accounts.extend([
+        AccountMeta::new(swap.counterparty_vault.pubkey(), false),
+        AccountMeta::new(swap.counterparty_token.pubkey(), false),
+    ]);
+    ix
+}
+
+#[tokio::test]
+async fn swap_settles_both_sides() {
+    let mut context = program_test().start_with_context().await;
+    let swap = init_swap(&mut context).await;
+    let keys = &swap.escrow;
+
+    process(&mut context, &[deposit_ix(&swap)], &[&swap.payee])
+        .await
+        .unwrap();
+    let vault = get_token_account(&mut context, &swap.counterparty_vault.pubkey()).await;
+    assert_eq!(vault.owner, solana_escrow_payment::find_program_authority(&id()).0);
+    assert_eq!(
+        get_escrow(&mut context, &keys.escrow.pubkey()).await.counterparty_vault,
+        swap.counterparty_vault.pubkey()
+    );
+
+    process(&mut context, &[swap_settle_ix(&swap)], &[&keys.authority])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut context, &keys.payee_token.pubkey()).await,
+        AMOUNT - FEE
+    );
+    assert_eq!(
+        get_token_balance(&mut context, &swap.counterparty_token.pubkey()).await,
+        COUNTERPARTY_AMOUNT
+    );
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert_eq!(
+        get_balance(&mut context, &swap.counterparty_vault.pubkey()).await,
+        0
+    );
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn settle_before_deposit_fails() {
+    let mut context = program_test().start_with_context().await;
+    let swap = init_swap(&mut context).await;
+
+    let result = process(
+        &mut context,
+        &[swap_settle_ix(&swap)],
+        &[&swap.escrow.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::CounterpartyNotDeposited);
+}
+
+#[tokio::test]
+async fn deposit_twice_fails() {
+    let mut context = program_test().start_with_context().await;
+    let swap = init_swap(&mut context).await;
+    process(&mut context, &[deposit_ix(&swap)], &[&swap.payee])
+        .await
+        .unwrap();
+
+    // Another vault keeps the transaction distinct from the first deposit
+    let mut ix = deposit_ix(&swap);
+    ix.accounts[3].pubkey = Pubkey::new_unique();
+    let result = process(&mut context, &[ix], &[&swap.payee]).await;
+    assert_custom_error(result, EscrowError::CounterpartyAlreadyDeposited);
+}
+
+#[tokio::test]
+async fn cancel_refunds_both_sides() {
+    let mut context = program_test().start_with_context().await;
+    let swap = init_swap(&mut context).await;
+    let keys = &swap.escrow;
+    process(&mut context, &[deposit_ix(&swap)], &[&swap.payee])
+        .await
+        .unwrap();
+
+    let mut ix = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &swap.fee_payer.pubkey(),
+        &keys.vault.pubkey(),
+    );
+    ix.accounts.extend([
+        AccountMeta::new(swap.counterparty_vault.pubkey(), false),
+        AccountMeta::new(swap.counterparty_refund.pubkey(), false),
+    ]);
+    process(&mut context, &[ix], &[&keys.authority])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut context, &keys.payer_token.pubkey()).await,
+        AMOUNT
+    );
+    assert_eq!(
+        get_token_balance(&mut context, &swap.counterparty_refund.pubkey()).await,
+        COUNTERPARTY_AMOUNT
+    );
+    assert_eq!(
+        get_token_balance(&mut context, &swap.counterparty_token.pubkey()).await,
+        0
+    );
+    assert_eq!(
+        get_balance(&mut context, &swap.counterparty_vault.pubkey()).await,
+        0
+    );
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
+
+#[tokio::test]
+async fn failed_counterparty_release_is_a_payee_transfer_failure() {
+    let mut context = program_test().start_with_context().await;
+    let swap = init_swap(&mut context).await;
+    process(&mut context, &[deposit_ix(&swap)], &[&swap.payee])
+        .await
+        .unwrap();
+    freeze_token_account(&mut context, &swap.counterparty_token.pubkey()).await;
+
+    let result = process(
+        &mut context,
+        &[swap_settle_ix(&swap)],
+        &[&swap.escrow.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::PayeeTransferFailed);
+}
@@ -0,0 +1,200 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::error::EscrowError;
+use solana_program::{instruction::InstructionError, program_pack::Pack, system_instruction};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use spl_token::state::Account as TokenAccount;
+use spl_token_2022::extension::ExtensionType;
+use utils::*;
+
+fn token_2022_keys() -> SplEscrow {
+    SplEscrow {
+        token_program: spl_token_2022::id(),
+        ..SplEscrow::default()
+    }
+}
+
+async fn init(context: &mut ProgramTestContext, amount: u64, fee: u64) -> SplEscrow {
+    init_spl_escrow_with_owners(
+        context,
+        token_2022_keys(),
+        &Keypair::new().pubkey(),
+        &Keypair::new().pubkey(),
+        amount,
+        fee,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn token_2022_escrow_settles() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000;
+    let fee = 10;
+    let keys = init(&mut context, amount, fee).await;
+    let fee_payer = Keypair::new();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.token_program, spl_token_2022::id());
+    let vault = context
+        .banks_client
+        .get_account(keys.vault.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(vault.owner, spl_token_2022::id());
+
+    let ix = settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &fee_payer.pubkey(),
+    );
+    process(
+        &mut context,
+        &[with_token_program(ix, &spl_token_2022::id())],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut context, &keys.payee_token.pubkey()).await,
+        amount - fee
+    );
+    assert_eq!(
+        get_token_balance(&mut context, &keys.fee_token.pubkey()).await,
+        fee
+    );
+    assert_eq!(get_balance(&mut context, &keys.vault.pubkey()).await, 0);
+    assert_eq!(
+        get_balance(&mut context, &fee_payer.pubkey()).await,
+        rent.minimum_balance(TokenAccount::LEN)
+    );
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_settled);
+}
+
+#[tokio::test]
+async fn token_2022_escrow_cancels() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000;
+    let keys = init(&mut context, amount, 10).await;
+
+    let ix = cancel_ix(
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &Keypair::new().pubkey(),
+        &keys.vault.pubkey(),
+    );
+    process(
+        &mut context,
+        &[with_token_program(ix, &spl_token_2022::id())],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut context, &keys.payer_token.pubkey()).await,
+        amount
+    );
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_canceled);
+}
+
+#[tokio::test]
+async fn classic_accounts_are_rejected_under_token_2022() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000;
+    let keys = SplEscrow::default();
+    create_spl_escrow_accounts(
+        &mut context,
+        &keys,
+        &Keypair::new().pubkey(),
+        &Keypair::new().pubkey(),
+        amount,
+    )
+    .await;
+
+    let ix = with_token_program(spl_init_ix(&keys, amount, 10), &spl_token_2022::id());
+    let result = process(&mut context, &[ix], &[&keys.payer, &keys.authority]).await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::IllegalOwner
+        )))
+    ));
+}
+
+#[tokio::test]
+async fn settle_must_use_the_escrow_token_program() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init(&mut context, 1_000, 10).await;
+
+    let result = process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await;
+    assert_custom_error(result, EscrowError::TokenProgramMismatch);
+}
+
+#[tokio::test]
+async fn vault_with_extensions_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let amount = 1_000;
+    let keys = token_2022_keys();
+    create_spl_escrow_accounts(
+        &mut context,
+        &keys,
+        &Keypair::new().pubkey(),
+        &Keypair::new().pubkey(),
+        amount,
+    )
+    .await;
+    let (vault, mint, payer) = (Keypair::new(), keys.mint.pubkey(), keys.payer.pubkey());
+    let len = ExtensionType::get_account_len::<spl_token_2022::state::Account>(&[
+        ExtensionType::ImmutableOwner,
+    ]);
+    let program = spl_token_2022::id();
+    let instructions = [
+        system_instruction::create_account(
+            &context.payer.pubkey(),
+            &vault.pubkey(),
+            rent.minimum_balance(len),
+            len as u64,
+            &program,
+        ),
+        spl_token_2022::instruction::initialize_immutable_owner(&program, &vault.pubkey())
+            .unwrap(),
+        spl_token_2022::instruction::initialize_account(&program, &vault.pubkey(), &mint, &payer)
+            .unwrap(),
+    ];
+    process(&mut context, &instructions, &[&vault]).await.unwrap();
+    mint_to_of(&mut context, &program, &mint, &vault.pubkey(), &keys.mint_authority, amount).await;
+
+    let mut ix = spl_init_ix(&keys, amount, 10);
+    ix.accounts[1].pubkey = vault.pubkey();
+    let result = process(&mut context, &[ix], &[&keys.payer, &keys.authority]).await;
+    assert_custom_error(result, EscrowError::TokenExtensionsUnsupported);
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "test-bpf")]
+
+use solana_escrow_payment::{error::EscrowError, utils::assert_token_account};
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+};
+use spl_token::state::{Account as TokenAccount, AccountState};
+
+/// Packed data of an initialized token account holding 1_000 of `mint`
+fn token_account_data(mint: &Pubkey) -> Vec<u8> {
+    let mut data = vec![0; TokenAccount::LEN];
+    let account = TokenAccount {
+        mint: *mint,
+        owner: Pubkey::new_unique(),
+        amount: 1_000,
+        state: AccountState::Initialized,
+        ..TokenAccount::default()
+    };
+    TokenAccount::pack(account, &mut data).unwrap();
+    data
+}
+
+#[test]
+fn token_account_passes_every_check() {
+    let (key, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut lamports = 0;
+    let mut data = token_account_data(&mint);
+    let info = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &spl_token::ID,
+        false,
+        0,
+    );
+
+    let account = assert_token_account(&info, &spl_token::id(), Some(&mint), Some(&key)).unwrap();
+    assert_eq!(account.amount, 1_000);
+    assert_eq!(assert_token_account(&info, &spl_token::id(), None, None), Ok(account));
+}
+
+#[test]
+fn each_failed_check_has_its_error() {
+    let (key, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mut lamports = 0;
+    let mut data = token_account_data(&mint);
+    let info = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &spl_token::ID,
+        false,
+        0,
+    );
+    let token_program = spl_token::id();
+
+    assert_eq!(
+        assert_token_account(&info, &token_program, Some(&mint), Some(&Pubkey::new_unique())),
+        Err(ProgramError::InvalidArgument)
+    );
+    assert_eq!(
+        assert_token_account(&info, &spl_token_2022::id(), Some(&mint), Some(&key)),
+        Err(ProgramError::IllegalOwner)
+    );
+    assert_eq!(
+        assert_token_account(&info, &token_program, Some(&Pubkey::new_unique()), Some(&key)),
+        Err(EscrowError::MintMismatch.into())
+    );
+
+    let mut lamports = 0;
+    let mut empty = vec![0; TokenAccount::LEN];
+    let uninitialized = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut empty,
+        &spl_token::ID,
+        false,
+        0,
+    );
+    assert_eq!(
+        assert_token_account(&uninitialized, &token_program, Some(&mint), Some(&key)),
+        Err(EscrowError::AccountNotInitialized.into())
+    );
+}
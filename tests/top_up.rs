@@ -0,0 +1,100 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{error::EscrowError, id, instruction::top_up};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+#[tokio::test]
+async fn top_up_grows_the_escrowed_amount() {
+    let mut context = program_test().start_with_context().await;
+    let amount = 1_000;
+    let fee = 10;
+    let extra = 250;
+    let keys = init_spl_escrow(&mut context, amount, fee).await;
+    let mint = keys.mint.pubkey();
+    mint_to(&mut context, &mint, &keys.payer_token.pubkey(), &keys.mint_authority, extra).await;
+
+    process(
+        &mut context,
+        &[top_up(
+            &id(),
+            &keys.payer.pubkey(),
+            &keys.payer_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            extra,
+        )],
+        &[&keys.payer],
+    )
+    .await
+    .unwrap();
+
+    let escrow = get_escrow(&mut context, &keys.escrow.pubkey()).await;
+    assert_eq!(escrow.amount, amount + extra);
+    assert_eq!(
+        get_token_balance(&mut context, &keys.vault.pubkey()).await,
+        amount + extra
+    );
+    assert_eq!(get_token_balance(&mut context, &keys.payer_token.pubkey()).await, 0);
+
+    // The vault still matches the escrowed amount, so it settles in full
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        get_token_balance(&mut context, &keys.payee_token.pubkey()).await,
+        amount + extra - fee
+    );
+}
+
+#[tokio::test]
+async fn settled_escrow_cant_be_topped_up() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let mint = keys.mint.pubkey();
+    mint_to(&mut context, &mint, &keys.payer_token.pubkey(), &keys.mint_authority, 250).await;
+    process(
+        &mut context,
+        &[settle_ix(
+            &keys.authority.pubkey(),
+            &keys.payee_token.pubkey(),
+            &keys.fee_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            &Keypair::new().pubkey(),
+        )],
+        &[&keys.authority],
+    )
+    .await
+    .unwrap();
+
+    let result = process(
+        &mut context,
+        &[top_up(
+            &id(),
+            &keys.payer.pubkey(),
+            &keys.payer_token.pubkey(),
+            &keys.vault.pubkey(),
+            &keys.escrow.pubkey(),
+            250,
+        )],
+        &[&keys.payer],
+    )
+    .await;
+
+    assert_custom_error(result, EscrowError::AccountAlreadySettled);
+}
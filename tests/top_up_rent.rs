@@ -0,0 +1,123 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::{
+    id,
+    instruction::InitOptions,
+    state::{Escrow, EscrowState},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use utils::*;
+
+const SHORTFALL: u64 = 1_000;
+
+/// Accounts of an SPL escrow whose escrow account is `SHORTFALL` short of rent exemption.
+/// The runtime refuses to create such an account, so it is seeded at genesis.
+async fn start() -> (ProgramTestContext, SplEscrow) {
+    let keys = SplEscrow::default();
+    let mut program_test = program_test();
+    program_test.add_account(
+        keys.escrow.pubkey(),
+        Account {
+            lamports: Rent::default().minimum_balance(Escrow::LEN) - SHORTFALL,
+            data: vec![0; Escrow::LEN],
+            owner: id(),
+            ..Account::default()
+        },
+    );
+    let mut context = program_test.start_with_context().await;
+    let keys = accounts(&mut context, keys).await;
+    (context, keys)
+}
+
+async fn accounts(context: &mut ProgramTestContext, keys: SplEscrow) -> SplEscrow {
+    let (payee_owner, fee_owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let mint = keys.mint.pubkey();
+    let program = &keys.token_program;
+    create_mint_of(context, program, &keys.mint, &keys.mint_authority.pubkey()).await;
+    let payer = keys.payer.pubkey();
+    for (account, owner) in [
+        (&keys.vault, &payer),
+        (&keys.payer_token, &payer),
+        (&keys.payee_token, &payee_owner),
+        (&keys.fee_token, &fee_owner),
+    ] {
+        create_token_account_of(context, program, account, &mint, owner).await;
+    }
+    let vault = keys.vault.pubkey();
+    mint_to_of(context, program, &mint, &vault, &keys.mint_authority, 1_000).await;
+    keys
+}
+
+fn init_with_top_up(keys: &SplEscrow, funder: &Pubkey) -> Instruction {
+    let options = InitOptions {
+        top_up_rent: true,
+        ..InitOptions::default()
+    };
+    let mut ix = with_options(spl_init_ix(keys, 1_000, 10), &options);
+    ix.accounts.extend([
+        AccountMeta::new(*funder, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ]);
+    ix
+}
+
+#[tokio::test]
+async fn under_funded_escrow_is_topped_up_by_the_funder() {
+    let (mut context, keys) = start().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let funder = Keypair::new();
+    fund(&mut context, &funder.pubkey(), 1_000_000_000).await;
+
+    let ix = init_with_top_up(&keys, &funder.pubkey());
+    process(&mut context, &[ix], &[&keys.payer, &keys.authority, &funder])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        get_balance(&mut context, &keys.escrow.pubkey()).await,
+        rent.minimum_balance(Escrow::LEN)
+    );
+    assert_eq!(
+        get_balance(&mut context, &funder.pubkey()).await,
+        1_000_000_000 - SHORTFALL
+    );
+    assert!(get_escrow(&mut context, &keys.escrow.pubkey()).await.is_initialized);
+}
+
+#[tokio::test]
+async fn under_funded_escrow_fails_without_the_top_up() {
+    let (mut context, keys) = start().await;
+
+    let result = process(
+        &mut context,
+        &[spl_init_ix(&keys, 1_000, 10)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::AccountNotRentExempt
+        )))
+    ));
+    assert_eq!(
+        get_escrow_state(&mut context, &keys.escrow.pubkey()).await,
+        EscrowState::Uninitialized
+    );
+}
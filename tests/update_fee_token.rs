@@ -0,0 +1,95 @@
+#![cfg(feature = "test-bpf")]
+
+mod utils;
+
+use solana_escrow_payment::error::EscrowError;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+use utils::*;
+
+/// A token account of the escrow mint for the rotated fee
+async fn rotated_fee_token(context: &mut ProgramTestContext, keys: &SplEscrow) -> Pubkey {
+    let rotated = Keypair::new();
+    let mint = keys.mint.pubkey();
+    create_token_account(context, &rotated, &mint, &Pubkey::new_unique()).await;
+    rotated.pubkey()
+}
+
+fn settle(keys: &SplEscrow, fee_token: &Pubkey) -> Instruction {
+    settle_ix(
+        &keys.authority.pubkey(),
+        &keys.payee_token.pubkey(),
+        fee_token,
+        &keys.vault.pubkey(),
+        &keys.escrow.pubkey(),
+        &Keypair::new().pubkey(),
+    )
+}
+
+#[tokio::test]
+async fn rotated_fee_token_takes_the_fee() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let rotated = rotated_fee_token(&mut context, &keys).await;
+
+    let ix = update_fee_token_ix(&keys.authority.pubkey(), &keys.escrow.pubkey(), &rotated);
+    process(&mut context, &[ix], &[&keys.authority]).await.unwrap();
+    assert_eq!(get_escrow(&mut context, &keys.escrow.pubkey()).await.fee_token, rotated);
+
+    // The previous fee token account is no longer accepted
+    let result = process(
+        &mut context,
+        &[settle(&keys, &keys.fee_token.pubkey())],
+        &[&keys.authority],
+    )
+    .await;
+    assert!(result.is_err());
+    process(&mut context, &[settle(&keys, &rotated)], &[&keys.authority])
+        .await
+        .unwrap();
+    assert_token_balances(
+        &mut context,
+        &[(rotated, 10), (keys.fee_token.pubkey(), 0), (keys.payee_token.pubkey(), 990)],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn fee_token_of_another_mint_is_rejected() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let (other_mint, other_token) = (Keypair::new(), Keypair::new());
+    create_mint(&mut context, &other_mint, &Pubkey::new_unique()).await;
+    let mint = other_mint.pubkey();
+    create_token_account(&mut context, &other_token, &mint, &Pubkey::new_unique()).await;
+
+    let escrow = keys.escrow.pubkey();
+    let ix = update_fee_token_ix(&keys.authority.pubkey(), &escrow, &other_token.pubkey());
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::MintMismatch);
+    assert_eq!(
+        get_escrow(&mut context, &escrow).await.fee_token,
+        keys.fee_token.pubkey()
+    );
+}
+
+#[tokio::test]
+async fn only_the_authority_updates_an_active_escrow() {
+    let mut context = program_test().start_with_context().await;
+    let keys = init_spl_escrow(&mut context, 1_000, 10).await;
+    let rotated = rotated_fee_token(&mut context, &keys).await;
+    let escrow = keys.escrow.pubkey();
+
+    let intruder = Keypair::new();
+    let ix = update_fee_token_ix(&intruder.pubkey(), &escrow, &rotated);
+    let result = process(&mut context, &[ix], &[&intruder]).await;
+    assert_custom_error(result, EscrowError::InvalidAuthority);
+
+    process(&mut context, &[settle(&keys, &keys.fee_token.pubkey())], &[&keys.authority])
+        .await
+        .unwrap();
+    let ix = update_fee_token_ix(&keys.authority.pubkey(), &escrow, &rotated);
+    let result = process(&mut context, &[ix], &[&keys.authority]).await;
+    assert_custom_error(result, EscrowError::AccountAlreadySettled);
+}
@@ -0,0 +1,659 @@
+#![allow(dead_code)]
+
+use solana_escrow_payment::{
+    error::EscrowError,
+    find_program_authority, id,
+    instruction::{
+        self, account_metas, InitOptions, CLOSE_MANY_TO_TREASURY_ACCOUNTS,
+        FORCE_RECLAIM_ACCOUNTS, SETTLE_MANY_ACCOUNTS, SETTLE_MANY_GROUP,
+        TAG_CLOSE_MANY_TO_TREASURY, TAG_FORCE_RECLAIM, TAG_SETTLE_MANY, TAG_UPDATE_FEE_TOKEN,
+        UPDATE_FEE_TOKEN_ACCOUNTS,
+    },
+    processor::Processor,
+    state::{Escrow, EscrowState},
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+pub fn program_test() -> ProgramTest {
+    let mut program_test =
+        ProgramTest::new("solana_escrow_payment", id(), processor!(Processor::process));
+    // The BPF runtime comes with the classic token program
+    if !bpf_runtime() {
+        program_test.add_program(
+            "spl_token",
+            spl_token::id(),
+            processor!(spl_token::processor::Processor::process),
+        );
+    }
+    program_test.add_program(
+        "spl_token_2022",
+        spl_token_2022::id(),
+        processor!(spl_token_2022::processor::Processor::process),
+    );
+    program_test
+}
+
+/// Whether the programs run as BPF, `cargo test-bpf` sets `BPF_OUT_DIR` to the built programs.
+/// The native runner can't grow an account created by a system program CPI, tests creating
+/// program derived accounts only run against the BPF build.
+pub fn bpf_runtime() -> bool {
+    std::env::var("BPF_OUT_DIR").is_ok()
+}
+
+/// Points the token program accounts of an instruction built for the classic program, and
+/// the instruction itself when it is a token one, at `token_program`
+pub fn with_token_program(mut ix: Instruction, token_program: &Pubkey) -> Instruction {
+    if ix.program_id == spl_token::id() {
+        ix.program_id = *token_program;
+    }
+    for meta in ix.accounts.iter_mut() {
+        if meta.pubkey == spl_token::id() {
+            meta.pubkey = *token_program;
+        }
+    }
+    ix
+}
+
+/// Seeds a vault of `amount` tokens owned by the program authority, a wrapped SOL one for the
+/// native mint
+pub fn add_vault(program_test: &mut ProgramTest, key: &Pubkey, mint: &Pubkey, amount: u64) {
+    let rent = Rent::default().minimum_balance(TokenAccount::LEN);
+    let is_native = *mint == spl_token::native_mint::id();
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount {
+            mint: *mint,
+            owner: find_program_authority(&id()).0,
+            amount,
+            state: AccountState::Initialized,
+            is_native: if is_native { COption::Some(rent) } else { COption::None },
+            ..TokenAccount::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    program_test.add_account(
+        *key,
+        Account {
+            lamports: if is_native { rent + amount } else { rent },
+            data,
+            owner: spl_token::id(),
+            ..Account::default()
+        },
+    );
+}
+
+/// Seeds a rent exempt escrow account holding `escrow`
+pub fn add_escrow(program_test: &mut ProgramTest, key: &Pubkey, escrow: Escrow) {
+    add_escrow_owned_by(program_test, key, escrow, &id());
+}
+
+/// Like `add_escrow` for an account owned by `owner`
+pub fn add_escrow_owned_by(
+    program_test: &mut ProgramTest,
+    key: &Pubkey,
+    escrow: Escrow,
+    owner: &Pubkey,
+) {
+    program_test.add_account(
+        *key,
+        Account {
+            lamports: Rent::default().minimum_balance(Escrow::LEN),
+            data: escrow.into_account_data(),
+            owner: *owner,
+            ..Account::default()
+        },
+    );
+}
+
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let blockhash = context.banks_client.get_latest_blockhash().await?;
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await
+}
+
+/// Runs a transaction expected to fail and returns its program logs
+pub async fn process_failing(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Vec<String> {
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    match context.banks_client.process_transaction_with_preflight(tx).await {
+        Err(BanksClientError::SimulationError { logs, .. }) => logs,
+        other => panic!("expected a failed simulation, got {:?}", other),
+    }
+}
+
+pub async fn get_balance(context: &mut ProgramTestContext, key: &Pubkey) -> u64 {
+    context.banks_client.get_balance(*key).await.unwrap()
+}
+
+pub async fn get_escrow(context: &mut ProgramTestContext, key: &Pubkey) -> Escrow {
+    let account = context.banks_client.get_account(*key).await.unwrap().unwrap();
+    Escrow::unpack(&account.data).unwrap()
+}
+
+/// Reads the lifecycle state of an escrow, a deleted account reads as closed
+pub async fn get_escrow_state(context: &mut ProgramTestContext, key: &Pubkey) -> EscrowState {
+    let account = context.banks_client.get_account(*key).await.unwrap();
+    let data = account.map(|account| account.data).unwrap_or_default();
+    Escrow::state_of(&data).unwrap()
+}
+
+/// Funds `wallet` with `lamports` from the context payer
+pub async fn fund(context: &mut ProgramTestContext, wallet: &Pubkey, lamports: u64) {
+    let ix = system_instruction::transfer(&context.payer.pubkey(), wallet, lamports);
+    process(context, &[ix], &[]).await.unwrap();
+}
+
+/// Creates a wrapped SOL vault owned by `owner` holding `amount` lamports
+pub async fn create_native_vault(
+    context: &mut ProgramTestContext,
+    vault: &Keypair,
+    owner: &Pubkey,
+    amount: u64,
+) {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &context.payer.pubkey(),
+            &vault.pubkey(),
+            rent.minimum_balance(TokenAccount::LEN) + amount,
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_account(
+            &spl_token::id(),
+            &vault.pubkey(),
+            &spl_token::native_mint::id(),
+            owner,
+        )
+        .unwrap(),
+    ];
+    process(context, &instructions, &[vault]).await.unwrap();
+}
+
+/// Creates an uninitialized, rent exempt escrow account
+pub async fn create_escrow_account(context: &mut ProgramTestContext, escrow: &Keypair) {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let ix = system_instruction::create_account(
+        &context.payer.pubkey(),
+        &escrow.pubkey(),
+        rent.minimum_balance(Escrow::LEN),
+        Escrow::LEN as u64,
+        &id(),
+    );
+    process(context, &[ix], &[escrow]).await.unwrap();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn init_escrow_ix(
+    payer: &Pubkey,
+    vault_token: &Pubkey,
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    payer_token: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+    amount: u64,
+    fee: u64,
+) -> Instruction {
+    instruction::init_escrow(
+        &id(),
+        payer,
+        vault_token,
+        authority,
+        escrow,
+        payer_token,
+        payee_token,
+        fee_token,
+        amount,
+        fee,
+    )
+}
+
+/// Appends the optional settings to an `InitEscrow` built by `init_escrow_ix`
+pub fn with_options(mut ix: Instruction, options: &InitOptions) -> Instruction {
+    ix.data.extend_from_slice(&options.pack());
+    ix
+}
+
+pub fn settle_ix(
+    authority: &Pubkey,
+    payee_token: &Pubkey,
+    fee_token: &Pubkey,
+    vault_token: &Pubkey,
+    escrow: &Pubkey,
+    fee_payer: &Pubkey,
+) -> Instruction {
+    instruction::settle(
+        &id(),
+        authority,
+        payee_token,
+        fee_token,
+        vault_token,
+        escrow,
+        fee_payer,
+    )
+}
+
+/// Settles every `[payee, fee, vault, escrow, fee_payer]` group under `authority`
+pub fn settle_many_ix(authority: &Pubkey, groups: &[[Pubkey; 5]]) -> Instruction {
+    let mut accounts = account_metas(
+        SETTLE_MANY_ACCOUNTS,
+        &[*authority, spl_token::id(), find_program_authority(&id()).0],
+    );
+    for group in groups {
+        accounts.extend(account_metas(SETTLE_MANY_GROUP, group));
+    }
+    Instruction {
+        program_id: id(),
+        accounts,
+        data: vec![TAG_SETTLE_MANY],
+    }
+}
+
+/// Closes every escrow of `escrows` under `authority`, sweeping their rent to `treasury`
+pub fn close_many_to_treasury_ix(
+    authority: &Pubkey,
+    treasury: &Pubkey,
+    escrows: &[Pubkey],
+) -> Instruction {
+    let mut accounts = account_metas(CLOSE_MANY_TO_TREASURY_ACCOUNTS, &[*authority, *treasury]);
+    accounts.extend(escrows.iter().map(|escrow| AccountMeta::new(*escrow, false)));
+    Instruction {
+        program_id: id(),
+        accounts,
+        data: vec![TAG_CLOSE_MANY_TO_TREASURY],
+    }
+}
+
+/// The keypair of `admin::id()` in `test-admin` builds
+pub fn admin_keypair() -> Keypair {
+    Keypair::from_bytes(&[
+        20, 176, 21, 202, 245, 78, 207, 107, 121, 224, 23, 225, 80, 198, 233, 0, 207, 208, 48,
+        180, 212, 24, 42, 160, 239, 164, 63, 63, 185, 230, 228, 38, 161, 143, 81, 30, 253, 77,
+        216, 6, 117, 101, 58, 166, 155, 141, 77, 156, 85, 246, 193, 180, 68, 48, 143, 232, 60,
+        61, 183, 101, 56, 198, 121, 128,
+    ])
+    .unwrap()
+}
+
+/// Reclaims `reclaimed` into `destination` on behalf of `admin`
+pub fn force_reclaim_ix(admin: &Pubkey, reclaimed: &Pubkey, destination: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: id(),
+        accounts: account_metas(FORCE_RECLAIM_ACCOUNTS, &[*admin, *reclaimed, *destination]),
+        data: vec![TAG_FORCE_RECLAIM],
+    }
+}
+
+/// Points the fee of `escrow` at `fee_token` on behalf of `authority`
+pub fn update_fee_token_ix(authority: &Pubkey, escrow: &Pubkey, fee_token: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: id(),
+        accounts: account_metas(UPDATE_FEE_TOKEN_ACCOUNTS, &[*authority, *escrow, *fee_token]),
+        data: vec![TAG_UPDATE_FEE_TOKEN],
+    }
+}
+
+/// Appends a memo to a `Settle` built by `settle_ix`
+pub fn with_memo(mut ix: Instruction, memo: &[u8]) -> Instruction {
+    ix.data.extend_from_slice(memo);
+    ix
+}
+
+pub fn close_ix(authority: &Pubkey, escrow: &Pubkey, fee_payer: &Pubkey) -> Instruction {
+    instruction::close(&id(), authority, escrow, fee_payer)
+}
+
+pub fn cancel_ix(
+    authority: &Pubkey,
+    escrow: &Pubkey,
+    payer_token: &Pubkey,
+    fee_payer: &Pubkey,
+    vault_token: &Pubkey,
+) -> Instruction {
+    instruction::cancel(&id(), authority, escrow, payer_token, fee_payer, vault_token)
+}
+
+/// Asserts the transaction failed in its first instruction with a custom program error
+pub fn assert_custom_error(result: Result<(), TransportError>, error: EscrowError) {
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(code),
+        ))) => assert_eq!(code, error as u32),
+        other => panic!("expected {:?}, got {:?}", error, other),
+    }
+}
+
+/// Keys of an escrow set up by `init_native_escrow`
+pub struct NativeEscrow {
+    pub payer: Keypair,
+    pub authority: Keypair,
+    pub vault: Keypair,
+    pub escrow: Keypair,
+    pub payee: Keypair,
+    pub fee_taker: Keypair,
+}
+
+/// Creates and initializes a wrapped SOL escrow of `amount` lamports and `fee`
+pub async fn init_native_escrow(
+    context: &mut ProgramTestContext,
+    amount: u64,
+    fee: u64,
+) -> NativeEscrow {
+    init_native_escrow_with_options(context, amount, fee, &InitOptions::default()).await
+}
+
+/// Like `init_native_escrow` with the given optional settings
+pub async fn init_native_escrow_with_options(
+    context: &mut ProgramTestContext,
+    amount: u64,
+    fee: u64,
+    options: &InitOptions,
+) -> NativeEscrow {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let keys = NativeEscrow {
+        payer: Keypair::new(),
+        authority: Keypair::new(),
+        vault: Keypair::new(),
+        escrow: Keypair::new(),
+        payee: Keypair::new(),
+        fee_taker: Keypair::new(),
+    };
+    fund(context, &keys.payer.pubkey(), rent.minimum_balance(0)).await;
+    create_native_vault(context, &keys.vault, &keys.payer.pubkey(), amount).await;
+    create_escrow_account(context, &keys.escrow).await;
+    process(
+        context,
+        &[with_options(
+            init_escrow_ix(
+                &keys.payer.pubkey(),
+                &keys.vault.pubkey(),
+                &keys.authority.pubkey(),
+                &keys.escrow.pubkey(),
+                &keys.payer.pubkey(),
+                &keys.payee.pubkey(),
+                &keys.fee_taker.pubkey(),
+                amount,
+                fee,
+            ),
+            options,
+        )],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    keys
+}
+
+pub async fn get_token_account(context: &mut ProgramTestContext, key: &Pubkey) -> TokenAccount {
+    let account = context.banks_client.get_account(*key).await.unwrap().unwrap();
+    TokenAccount::unpack(&account.data).unwrap()
+}
+
+pub async fn get_token_balance(context: &mut ProgramTestContext, key: &Pubkey) -> u64 {
+    get_token_account(context, key).await.amount
+}
+
+/// Freezes a token account in place, its mint needs no freeze authority
+pub async fn freeze_token_account(context: &mut ProgramTestContext, key: &Pubkey) {
+    let mut account = context.banks_client.get_account(*key).await.unwrap().unwrap();
+    let mut token = TokenAccount::unpack(&account.data).unwrap();
+    token.state = AccountState::Frozen;
+    TokenAccount::pack(token, &mut account.data).unwrap();
+    context.set_account(key, &account.into());
+}
+
+/// Asserts the token balance of every `(account, amount)` pair
+pub async fn assert_token_balances(context: &mut ProgramTestContext, expected: &[(Pubkey, u64)]) {
+    for (key, amount) in expected {
+        assert_eq!(get_token_balance(context, key).await, *amount, "balance of {}", key);
+    }
+}
+
+pub async fn create_mint(context: &mut ProgramTestContext, mint: &Keypair, authority: &Pubkey) {
+    create_mint_of(context, &spl_token::id(), mint, authority).await
+}
+
+/// Creates a mint without extensions under `token_program`
+pub async fn create_mint_of(
+    context: &mut ProgramTestContext,
+    token_program: &Pubkey,
+    mint: &Keypair,
+    authority: &Pubkey,
+) {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &context.payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(Mint::LEN),
+            Mint::LEN as u64,
+            token_program,
+        ),
+        with_token_program(
+            spl_token::instruction::initialize_mint(
+                &spl_token::id(),
+                &mint.pubkey(),
+                authority,
+                None,
+                0,
+            )
+            .unwrap(),
+            token_program,
+        ),
+    ];
+    process(context, &instructions, &[mint]).await.unwrap();
+}
+
+pub async fn create_token_account(
+    context: &mut ProgramTestContext,
+    account: &Keypair,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) {
+    create_token_account_of(context, &spl_token::id(), account, mint, owner).await
+}
+
+/// Creates a token account without extensions under `token_program`
+pub async fn create_token_account_of(
+    context: &mut ProgramTestContext,
+    token_program: &Pubkey,
+    account: &Keypair,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) {
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &context.payer.pubkey(),
+            &account.pubkey(),
+            rent.minimum_balance(TokenAccount::LEN),
+            TokenAccount::LEN as u64,
+            token_program,
+        ),
+        with_token_program(
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+            token_program,
+        ),
+    ];
+    process(context, &instructions, &[account]).await.unwrap();
+}
+
+pub async fn mint_to(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    account: &Pubkey,
+    authority: &Keypair,
+    amount: u64,
+) {
+    mint_to_of(context, &spl_token::id(), mint, account, authority, amount).await
+}
+
+pub async fn mint_to_of(
+    context: &mut ProgramTestContext,
+    token_program: &Pubkey,
+    mint: &Pubkey,
+    account: &Pubkey,
+    authority: &Keypair,
+    amount: u64,
+) {
+    let ix = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        mint,
+        account,
+        &authority.pubkey(),
+        &[],
+        amount,
+    )
+    .unwrap();
+    process(context, &[with_token_program(ix, token_program)], &[authority])
+        .await
+        .unwrap();
+}
+
+/// Keys of an escrow set up by `init_spl_escrow`
+pub struct SplEscrow {
+    pub payer: Keypair,
+    pub authority: Keypair,
+    pub mint: Keypair,
+    pub mint_authority: Keypair,
+    pub vault: Keypair,
+    pub escrow: Keypair,
+    pub payer_token: Keypair,
+    pub payee_token: Keypair,
+    pub fee_token: Keypair,
+    pub token_program: Pubkey,
+}
+
+impl Default for SplEscrow {
+    fn default() -> Self {
+        SplEscrow {
+            payer: Keypair::new(),
+            authority: Keypair::new(),
+            mint: Keypair::new(),
+            mint_authority: Keypair::new(),
+            vault: Keypair::new(),
+            escrow: Keypair::new(),
+            payer_token: Keypair::new(),
+            payee_token: Keypair::new(),
+            fee_token: Keypair::new(),
+            token_program: spl_token::id(),
+        }
+    }
+}
+
+/// Creates a fresh mint and initializes an escrow of `amount` tokens and `fee`
+pub async fn init_spl_escrow(context: &mut ProgramTestContext, amount: u64, fee: u64) -> SplEscrow {
+    init_spl_escrow_with_owners(
+        context,
+        SplEscrow::default(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        amount,
+        fee,
+    )
+    .await
+}
+
+/// Like `init_spl_escrow` with the payee and fee token accounts owned by the given wallets
+pub async fn init_spl_escrow_with_owners(
+    context: &mut ProgramTestContext,
+    keys: SplEscrow,
+    payee_owner: &Pubkey,
+    fee_owner: &Pubkey,
+    amount: u64,
+    fee: u64,
+) -> SplEscrow {
+    create_spl_escrow_accounts(context, &keys, payee_owner, fee_owner, amount).await;
+    process(
+        context,
+        &[spl_init_ix(&keys, amount, fee)],
+        &[&keys.payer, &keys.authority],
+    )
+    .await
+    .unwrap();
+    keys
+}
+
+/// Creates the mint, token accounts and escrow account `init_spl_escrow` initializes, with
+/// `amount` minted to the vault
+pub async fn create_spl_escrow_accounts(
+    context: &mut ProgramTestContext,
+    keys: &SplEscrow,
+    payee_owner: &Pubkey,
+    fee_owner: &Pubkey,
+    amount: u64,
+) {
+    let mint = keys.mint.pubkey();
+    let program = &keys.token_program;
+    create_mint_of(context, program, &keys.mint, &keys.mint_authority.pubkey()).await;
+    create_token_account_of(context, program, &keys.vault, &mint, &keys.payer.pubkey()).await;
+    let payer = keys.payer.pubkey();
+    create_token_account_of(context, program, &keys.payer_token, &mint, &payer).await;
+    create_token_account_of(context, program, &keys.payee_token, &mint, payee_owner).await;
+    create_token_account_of(context, program, &keys.fee_token, &mint, fee_owner).await;
+    let vault = keys.vault.pubkey();
+    mint_to_of(context, program, &mint, &vault, &keys.mint_authority, amount).await;
+    create_escrow_account(context, &keys.escrow).await;
+}
+
+/// `InitEscrow` over the accounts of `keys`, signed by its payer and authority
+pub fn spl_init_ix(keys: &SplEscrow, amount: u64, fee: u64) -> Instruction {
+    let ix = init_escrow_ix(
+        &keys.payer.pubkey(),
+        &keys.vault.pubkey(),
+        &keys.authority.pubkey(),
+        &keys.escrow.pubkey(),
+        &keys.payer_token.pubkey(),
+        &keys.payee_token.pubkey(),
+        &keys.fee_token.pubkey(),
+        amount,
+        fee,
+    );
+    with_token_program(ix, &keys.token_program)
+}